@@ -0,0 +1,311 @@
+//! Key/value annotations of snapshots
+//!
+//! Snapshots have no dedicated field for annotations, so they are saved as tags of the form
+//! `annotation:KEY=VALUE`. These tags are shown as annotations and not as tags.
+//!
+//! Tags are used, as the snapshot file format is shared with restic and other tools: unknown fields
+//! are dropped whenever a snapshot is rewritten, e.g. by `rewrite`, `copy` or older rustic
+//! versions, whereas tags are kept by all of them. The description is text given by the user and
+//! may already contain the backup log. Note that annotations are part of the tags when grouping
+//! snapshots by tags.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+use anyhow::{Result, anyhow, bail};
+use rustic_core::{
+    StringList,
+    repofile::{SnapshotFile, SnapshotModification},
+};
+
+/// Prefix of tags which save an annotation
+pub(crate) const ANNOTATION_PREFIX: &str = "annotation:";
+
+/// Annotations of a snapshot, sorted by key
+pub(crate) type Annotations = BTreeMap<String, String>;
+
+/// A key/value annotation given as `KEY=VALUE`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Annotation {
+    key: String,
+    value: String,
+}
+
+impl FromStr for Annotation {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        let (key, value) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("invalid annotation `{s}`, please use KEY=VALUE"))?;
+        let key = validate_key(key)?;
+        if value.contains([',', '\n']) {
+            bail!("invalid annotation `{s}`: the value must not contain commas or newlines");
+        }
+        Ok(Self {
+            key: key.to_string(),
+            value: value.to_string(),
+        })
+    }
+}
+
+impl Display for Annotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.key, self.value)
+    }
+}
+
+/// Check that the given annotation key is valid
+///
+/// # Errors
+///
+/// * If the key is empty or contains a `=`, comma or newline
+pub(crate) fn validate_key(key: &str) -> Result<&str> {
+    if key.is_empty() || key.contains(['=', ',', '\n']) {
+        bail!(
+            "invalid annotation key `{key}`: it must not be empty or contain `=`, commas or newlines"
+        );
+    }
+    Ok(key)
+}
+
+/// Parse and validate an annotation key given on the command line
+pub(crate) fn parse_key(key: &str) -> Result<String> {
+    Ok(validate_key(key)?.to_string())
+}
+
+/// The tag saving the annotation `key=value`
+fn tag(key: &str, value: &str) -> String {
+    format!("{ANNOTATION_PREFIX}{key}={value}")
+}
+
+/// Get key and value if the tag saves an annotation
+fn parse_tag(tag: &str) -> Option<(&str, &str)> {
+    tag.strip_prefix(ANNOTATION_PREFIX)?
+        .split_once('=')
+        .filter(|(key, _)| !key.is_empty())
+}
+
+/// The annotations saved in the given tags
+pub(crate) fn annotations(tags: &StringList) -> Annotations {
+    tags.iter()
+        .filter_map(|tag| parse_tag(tag))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+/// The given tags without the tags saving annotations
+pub(crate) fn plain_tags(tags: &StringList) -> StringList {
+    let mut plain = StringList::default();
+    for tag in tags.iter().filter(|tag| parse_tag(tag).is_none()) {
+        plain.add(tag.clone());
+    }
+    plain
+}
+
+/// The tags to save the given plain tags together with the annotations
+pub(crate) fn with_annotations(mut tags: StringList, annotations: &Annotations) -> StringList {
+    for (key, value) in annotations {
+        tags.add(tag(key, value));
+    }
+    tags
+}
+
+/// Set and remove annotations saved in the given tags; set annotations replace those with the same key
+pub(crate) fn annotate(tags: &StringList, set: &[Annotation], remove: &[String]) -> StringList {
+    let mut annotations = annotations(tags);
+    for key in remove {
+        _ = annotations.remove(key);
+    }
+    for annotation in set {
+        _ = annotations.insert(annotation.key.clone(), annotation.value.clone());
+    }
+    with_annotations(plain_tags(tags), &annotations)
+}
+
+/// A [`SnapshotModification`] which additionally sets and removes annotations
+#[derive(clap::Parser, Clone, Debug, Default)]
+pub(crate) struct AnnotatedModification {
+    #[clap(flatten)]
+    pub(crate) modification: SnapshotModification,
+
+    /// Set the annotation KEY to VALUE (can be specified multiple times)
+    #[clap(long, value_name = "KEY=VALUE")]
+    pub(crate) annotate: Vec<Annotation>,
+
+    /// Remove the annotation with the given KEY (can be specified multiple times)
+    #[clap(long, value_name = "KEY", value_parser = parse_key)]
+    pub(crate) remove_annotation: Vec<String>,
+}
+
+impl AnnotatedModification {
+    /// The [`SnapshotModification`] doing this modification on the given snapshots
+    ///
+    /// Setting tags keeps the annotations, so it is done by adding the given tags and removing all
+    /// other plain tags. Annotations are set by adding their tag and removing the tags saving other
+    /// values. Removing tags which a snapshot doesn't have doesn't change it, so the result can be
+    /// applied to each of the given snapshots.
+    pub(crate) fn resolve(&self, snapshots: &[SnapshotFile]) -> SnapshotModification {
+        let mut modification = self.modification.clone();
+        let tags: BTreeSet<_> = snapshots.iter().flat_map(|sn| sn.tags.iter()).collect();
+        let mut remove = StringList::default();
+        if !modification.set_tags.is_empty() {
+            let mut set = StringList::default();
+            set.add_all(std::mem::take(&mut modification.set_tags));
+            for tag in tags.iter().filter(|tag| parse_tag(tag).is_none()) {
+                if !set.contains(tag) {
+                    remove.add((*tag).clone());
+                }
+            }
+            modification.add_tags.push(set);
+        }
+
+        // later annotations win, as in `annotate`
+        let set: BTreeMap<_, _> = self
+            .annotate
+            .iter()
+            .map(|annotation| (annotation.key.as_str(), annotation.value.as_str()))
+            .collect();
+        let mut add = StringList::default();
+        for (key, value) in &set {
+            add.add(tag(key, value));
+        }
+        for tag in tags {
+            let Some((key, _)) = parse_tag(tag) else {
+                continue;
+            };
+            if (set.contains_key(key) || self.remove_annotation.iter().any(|k| k == key))
+                && !add.contains(tag)
+            {
+                remove.add(tag.clone());
+            }
+        }
+        modification.add_tags.push(add);
+        modification.remove_tags.push(remove);
+        modification
+    }
+}
+
+/// Format annotations using newlines
+pub(crate) fn formatln(annotations: &Annotations) -> String {
+    annotations
+        .iter()
+        .map(|(key, value)| format!("{key}={value}"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Check if the given annotations match the filter
+///
+/// For each key given in the filter, the annotation must match one of the values given for this key.
+pub(crate) fn matches_filter(annotations: &Annotations, filter: &[Annotation]) -> bool {
+    filter.iter().all(|f| {
+        annotations.get(&f.key).is_some_and(|value| {
+            filter
+                .iter()
+                .any(|other| other.key == f.key && &other.value == value)
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn tags(s: &str) -> StringList {
+        s.parse().unwrap()
+    }
+
+    fn filter(annotations: &[&str]) -> Vec<Annotation> {
+        annotations.iter().map(|a| a.parse().unwrap()).collect()
+    }
+
+    #[rstest]
+    #[case("ticket=123", true)]
+    #[case("commit=", true)]
+    #[case("url=a=b", true)]
+    #[case("ticket", false)]
+    #[case("=123", false)]
+    #[case("ticket=1,2", false)]
+    fn parse_annotation(#[case] s: &str, #[case] ok: bool) {
+        assert_eq!(s.parse::<Annotation>().is_ok(), ok);
+    }
+
+    #[test]
+    fn split_tags() {
+        let tags = tags("a,annotation:ticket=123,annotation:url=a=b,annotation:x,annotation:=y");
+        let annotations = annotations(&tags);
+        assert_eq!(formatln(&annotations), "ticket=123\nurl=a=b");
+        let plain = plain_tags(&tags);
+        assert_eq!(plain.to_string(), "a,annotation:=y,annotation:x");
+        assert_eq!(with_annotations(plain, &annotations), tags);
+    }
+
+    #[test]
+    fn annotate_tags() {
+        let tags = tags("a,annotation:ticket=123,annotation:env=prod");
+        let tags = annotate(
+            &tags,
+            &filter(&["ticket=456", "commit=abc"]),
+            &["env".to_string()],
+        );
+        assert_eq!(
+            tags.to_string(),
+            "a,annotation:commit=abc,annotation:ticket=456"
+        );
+    }
+
+    #[rstest]
+    #[case(&[], &[], &[], "a,annotation:env=prod,annotation:ticket=123", "annotation:env=prod,b")]
+    #[case(&["c"], &[], &[], "annotation:env=prod,annotation:ticket=123,c", "annotation:env=prod,c")]
+    #[case(&[], &["ticket=456", "env=test", "env=prod"], &[], "a,annotation:env=prod,annotation:ticket=456", "annotation:env=prod,annotation:ticket=456,b")]
+    #[case(&[], &[], &["env"], "a,annotation:ticket=123", "b")]
+    #[case(&["a"], &["commit=abc"], &["ticket"], "a,annotation:commit=abc,annotation:env=prod", "a,annotation:commit=abc,annotation:env=prod")]
+    fn resolve_modification(
+        #[case] set: &[&str],
+        #[case] annotate: &[&str],
+        #[case] remove: &[&str],
+        #[case] expected1: &str,
+        #[case] expected2: &str,
+    ) -> Result<()> {
+        let modification = AnnotatedModification {
+            modification: SnapshotModification::default()
+                .set_tags(set.iter().map(|tag| tags(tag)).collect::<Vec<_>>()),
+            annotate: filter(annotate),
+            remove_annotation: remove.iter().map(ToString::to_string).collect(),
+        };
+        let mut snapshots = [
+            SnapshotFile {
+                tags: tags("a,annotation:ticket=123,annotation:env=prod"),
+                ..Default::default()
+            },
+            SnapshotFile {
+                tags: tags("b,annotation:env=prod"),
+                ..Default::default()
+            },
+        ];
+        let resolved = modification.resolve(&snapshots);
+        for (sn, expected) in snapshots.iter_mut().zip([expected1, expected2]) {
+            let original = sn.tags.clone();
+            assert_eq!(sn.modify(&resolved)?, original != tags(expected));
+            assert_eq!(sn.tags, tags(expected));
+        }
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(&[], true)]
+    #[case(&["env=prod"], true)]
+    #[case(&["env=prod", "ticket=123"], true)]
+    #[case(&["env=prod", "env=test"], true)]
+    #[case(&["env=test"], false)]
+    #[case(&["env=prod", "ticket=456"], false)]
+    #[case(&["commit=abc"], false)]
+    fn match_filter(#[case] given: &[&str], #[case] expected: bool) {
+        let annotations = annotations(&tags("annotation:ticket=123,annotation:env=prod"));
+        assert_eq!(matches_filter(&annotations, &filter(given)), expected);
+    }
+}
@@ -11,7 +11,11 @@ use abscissa_core::{
 use anyhow::Result;
 
 // use crate::helpers::*;
-use crate::{commands::EntryPoint, config::RusticConfig};
+use crate::{
+    commands::EntryPoint,
+    config::{RusticConfig, progress_options},
+    telemetry,
+};
 
 /// Application state
 pub static RUSTIC_APP: AppCell<RusticApp> = AppCell::new();
@@ -132,6 +136,8 @@ impl Application for RusticApp {
             _ => _ = hooks.run_after(),
         };
         _ = hooks.run_finally();
+        telemetry::shutdown(exit_code == 0);
+        progress_options::status::shutdown();
         let result = self.state().components().shutdown(self, shutdown);
         if let Err(e) = result {
             fatal_error(self, &e)
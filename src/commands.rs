@@ -16,6 +16,9 @@ pub(crate) mod key;
 pub(crate) mod list;
 pub(crate) mod ls;
 pub(crate) mod merge;
+#[cfg(feature = "prometheus")]
+pub(crate) mod metrics;
+pub(crate) mod migrate;
 #[cfg(feature = "mount")]
 pub(crate) mod mount;
 pub(crate) mod prune;
@@ -26,6 +29,7 @@ pub(crate) mod rewrite;
 pub(crate) mod self_update;
 pub(crate) mod show_config;
 pub(crate) mod snapshots;
+pub(crate) mod status;
 pub(crate) mod tag;
 #[cfg(feature = "tui")]
 pub(crate) mod tui;
@@ -37,6 +41,8 @@ use std::fmt::Debug;
 use std::path::PathBuf;
 use std::sync::mpsc::channel;
 
+#[cfg(feature = "prometheus")]
+use crate::commands::metrics::MetricsCmd;
 #[cfg(feature = "mount")]
 use crate::commands::mount::MountCmd;
 #[cfg(feature = "webdav")]
@@ -47,11 +53,16 @@ use crate::{
         backup::BackupCmd, cat::CatCmd, check::CheckCmd, completions::CompletionsCmd,
         config::ConfigCmd, copy::CopyCmd, diff::DiffCmd, docs::DocsCmd, dump::DumpCmd,
         forget::ForgetCmd, init::InitCmd, key::KeyCmd, list::ListCmd, ls::LsCmd, merge::MergeCmd,
-        prune::PruneCmd, repair::RepairCmd, repoinfo::RepoInfoCmd, restore::RestoreCmd,
-        rewrite::RewriteCmd, self_update::SelfUpdateCmd, show_config::ShowConfigCmd,
-        snapshots::SnapshotCmd, tag::TagCmd,
+        migrate::MigrateCmd, prune::PruneCmd, repair::RepairCmd, repoinfo::RepoInfoCmd,
+        restore::RestoreCmd, rewrite::RewriteCmd, self_update::SelfUpdateCmd,
+        show_config::ShowConfigCmd, snapshots::SnapshotCmd, status::StatusCmd, tag::TagCmd,
     },
-    config::RusticConfig,
+    config::{
+        RusticConfig,
+        logging::{redact_repository, set_log_context},
+        progress_options,
+    },
+    telemetry,
 };
 
 use abscissa_core::{
@@ -125,13 +136,20 @@ enum RusticCmd {
     /// Merge snapshots
     Merge(Box<MergeCmd>),
 
+    /// Export metrics about the repository
+    #[cfg(feature = "prometheus")]
+    Metrics(Box<MetricsCmd>),
+
+    /// Migrate snapshots to a new repository, e.g. using another backend or other config options
+    Migrate(Box<MigrateCmd>),
+
     /// Show a detailed overview of the snapshots within the repository
     Snapshots(Box<SnapshotCmd>),
 
     /// Show the configuration which has been read from the config file(s)
     ShowConfig(Box<ShowConfigCmd>),
 
-    /// Update to the latest stable rustic release
+    /// Update to the latest rustic release or install a downloaded release
     #[cfg_attr(not(feature = "self-update"), clap(hide = true))]
     SelfUpdate(Box<SelfUpdateCmd>),
 
@@ -150,6 +168,9 @@ enum RusticCmd {
     /// Show general information about the repository
     Repoinfo(Box<RepoInfoCmd>),
 
+    /// Check the freshness and health of the repository, e.g. for monitoring systems
+    Status(Box<StatusCmd>),
+
     /// Change tags of snapshots
     Tag(Box<TagCmd>),
 
@@ -169,7 +190,7 @@ fn styles() -> Styles {
         .placeholder(AnsiColor::Green.on_default())
 }
 
-fn version() -> &'static str {
+pub(crate) fn version() -> &'static str {
     option_env!("PROJECT_VERSION").unwrap_or(env!("CARGO_PKG_VERSION"))
 }
 
@@ -177,6 +198,48 @@ pub(crate) fn program_version() -> String {
     format!("rustic {}", version())
 }
 
+impl RusticCmd {
+    /// The name of the subcommand as given on the command line
+    const fn name(&self) -> &'static str {
+        match self {
+            Self::Backup(_) => "backup",
+            Self::Cat(_) => "cat",
+            Self::Config(_) => "config",
+            Self::Completions(_) => "completions",
+            Self::Check(_) => "check",
+            Self::Copy(_) => "copy",
+            Self::Diff(_) => "diff",
+            Self::Docs(_) => "docs",
+            Self::Dump(_) => "dump",
+            Self::Find(_) => "find",
+            Self::Forget(_) => "forget",
+            Self::Init(_) => "init",
+            Self::Key(_) => "key",
+            Self::List(_) => "list",
+            #[cfg(feature = "mount")]
+            Self::Mount(_) => "mount",
+            Self::Ls(_) => "ls",
+            Self::Merge(_) => "merge",
+            #[cfg(feature = "prometheus")]
+            Self::Metrics(_) => "metrics",
+            Self::Migrate(_) => "migrate",
+            Self::Snapshots(_) => "snapshots",
+            Self::ShowConfig(_) => "show-config",
+            Self::SelfUpdate(_) => "self-update",
+            Self::Prune(_) => "prune",
+            Self::Restore(_) => "restore",
+            Self::Rewrite(_) => "rewrite",
+            Self::Repair(_) => "repair",
+            Self::Repoinfo(_) => "repoinfo",
+            Self::Status(_) => "status",
+            Self::Tag(_) => "tag",
+            #[cfg(feature = "webdav")]
+            Self::Webdav(_) => "webdav",
+            Self::Version(_) => "version",
+        }
+    }
+}
+
 /// Entry point for the application. It needs to be a struct to allow using subcommands!
 #[derive(clap::Parser, Command, Debug)]
 #[command(author, about, name="rustic", styles=styles(), version=version())]
@@ -206,7 +269,8 @@ impl Runnable for EntryPoint {
             RUSTIC_APP.shutdown(Shutdown::Graceful)
         });
 
-        // Run the subcommand
+        // Run the subcommand within a span
+        let _span = telemetry::start_command(self.commands.name());
         self.commands.run();
         RUSTIC_APP.shutdown(Shutdown::Graceful)
     }
@@ -262,6 +326,21 @@ impl Configurable<RusticConfig> for EntryPoint {
                 if let Ok(url) = Url::parse(&value) {
                     _ = config.global.opentelemetry.insert(url);
                 }
+            } else if var == "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT" {
+                #[cfg(feature = "opentelemetry")]
+                if let Ok(url) = Url::parse(&value) {
+                    _ = config.global.opentelemetry_traces.insert(url);
+                }
+            } else if var == "OTEL_EXPORTER_OTLP_LOGS_ENDPOINT" {
+                #[cfg(feature = "opentelemetry")]
+                if let Ok(url) = Url::parse(&value) {
+                    _ = config.global.opentelemetry_logs.insert(url);
+                }
+            } else if var == "OTEL_EXPORTER_OTLP_ENDPOINT" {
+                #[cfg(feature = "opentelemetry")]
+                if let Ok(url) = Url::parse(&value) {
+                    _ = config.global.otel.insert(url);
+                }
             } else if var == "OTEL_SERVICE_NAME" && cfg!(feature = "opentelemetry") {
                 _ = config.backup.metrics_job.insert(value);
             }
@@ -282,12 +361,42 @@ impl Configurable<RusticConfig> for EntryPoint {
         // start logger also check if version command was supplied by the user
         // if so skip logging for version
         if !matches!(self.commands, RusticCmd::Version(_)) {
+            // the log appender for OpenTelemetry needs to be available when starting the logger
+            telemetry::init(&config.global)
+                .map_err(|e| FrameworkErrorKind::ConfigError.context(e))?;
+
             config
                 .global
                 .logging_options
                 .start_logger(config.global.dry_run)
                 .map_err(|e| FrameworkErrorKind::ConfigError.context(e))?;
 
+            progress_options::status::serve(
+                self.commands.name(),
+                config.global.progress_socket.as_deref(),
+                config.global.progress_http,
+            )
+            .map_err(|e| FrameworkErrorKind::ConfigError.context(e))?;
+
+            set_log_context("command", Some(self.commands.name().to_string()));
+            set_log_context(
+                "profile",
+                Some(if config.global.use_profiles.is_empty() {
+                    "rustic".to_string()
+                } else {
+                    config.global.use_profiles.join(",")
+                }),
+            );
+            set_log_context(
+                "repository",
+                config
+                    .repository
+                    .be
+                    .repository
+                    .as_deref()
+                    .map(redact_repository),
+            );
+
             if config.global.logging_options.log_file.is_some() {
                 info!("rustic {}", version());
                 info!("command: {:?}", std::env::args_os().collect::<Vec<_>>());
@@ -302,6 +411,7 @@ impl Configurable<RusticConfig> for EntryPoint {
         match &self.commands {
             RusticCmd::Forget(cmd) => cmd.override_config(config),
             RusticCmd::Copy(cmd) => cmd.override_config(config),
+            RusticCmd::Status(cmd) => cmd.override_config(config),
             #[cfg(feature = "webdav")]
             RusticCmd::Webdav(cmd) => cmd.override_config(config),
             #[cfg(feature = "mount")]
@@ -316,10 +426,34 @@ impl Configurable<RusticConfig> for EntryPoint {
 #[cfg(test)]
 mod tests {
     use crate::commands::EntryPoint;
-    use clap::CommandFactory;
+    use clap::{CommandFactory, Parser};
 
     #[test]
     fn verify_cli() {
         EntryPoint::command().debug_assert();
     }
+
+    #[test]
+    fn command_names_match_cli() {
+        let mut checked = 0;
+        for sub in EntryPoint::command().get_subcommands() {
+            let name = sub.get_name();
+            let mut args = vec!["rustic", name];
+            // some commands need further arguments to be parsed
+            match name {
+                "completions" => args.push("bash"),
+                "key" => args.extend(["add", "--new-password", "x"]),
+                "metrics" => args.push("serve"),
+                "config" | "migrate" | "diff" | "find" | "merge" | "restore" | "dump" | "ls"
+                | "cat" | "list" | "mount" | "webdav" => continue,
+                _ => {}
+            }
+            let Ok(entry) = EntryPoint::try_parse_from(&args) else {
+                continue;
+            };
+            assert_eq!(entry.commands.name(), name);
+            checked += 1;
+        }
+        assert!(checked > 10);
+    }
 }
@@ -1,5 +1,11 @@
 //! `backup` subcommand
 
+mod explain;
+mod files_from;
+mod run_log;
+mod stdin_streams;
+mod tag_rules;
+
 use std::collections::HashMap;
 use std::fmt::Display;
 use std::path::PathBuf;
@@ -10,10 +16,18 @@ use crate::commands::program_version;
 use crate::repository::IndexedIdsRepo;
 use crate::{
     Application, RUSTIC_APP,
+    annotations::{Annotation, annotate},
     commands::{init::init, snapshots::fill_table},
-    config::{hooks::Hooks, parse_labels},
+    config::{
+        hooks::Hooks,
+        logging::{capture_log, set_log_context, with_log_context},
+    },
     helpers::{bold_cell, bytes_size_to_string, table},
-    repository::Repo,
+    metrics::CommandMetrics,
+    repository::{
+        Repo,
+        throttle::{Direction, ThrottledSource},
+    },
     status_err,
 };
 
@@ -22,16 +36,22 @@ use anyhow::{Context, Result, anyhow, bail};
 use clap::ValueHint;
 use comfy_table::Cell;
 use conflate::{Merge, MergeFrom};
+use files_from::{ListFormat, read_lists};
+use jiff::SignedDuration;
 use log::{debug, error, info, warn};
+use run_log::attach_log;
+pub(crate) use run_log::{keep_log, modifies_description, plain_description, read_log, with_log};
 use rustic_backend::OpenDALBackend;
 use rustic_core::{ChildStdoutSource, Excludes, LocalSource, ReadSource, StdinSource, StringList};
 use serde::{Deserialize, Serialize};
-use serde_with::serde_as;
+use serde_with::{DisplayFromStr, serde_as};
+use stdin_streams::{DEFAULT_CONCURRENCY, StdinStream, StdinStreamsSource};
+use tag_rules::{TagRule, apply_tag_rules};
 
 use rustic_core::{
     BackupOptions, CommandInput, ConfigOptions, KeyOptions, LocalSourceFilterOptions,
     LocalSourceSaveOptions, ParentOptions, PathList, SnapshotOptions,
-    repofile::{SnapshotFile, SnapshotId},
+    repofile::{SnapshotFile, SnapshotId, SnapshotSummary},
 };
 
 /// `backup` subcommand
@@ -64,6 +84,12 @@ pub struct BackupCmd {
     #[serde(skip)]
     ls: bool,
 
+    /// Don't run the backup, but list all excluded paths together with the rule which excluded them
+    #[clap(long, conflicts_with = "ls")]
+    #[merge(skip)]
+    #[serde(skip)]
+    explain_excludes: bool,
+
     #[clap(skip)]
     #[merge(skip)]
     name: Option<String>,
@@ -78,6 +104,50 @@ pub struct BackupCmd {
     #[merge(strategy=conflate::option::overwrite_none)]
     stdin_command: Option<CommandInput>,
 
+    /// Start the given command and save its output as FILENAME (can be specified multiple times).
+    /// All outputs are saved within one snapshot.
+    #[clap(
+        long = "stdin-stream",
+        value_name = "FILENAME=COMMAND",
+        conflicts_with = "stdin_command"
+    )]
+    #[merge(strategy=conflate::vec::overwrite_empty)]
+    stdin_streams: Vec<StdinStream>,
+
+    /// Maximum number of stdin stream commands running in parallel [default: 4]
+    #[clap(long, value_name = "N")]
+    #[merge(strategy=conflate::option::overwrite_none)]
+    stdin_concurrency: Option<usize>,
+
+    /// Kill stdin stream commands running longer than the given duration
+    #[clap(long, value_name = "DURATION")]
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[merge(strategy=conflate::option::overwrite_none)]
+    stdin_timeout: Option<SignedDuration>,
+
+    /// Read paths to back up from FILE (can be specified multiple times), use - for stdin.
+    /// One path per line; surrounding whitespace, empty lines and lines starting with # are ignored.
+    #[clap(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    #[merge(strategy=conflate::vec::overwrite_empty)]
+    files_from: Vec<PathBuf>,
+
+    /// Read paths to back up from FILE (can be specified multiple times), use - for stdin.
+    /// One path per line which is taken as-is; only empty lines are ignored.
+    #[clap(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    #[merge(strategy=conflate::vec::overwrite_empty)]
+    files_from_verbatim: Vec<PathBuf>,
+
+    /// Read NUL-separated paths to back up from FILE (can be specified multiple times), use - for stdin.
+    /// Such lists can be generated e.g. by `find -print0`.
+    #[clap(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    #[merge(strategy=conflate::vec::overwrite_empty)]
+    files_from_raw: Vec<PathBuf>,
+
+    /// Fail if a path read by --files-from* doesn't exist instead of skipping it with a warning
+    #[clap(long)]
+    #[merge(strategy=conflate::bool::overwrite_false)]
+    fail_on_missing: bool,
+
     /// Manually set backup path in snapshot
     #[clap(long, value_name = "PATH", value_hint = ValueHint::DirPath)]
     #[merge(strategy=conflate::option::overwrite_none)]
@@ -103,6 +173,22 @@ pub struct BackupCmd {
     #[merge(strategy=conflate::bool::overwrite_false)]
     init: bool,
 
+    /// Save warnings and errors logged during the backup within the snapshot description.
+    /// Show it using `rustic snapshots --log`.
+    #[clap(long)]
+    #[merge(strategy=conflate::bool::overwrite_false)]
+    capture_log: bool,
+
+    /// Annotate the snapshot with a KEY=VALUE pair (can be specified multiple times)
+    #[clap(
+        long = "annotate",
+        value_name = "KEY=VALUE",
+        help_heading = "Snapshot options"
+    )]
+    #[serde_as(as = "Vec<DisplayFromStr>")]
+    #[merge(strategy=conflate::vec::overwrite_empty)]
+    annotations: Vec<Annotation>,
+
     /// Node save options
     #[clap(flatten, next_help_heading = "Node modification options")]
     #[serde(flatten)]
@@ -144,6 +230,11 @@ pub struct BackupCmd {
     #[clap(skip)]
     hooks: Hooks,
 
+    /// Rules to add tags to the snapshot, evaluated once the backup is done; used within config file
+    #[clap(skip)]
+    #[merge(strategy = conflate::vec::append)]
+    tag_rules: Vec<TagRule>,
+
     /// Backup snapshots to generate
     #[clap(skip)]
     #[merge(strategy = merge_snapshots)]
@@ -164,8 +255,8 @@ pub struct BackupCmd {
     #[merge(strategy=conflate::option::overwrite_none)]
     pub metrics_job: Option<String>,
 
-    /// Additional labels to set to generated metrics
-    #[clap(long, value_name = "NAME=VALUE", value_parser = parse_labels, default_value = "")]
+    /// Additional labels to set to generated metrics, used within config file; see also global --metrics-labels
+    #[clap(skip)]
     #[merge(strategy=conflate::btreemap::append_or_ignore)]
     metrics_labels: BTreeMap<String, String>,
 }
@@ -177,6 +268,14 @@ impl BackupCmd {
             return Err("key \"sources\" is not valid in the [backup] section!");
         }
 
+        // paths given by files-from lists define the sources of a snapshot
+        if !self.files_from.is_empty()
+            || !self.files_from_verbatim.is_empty()
+            || !self.files_from_raw.is_empty()
+        {
+            return Err("keys \"files-from*\" are not valid in the [backup] section!");
+        }
+
         // manually check for a "name" field, check is not done by serde, see above.
         if self.name.is_some() {
             return Err("key \"name\" is not valid in the [backup] section!");
@@ -203,6 +302,9 @@ pub(crate) fn merge_snapshots(left: &mut Vec<BackupCmd>, mut right: Vec<BackupCm
         opt1.name
             .cmp(&opt2.name)
             .then(opt1.sources.cmp(&opt2.sources))
+            .then(opt1.files_from.cmp(&opt2.files_from))
+            .then(opt1.files_from_verbatim.cmp(&opt2.files_from_verbatim))
+            .then(opt1.files_from_raw.cmp(&opt2.files_from_raw))
     };
 
     left.append(&mut right);
@@ -260,7 +362,15 @@ impl BackupCmd {
         hooks.use_with(|| -> Result<_> {
             let mut is_err = false;
             for (opts, sources) in snapshots {
-                if let Err(err) = opts.backup_snapshot(sources.clone(), &repo) {
+                let context = [
+                    ("snapshot_sources", Some(sources.to_string())),
+                    ("snapshot_name", opts.name.clone()),
+                    ("snapshot_label", opts.snap_opts.label.clone()),
+                    ("snapshot_id", None),
+                ];
+                let res =
+                    with_log_context(&context, || opts.backup_snapshot(sources.clone(), &repo));
+                if let Err(err) = res {
                     error!("error backing up {sources}: {err}");
                     is_err = true;
                 }
@@ -275,34 +385,36 @@ impl BackupCmd {
 
     fn get_snapshots_to_backup(&self) -> Result<Vec<(Self, PathList)>> {
         let config = RUSTIC_APP.config();
-        let mut config_snapshots = config
-            .backup
-            .snapshots
-            .iter()
-            .map(|opt| (opt.clone(), PathList::from_iter(&opt.sources)));
-
-        if !self.cli_sources.is_empty() {
-            let sources = PathList::from_iter(&self.cli_sources);
+        let mut config_snapshots = config.backup.snapshots.iter();
+
+        if !self.cli_sources.is_empty() || self.has_files_from() {
+            let sources = self.sources(&self.cli_sources)?;
             let mut opts = self.clone();
             // merge Options from config file, if given
-            if let Some((config_opts, _)) = config_snapshots.find(|(_, s)| s == &sources) {
+            if let Some(config_opts) =
+                config_snapshots.find(|opt| PathList::from_iter(&opt.sources) == sources)
+            {
                 info!("merging sources={sources} section from config file");
-                opts.merge(config_opts);
+                opts.merge(config_opts.clone());
             }
             return Ok(vec![(opts, sources)]);
         }
 
         let config_snapshots: Vec<_> = config_snapshots
             // filter out using cli_name, if given
-            .filter(|(opt, _)| {
+            .filter(|opt| {
                 self.cli_name.is_empty()
                     || opt
                         .name
                         .as_ref()
                         .is_some_and(|name| self.cli_name.contains(name))
             })
-            .map(|(opt, sources)| (self.clone().merge_from(opt), sources))
-            .collect();
+            .map(|opt| {
+                let opts = self.clone().merge_from(opt.clone());
+                let sources = opts.sources(&opt.sources)?;
+                Ok((opts, sources))
+            })
+            .collect::<Result<_>>()?;
 
         if config_snapshots.is_empty() {
             bail!("no backup source given.");
@@ -341,11 +453,36 @@ impl BackupCmd {
         hooks.with_env(&hooks_variables)
     }
 
+    fn has_files_from(&self) -> bool {
+        !(self.files_from.is_empty()
+            && self.files_from_verbatim.is_empty()
+            && self.files_from_raw.is_empty())
+    }
+
+    /// The given sources together with the paths read from the files-from lists
+    fn sources(&self, sources: &[String]) -> Result<PathList> {
+        let paths = read_lists(
+            [
+                (&self.files_from[..], ListFormat::Lines),
+                (&self.files_from_verbatim[..], ListFormat::Verbatim),
+                (&self.files_from_raw[..], ListFormat::Raw),
+            ],
+            self.fail_on_missing,
+        )?;
+        if sources.is_empty() && paths.is_empty() {
+            bail!("no backup source given: all paths given by files-from lists are missing.");
+        }
+        Ok(sources.iter().map(PathBuf::from).chain(paths).collect())
+    }
+
+    #[allow(clippy::too_many_arguments)]
     fn backup_source(
         source: &PathList,
         options: BTreeMap<String, String>,
         ls: bool,
+        explain_excludes: bool,
         backup_opts: BackupOptions,
+        stdin_streams: Option<StdinStreamsSource>,
         snap: &mut SnapshotFile,
         repo: &IndexedIdsRepo,
     ) -> Result<()> {
@@ -356,12 +493,44 @@ impl BackupCmd {
             .with_context(|| format!("error sanitizing source=s\"{:?}\"", source))?
             .merge();
 
+        let is_local = !(source == backup_stdin
+            || source.len() == 1 && source[0].to_string_lossy().starts_with("opendal:"));
+        if explain_excludes {
+            if !is_local {
+                warn!(
+                    "explaining excludes is only supported for local sources, skipping {source}."
+                );
+                return Ok(());
+            }
+            return explain::explain_excludes(
+                &backup_opts.excludes,
+                &backup_opts.ignore_filter_opts,
+                &source.paths(),
+            );
+        }
+
         if source.len() == 1
                 // TODO: This check should not be done on PathList, but in the sources list directly
                 && let Some(path) = source[0].to_string_lossy().strip_prefix("opendal:")
         {
             let source = OpenDALBackend::new(path, options)?.as_source(&backup_opts.excludes)?;
             Self::archive(repo, &backup_opts, ls, &source, snap, &[PathBuf::new()])?;
+        } else if source == backup_stdin
+            && let Some(src) = stdin_streams
+        {
+            let backup_paths = src.paths();
+            Self::archive(repo, &backup_opts, ls, &src, snap, &backup_paths)?;
+            if !ls && let Err(err) = src.finish() {
+                // don't keep a snapshot with an incomplete set of streams; the failed streams are the
+                // error to report, even if removing the snapshot fails
+                if !snap.id.is_null() {
+                    warn!("removing incomplete snapshot {}", snap.id);
+                    if let Err(remove_err) = repo.delete_snapshots(&[snap.id]) {
+                        warn!("error removing snapshot {}: {remove_err}", snap.id);
+                    }
+                }
+                return Err(err);
+            }
         } else if source == backup_stdin {
             let path = PathBuf::from(&backup_opts.stdin_filename);
             let backup_paths = vec![path.clone()];
@@ -381,6 +550,12 @@ impl BackupCmd {
                 &backup_opts.ignore_filter_opts,
                 &backup_path,
             )?;
+            let limiter = RUSTIC_APP
+                .config()
+                .global
+                .throttle_options
+                .limiter(Direction::Read);
+            let src = ThrottledSource::new(src, limiter);
             Self::archive(repo, &backup_opts, ls, &src, snap, &backup_path)?;
         };
         Ok(())
@@ -440,10 +615,43 @@ impl BackupCmd {
 
         let hooks = self.hooks(&hooks, "source-specific-backup", &source);
 
+        let mut metrics = CommandMetrics::new("backup").with_job_name(self.metrics_job.take());
+        for (name, value) in std::mem::take(&mut self.metrics_labels) {
+            metrics.add_label(&name, value);
+        }
+        let res = self.backup_with_hooks(&source, &hooks, repo, &mut metrics);
+        metrics.publish(res.is_ok());
+        res
+    }
+
+    fn backup_with_hooks(
+        self,
+        source: &PathList,
+        hooks: &Hooks,
+        repo: &IndexedIdsRepo,
+        metrics: &mut CommandMetrics,
+    ) -> Result<()> {
+        let config = RUSTIC_APP.config();
+
         // use global group-by if not set
         let mut parent_opts = self.parent_opts;
         parent_opts.group_by = parent_opts.group_by.or(config.global.group_by);
 
+        if !self.stdin_streams.is_empty() && *source != PathList::from_string("-")? {
+            warn!(
+                "stdin streams are only used when backing up source \"-\", ignoring them for {source}."
+            );
+        }
+        let stdin_streams = (!self.stdin_streams.is_empty())
+            .then(|| {
+                StdinStreamsSource::new(
+                    self.stdin_streams,
+                    self.stdin_concurrency.unwrap_or(DEFAULT_CONCURRENCY),
+                    self.stdin_timeout,
+                )
+            })
+            .transpose()?;
+
         let backup_opts = BackupOptions::default()
             .stdin_filename(self.stdin_filename.unwrap_or_else(|| "stdin".to_string()))
             .stdin_command(self.stdin_command)
@@ -456,12 +664,62 @@ impl BackupCmd {
             .dry_run(config.global.dry_run);
 
         let mut snap = self.snap_opts.to_snapshot()?;
+        snap.tags = annotate(&snap.tags, &self.annotations, &[]);
         snap.program_version = program_version();
-        hooks.use_with(|| {
-            Self::backup_source(&source, self.options, self.ls, backup_opts, &mut snap, repo)
-        })?;
+        // the labels must not depend on the result, so that failed runs are published in the same group
+        let paths = backup_opts.as_path.as_ref().map_or_else(
+            || {
+                source
+                    .clone()
+                    .sanitize()
+                    .map_or_else(|_| source.to_string(), |source| source.merge().to_string())
+            },
+            |path| path.display().to_string(),
+        );
+        metrics.add_label("paths", paths);
+        metrics.add_label("hostname", snap.hostname.clone());
+        metrics.add_label("snapshot_label", snap.label.clone());
+        metrics.add_label("tags", snap.tags.to_string());
+        let backup = || {
+            hooks.use_with(|| {
+                Self::backup_source(
+                    source,
+                    self.options,
+                    self.ls,
+                    self.explain_excludes,
+                    backup_opts,
+                    stdin_streams,
+                    &mut snap,
+                    repo,
+                )
+            })
+        };
+        let log = if self.capture_log {
+            let (res, log) = capture_log(backup);
+            res?;
+            log
+        } else {
+            backup()?;
+            Vec::new()
+        };
+        // the snapshot id is only set if the snapshot is saved; dry-run never sets it
+        if snap.summary.is_some() && (config.global.dry_run || !snap.id.is_null()) {
+            let retagged = apply_tag_rules(&self.tag_rules, &mut snap)?;
+            if !log.is_empty() {
+                attach_log(&mut snap, &log);
+            }
+            if !config.global.dry_run && (retagged || !log.is_empty()) {
+                replace_snapshot(repo, &mut snap)?;
+                if !log.is_empty() {
+                    info!("saved backup log within the snapshot");
+                }
+            }
+        }
+        if !snap.id.is_null() {
+            set_log_context("snapshot_id", Some(snap.id.to_string()));
+        }
 
-        if self.ls {
+        if self.ls || self.explain_excludes {
             // no output here
         } else if config.global.progress_options.json_progress {
             write_json_progress_summary(&snap)?;
@@ -500,18 +758,15 @@ impl BackupCmd {
                 summary.total_files_processed,
                 bytes_size_to_string(summary.total_bytes_processed)
             );
-            info!("snapshot {} successfully saved.", snap.id);
+            if snap.id.is_null() {
+                info!("snapshot successfully saved.");
+            } else {
+                info!("snapshot {} successfully saved.", snap.id);
+            }
         }
 
-        if config.global.is_metrics_configured() {
-            // Merge global metrics labels
-            conflate::btreemap::append_or_ignore(
-                &mut self.metrics_labels,
-                config.global.metrics_labels.clone(),
-            );
-            if let Err(err) = publish_metrics(&snap, self.metrics_job, self.metrics_labels) {
-                warn!("error pushing metrics: {err}");
-            }
+        if let Some(summary) = &snap.summary {
+            add_metrics(metrics, &snap, summary);
         }
 
         info!("backup of {source} done.");
@@ -519,6 +774,16 @@ impl BackupCmd {
     }
 }
 
+/// Replace the snapshot written by the backup with the modified in-memory snapshot
+///
+/// Saving snapshots doesn't return the id of the new snapshot file, so the id is cleared.
+fn replace_snapshot(repo: &IndexedIdsRepo, snap: &mut SnapshotFile) -> Result<()> {
+    let written = std::mem::take(&mut snap.id);
+    repo.save_snapshots(vec![snap.clone()])?;
+    repo.delete_snapshots(&[written])?;
+    Ok(())
+}
+
 #[derive(Serialize)]
 struct JsonProgressSummary {
     message_type: &'static str,
@@ -566,200 +831,123 @@ fn write_json_progress_summary(snap: &SnapshotFile) -> Result<()> {
     Ok(())
 }
 
-#[cfg(not(any(feature = "prometheus", feature = "opentelemetry")))]
-fn publish_metrics(
-    snap: &SnapshotFile,
-    job_name: Option<String>,
-    mut labels: BTreeMap<String, String>,
-) -> Result<()> {
-    Err(anyhow!("metrics support is not compiled-in!"))
-}
-
-#[cfg(any(feature = "prometheus", feature = "opentelemetry"))]
-fn publish_metrics(
-    snap: &SnapshotFile,
-    job_name: Option<String>,
-    mut labels: BTreeMap<String, String>,
-) -> Result<()> {
-    use crate::metrics::MetricValue::*;
-    use crate::metrics::{Metric, MetricsExporter};
-
-    let summary = snap.summary.as_ref().expect("Reaching the 'push to prometheus' point should only happen for successful backups, which must have a summary set.");
-    let metrics = [
-        Metric {
-            name: "rustic_backup_time",
-            description: "Timestamp of this snapshot",
-            value: Float(snap.time.timestamp().as_millisecond() as f64 / 1000.),
-        },
-        Metric {
-            name: "rustic_backup_files_new",
-            description: "New files compared to the last (i.e. parent) snapshot",
-            value: Int(summary.files_new),
-        },
-        Metric {
-            name: "rustic_backup_files_changed",
-            description: "Changed files compared to the last (i.e. parent) snapshot",
-            value: Int(summary.files_changed),
-        },
-        Metric {
-            name: "rustic_backup_files_unmodified",
-            description: "Unchanged files compared to the last (i.e. parent) snapshot",
-            value: Int(summary.files_unmodified),
-        },
-        Metric {
-            name: "rustic_backup_total_files_processed",
-            description: "Total processed files",
-            value: Int(summary.total_files_processed),
-        },
-        Metric {
-            name: "rustic_backup_total_bytes_processed",
-            description: "Total size of all processed files",
-            value: Int(summary.total_bytes_processed),
-        },
-        Metric {
-            name: "rustic_backup_dirs_new",
-            description: "New directories compared to the last (i.e. parent) snapshot",
-            value: Int(summary.dirs_new),
-        },
-        Metric {
-            name: "rustic_backup_dirs_changed",
-            description: "Changed directories compared to the last (i.e. parent) snapshot",
-            value: Int(summary.dirs_changed),
-        },
-        Metric {
-            name: "rustic_backup_dirs_unmodified",
-            description: "Unchanged directories compared to the last (i.e. parent) snapshot",
-            value: Int(summary.dirs_unmodified),
-        },
-        Metric {
-            name: "rustic_backup_total_dirs_processed",
-            description: "Total processed directories",
-            value: Int(summary.total_dirs_processed),
-        },
-        Metric {
-            name: "rustic_backup_total_dirsize_processed",
-            description: "Total size of all processed dirs",
-            value: Int(summary.total_dirsize_processed),
-        },
-        Metric {
-            name: "rustic_backup_data_blobs",
-            description: "Total number of data blobs added by this snapshot",
-            value: Int(summary.data_blobs),
-        },
-        Metric {
-            name: "rustic_backup_tree_blobs",
-            description: "Total number of tree blobs added by this snapshot",
-            value: Int(summary.tree_blobs),
-        },
-        Metric {
-            name: "rustic_backup_data_added",
-            description: "Total uncompressed bytes added by this snapshot",
-            value: Int(summary.data_added),
-        },
-        Metric {
-            name: "rustic_backup_data_added_packed",
-            description: "Total bytes added to the repository by this snapshot",
-            value: Int(summary.data_added_packed),
-        },
-        Metric {
-            name: "rustic_backup_data_added_files",
-            description: "Total uncompressed bytes (new/changed files) added by this snapshot",
-            value: Int(summary.data_added_files),
-        },
-        Metric {
-            name: "rustic_backup_data_added_files_packed",
-            description: "Total bytes for new/changed files added to the repository by this snapshot",
-            value: Int(summary.data_added_files_packed),
-        },
-        Metric {
-            name: "rustic_backup_data_added_trees",
-            description: "Total uncompressed bytes (new/changed directories) added by this snapshot",
-            value: Int(summary.data_added_trees),
-        },
-        Metric {
-            name: "rustic_backup_data_added_trees_packed",
-            description: "Total bytes (new/changed directories) added to the repository by this snapshot",
-            value: Int(summary.data_added_trees_packed),
-        },
-        Metric {
-            name: "rustic_backup_backup_start",
-            description: "Start time of the backup. This may differ from the snapshot `time`.",
-            value: Float(summary.backup_start.timestamp().as_millisecond() as f64 / 1000.),
-        },
-        Metric {
-            name: "rustic_backup_backup_end",
-            description: "The time that the backup has been finished.",
-            value: Float(summary.backup_end.timestamp().as_millisecond() as f64 / 1000.),
-        },
-        Metric {
-            name: "rustic_backup_backup_duration",
-            description: "Total duration of the backup in seconds, i.e. the time between `backup_start` and `backup_end`",
-            value: Float(summary.backup_duration),
-        },
-        Metric {
-            name: "rustic_backup_total_duration",
-            description: "Total duration that the rustic command ran in seconds",
-            value: Float(summary.total_duration),
-        },
-    ];
-
-    _ = labels
-        .entry("paths".to_string())
-        .or_insert_with(|| format!("{}", snap.paths));
-    _ = labels
-        .entry("hostname".to_owned())
-        .or_insert_with(|| snap.hostname.clone());
-    _ = labels
-        .entry("snapshot_label".to_string())
-        .or_insert_with(|| snap.label.clone());
-    _ = labels
-        .entry("tags".to_string())
-        .or_insert_with(|| format!("{}", snap.tags));
-
-    let job_name = job_name.as_deref().unwrap_or("rustic_backup");
-    let global_config = &RUSTIC_APP.config().global;
-
-    #[cfg(feature = "prometheus")]
-    if let Some(prometheus_endpoint) = &global_config.prometheus {
-        use crate::metrics::prometheus::PrometheusExporter;
-
-        let metrics_exporter = PrometheusExporter {
-            endpoint: prometheus_endpoint.clone(),
-            job_name: job_name.to_string(),
-            grouping: labels.clone(),
-            prometheus_user: global_config.prometheus_user.clone(),
-            prometheus_pass: global_config.prometheus_pass.clone(),
-        };
-
-        metrics_exporter
-            .push_metrics(metrics.as_slice())
-            .context("pushing prometheus metrics")?;
-    }
-
-    #[cfg(not(feature = "prometheus"))]
-    if global_config.prometheus.is_some() {
-        bail!("prometheus metrics support is not compiled-in!");
-    }
-
-    #[cfg(feature = "opentelemetry")]
-    if let Some(otlp_endpoint) = &global_config.opentelemetry {
-        use crate::metrics::opentelemetry::OpentelemetryExporter;
-
-        let metrics_exporter = OpentelemetryExporter {
-            endpoint: otlp_endpoint.clone(),
-            service_name: job_name.to_string(),
-            labels: global_config.metrics_labels.clone(),
-        };
-
-        metrics_exporter
-            .push_metrics(metrics.as_slice())
-            .context("pushing opentelemetry metrics")?;
-    }
-
-    #[cfg(not(feature = "opentelemetry"))]
-    if global_config.opentelemetry.is_some() {
-        bail!("opentelemetry metrics support is not compiled-in!");
-    }
-
-    Ok(())
+/// Add the metrics of a saved snapshot
+fn add_metrics(metrics: &mut CommandMetrics, snap: &SnapshotFile, summary: &SnapshotSummary) {
+    use crate::metrics::{MetricValue::*, timestamp_secs};
+
+    metrics.add(
+        "time",
+        "Timestamp of this snapshot",
+        Float(timestamp_secs(snap.time.timestamp())),
+    );
+    metrics.add(
+        "files_new",
+        "New files compared to the last (i.e. parent) snapshot",
+        Int(summary.files_new),
+    );
+    metrics.add(
+        "files_changed",
+        "Changed files compared to the last (i.e. parent) snapshot",
+        Int(summary.files_changed),
+    );
+    metrics.add(
+        "files_unmodified",
+        "Unchanged files compared to the last (i.e. parent) snapshot",
+        Int(summary.files_unmodified),
+    );
+    metrics.add(
+        "total_files_processed",
+        "Total processed files",
+        Int(summary.total_files_processed),
+    );
+    metrics.add(
+        "total_bytes_processed",
+        "Total size of all processed files",
+        Int(summary.total_bytes_processed),
+    );
+    metrics.add(
+        "dirs_new",
+        "New directories compared to the last (i.e. parent) snapshot",
+        Int(summary.dirs_new),
+    );
+    metrics.add(
+        "dirs_changed",
+        "Changed directories compared to the last (i.e. parent) snapshot",
+        Int(summary.dirs_changed),
+    );
+    metrics.add(
+        "dirs_unmodified",
+        "Unchanged directories compared to the last (i.e. parent) snapshot",
+        Int(summary.dirs_unmodified),
+    );
+    metrics.add(
+        "total_dirs_processed",
+        "Total processed directories",
+        Int(summary.total_dirs_processed),
+    );
+    metrics.add(
+        "total_dirsize_processed",
+        "Total size of all processed dirs",
+        Int(summary.total_dirsize_processed),
+    );
+    metrics.add(
+        "data_blobs",
+        "Total number of data blobs added by this snapshot",
+        Int(summary.data_blobs),
+    );
+    metrics.add(
+        "tree_blobs",
+        "Total number of tree blobs added by this snapshot",
+        Int(summary.tree_blobs),
+    );
+    metrics.add(
+        "data_added",
+        "Total uncompressed bytes added by this snapshot",
+        Int(summary.data_added),
+    );
+    metrics.add(
+        "data_added_packed",
+        "Total bytes added to the repository by this snapshot",
+        Int(summary.data_added_packed),
+    );
+    metrics.add(
+        "data_added_files",
+        "Total uncompressed bytes (new/changed files) added by this snapshot",
+        Int(summary.data_added_files),
+    );
+    metrics.add(
+        "data_added_files_packed",
+        "Total bytes for new/changed files added to the repository by this snapshot",
+        Int(summary.data_added_files_packed),
+    );
+    metrics.add(
+        "data_added_trees",
+        "Total uncompressed bytes (new/changed directories) added by this snapshot",
+        Int(summary.data_added_trees),
+    );
+    metrics.add(
+        "data_added_trees_packed",
+        "Total bytes (new/changed directories) added to the repository by this snapshot",
+        Int(summary.data_added_trees_packed),
+    );
+    metrics.add(
+        "backup_start",
+        "Start time of the backup. This may differ from the snapshot `time`.",
+        Float(timestamp_secs(summary.backup_start.timestamp())),
+    );
+    metrics.add(
+        "backup_end",
+        "The time that the backup has been finished.",
+        Float(timestamp_secs(summary.backup_end.timestamp())),
+    );
+    metrics.add(
+        "backup_duration",
+        "Total duration of the backup in seconds, i.e. the time between `backup_start` and `backup_end`",
+        Float(summary.backup_duration),
+    );
+    metrics.add(
+        "total_duration",
+        "Total duration that the rustic command ran in seconds",
+        Float(summary.total_duration),
+    );
 }
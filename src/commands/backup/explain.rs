@@ -0,0 +1,474 @@
+//! Explain which paths are excluded from a backup of a local source and why
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+use ignore::{DirEntry, WalkBuilder, overrides::Override};
+use log::warn;
+use rustic_core::{Excludes, LocalSourceFilterOptions};
+
+use crate::helpers::{bytes_size_to_string, table_right_from};
+
+/// Marker file of cache directories, see <https://bford.info/cachedir/>
+const CACHEDIR_TAG: &str = "CACHEDIR.TAG";
+
+/// A single glob rule
+struct GlobRule {
+    label: String,
+    is_exclude: bool,
+    excludes: Override,
+}
+
+/// A path which is excluded, including all of its contents
+struct Excluded {
+    path: PathBuf,
+    rule: String,
+    entries: u64,
+    size: u64,
+}
+
+impl Excluded {
+    /// Count the path and all of its contents; contents on other file systems are not counted
+    fn new(path: PathBuf, rule: String) -> Self {
+        let mut excl = Self::single(path, rule);
+        if let Ok(meta) = fs::symlink_metadata(&excl.path) {
+            excl.add(&excl.path.clone(), &meta, device(&meta));
+        }
+        excl
+    }
+
+    /// An excluded path whose contents are not counted
+    fn single(path: PathBuf, rule: String) -> Self {
+        Self {
+            path,
+            rule,
+            entries: 0,
+            size: 0,
+        }
+    }
+
+    fn add(&mut self, path: &Path, meta: &fs::Metadata, dev: Option<u64>) {
+        self.entries += 1;
+        if !meta.is_dir() {
+            self.size += meta.len();
+            return;
+        }
+        let Ok(read_dir) = fs::read_dir(path) else {
+            return;
+        };
+        for entry in read_dir.flatten() {
+            if let Ok(meta) = entry.metadata()
+                && (!meta.is_dir() || device(&meta) == dev)
+            {
+                self.add(&entry.path(), &meta, dev);
+            }
+        }
+    }
+}
+
+/// The device of a file, if available on this platform
+#[cfg(unix)]
+fn device(meta: &fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(meta.dev())
+}
+
+#[cfg(not(unix))]
+fn device(_meta: &fs::Metadata) -> Option<u64> {
+    None
+}
+
+/// Rules which exclude paths from a local source
+struct Rules {
+    filter_opts: LocalSourceFilterOptions,
+    overrides: Override,
+    globs: Vec<GlobRule>,
+}
+
+impl Rules {
+    fn new(excludes: &Excludes, filter_opts: &LocalSourceFilterOptions) -> Result<Self> {
+        let mut globs = Vec::new();
+        let mut add_globs =
+            |kind: &str, patterns: &[String], case_insensitive: bool| -> Result<()> {
+                for glob in patterns {
+                    let excludes = if case_insensitive {
+                        Excludes::default().iglobs(vec![glob.clone()])
+                    } else {
+                        Excludes::default().globs(vec![glob.clone()])
+                    };
+                    globs.push(GlobRule {
+                        label: format!("{kind} {glob}"),
+                        is_exclude: glob.starts_with('!'),
+                        excludes: excludes.as_override()?,
+                    });
+                }
+                Ok(())
+            };
+        add_globs("glob", &excludes.globs, false)?;
+        for file in &excludes.glob_files {
+            add_globs(&format!("glob-file {file}:"), &read_lines(file)?, false)?;
+        }
+        add_globs("iglob", &excludes.iglobs, true)?;
+        for file in &excludes.iglob_files {
+            add_globs(&format!("iglob-file {file}:"), &read_lines(file)?, true)?;
+        }
+
+        Ok(Self {
+            filter_opts: filter_opts.clone(),
+            overrides: excludes.as_override()?,
+            globs,
+        })
+    }
+
+    /// The rule of the entry filter which excludes the given entry, using the order of the walker
+    fn filtered(&self, entry: &DirEntry) -> Option<String> {
+        let is_dir = entry.file_type().is_some_and(|tpe| tpe.is_dir());
+        if let Some(limit) = self.filter_opts.exclude_larger_than
+            && !is_dir
+            && entry
+                .metadata()
+                .is_ok_and(|meta| meta.len() > limit.as_u64())
+        {
+            return Some(format!("exclude-larger-than {limit}"));
+        }
+        if is_dir
+            && let Some(file) = self
+                .filter_opts
+                .exclude_if_present
+                .iter()
+                .find(|file| entry.path().join(file).exists())
+        {
+            return Some(if file == CACHEDIR_TAG {
+                format!("exclude-if-present {file} (cache dir tag)")
+            } else {
+                format!("exclude-if-present {file}")
+            });
+        }
+        #[cfg(not(any(windows, target_os = "openbsd")))]
+        if xattr::SUPPORTED_PLATFORM
+            && !self.filter_opts.exclude_if_xattr.is_empty()
+            && let Ok(mut attrs) = xattr::list(entry.path())
+            && attrs.any(|attr| {
+                self.filter_opts
+                    .exclude_if_xattr
+                    .iter()
+                    .any(|name| attr == name.as_str())
+            })
+        {
+            return Some(format!(
+                "exclude-if-xattr {}",
+                self.filter_opts.exclude_if_xattr.join(",")
+            ));
+        }
+        None
+    }
+
+    /// The rule which excluded an entry of `dir` which hasn't been walked, i.e. a glob or ignore file
+    fn skipped(&self, path: &Path, dir: &Path) -> String {
+        let is_dir = fs::symlink_metadata(path).is_ok_and(|meta| meta.is_dir());
+        if self.overrides.matched(path, is_dir).is_ignore() {
+            // the last matching exclude glob has precedence
+            return self
+                .globs
+                .iter()
+                .rev()
+                .find(|glob| glob.is_exclude && glob.excludes.matched(path, is_dir).is_ignore())
+                .map_or_else(
+                    || "glob (not matched by any include glob)".to_string(),
+                    |glob| glob.label.clone(),
+                );
+        }
+        if let Some(file) = self
+            .filter_opts
+            .custom_ignorefiles
+            .iter()
+            .find(|file| dir.ancestors().any(|dir| dir.join(file).exists()))
+        {
+            return format!("custom-ignorefile {file}");
+        }
+        if self.filter_opts.git_ignore {
+            return ".gitignore".to_string();
+        }
+        "unknown".to_string()
+    }
+}
+
+/// Read the glob patterns of a glob file
+fn read_lines(file: &str) -> Result<Vec<String>> {
+    Ok(fs::read_to_string(file)?
+        .lines()
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(ToString::to_string)
+        .collect())
+}
+
+/// A walked dir with its entries which have not been seen by the walker
+struct PendingDir {
+    path: PathBuf,
+    entries: BTreeSet<OsString>,
+}
+
+/// Get the excluded paths, contents of excluded dirs are aggregated into the dir
+///
+/// The source is walked once using the same walker settings as the local source. Entries excluded
+/// by the entry filter are recorded by the filter; entries excluded by the globs or ignore files
+/// are the entries of walked dirs which are not yielded by the walker.
+fn excluded_paths(
+    excludes: &Excludes,
+    filter_opts: &LocalSourceFilterOptions,
+    backup_paths: &[PathBuf],
+) -> Result<Vec<Excluded>> {
+    let rules = Arc::new(Rules::new(excludes, filter_opts)?);
+    let filtered = Arc::new(Mutex::new(Vec::new()));
+
+    let mut builder = WalkBuilder::new(&backup_paths[0]);
+    for path in &backup_paths[1..] {
+        _ = builder.add(path);
+    }
+    for file in &filter_opts.custom_ignorefiles {
+        _ = builder.add_custom_ignore_filename(file);
+    }
+    _ = builder
+        .follow_links(false)
+        .hidden(false)
+        .ignore(false)
+        .git_ignore(filter_opts.git_ignore)
+        .git_exclude(filter_opts.git_ignore)
+        .require_git(!filter_opts.no_require_git)
+        .sort_by_file_path(Path::cmp)
+        .same_file_system(filter_opts.one_file_system)
+        .overrides(rules.overrides.clone());
+    {
+        let (rules, filtered) = (rules.clone(), filtered.clone());
+        _ = builder.filter_entry(move |entry| {
+            let Some(rule) = rules.filtered(entry) else {
+                return true;
+            };
+            filtered
+                .lock()
+                .unwrap()
+                .push((entry.path().to_path_buf(), rule));
+            false
+        });
+    }
+
+    let mut excluded = Vec::new();
+    let mut pending: Vec<PendingDir> = Vec::new();
+    let mut root_dev = None;
+    // mark the path as seen in its parent dir
+    let seen = |pending: &mut Vec<PendingDir>, path: &Path| {
+        if let Some(parent) = path.parent()
+            && let Some(dir) = pending.iter_mut().rev().find(|dir| dir.path == parent)
+            && let Some(name) = path.file_name()
+        {
+            _ = dir.entries.remove(name);
+        }
+    };
+    // record the filtered entries; they are processed by the walker before the next yielded entry
+    let drain_filtered = |pending: &mut Vec<PendingDir>, excluded: &mut Vec<Excluded>| {
+        for (path, rule) in filtered.lock().unwrap().drain(..) {
+            seen(pending, &path);
+            excluded.push(Excluded::new(path, rule));
+        }
+    };
+    // all entries of the dir which are not seen are skipped by the walker
+    let finish = |dir: PendingDir, root_dev: Option<u64>, excluded: &mut Vec<Excluded>| {
+        // the walker doesn't descend into dirs on other file systems
+        let other_fs = filter_opts.one_file_system
+            && fs::symlink_metadata(&dir.path).is_ok_and(|meta| device(&meta) != root_dev);
+        for name in dir.entries {
+            let path = dir.path.join(name);
+            excluded.push(if other_fs {
+                Excluded::single(path, "one-file-system".to_string())
+            } else {
+                let rule = rules.skipped(&path, &dir.path);
+                Excluded::new(path, rule)
+            });
+        }
+    };
+
+    for entry in builder.build() {
+        drain_filtered(&mut pending, &mut excluded);
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                warn!("ignoring error {err}");
+                continue;
+            }
+        };
+        let path = entry.path();
+        while let Some(dir) = pending.pop_if(|dir| !path.starts_with(&dir.path)) {
+            finish(dir, root_dev, &mut excluded);
+        }
+        if entry.depth() == 0 {
+            root_dev = entry.metadata().ok().as_ref().and_then(device);
+        } else {
+            seen(&mut pending, path);
+        }
+        if entry.file_type().is_some_and(|tpe| tpe.is_dir()) {
+            let entries = fs::read_dir(path)?
+                .map(|entry| Ok(entry?.file_name()))
+                .collect::<Result<_>>()?;
+            pending.push(PendingDir {
+                path: path.to_path_buf(),
+                entries,
+            });
+        }
+    }
+    drain_filtered(&mut pending, &mut excluded);
+    while let Some(dir) = pending.pop() {
+        finish(dir, root_dev, &mut excluded);
+    }
+    excluded.sort_by(|e1, e2| e1.path.cmp(&e2.path));
+    Ok(excluded)
+}
+
+/// Print all paths which are excluded from the backup together with the rule which excluded them
+pub(super) fn explain_excludes(
+    excludes: &Excludes,
+    filter_opts: &LocalSourceFilterOptions,
+    backup_paths: &[PathBuf],
+) -> Result<()> {
+    let excluded = excluded_paths(excludes, filter_opts, backup_paths)?;
+
+    let mut table = table_right_from(2, ["Excluded path", "Rule", "Entries", "Size"]);
+    let mut per_rule: BTreeMap<&str, (u64, u64, u64)> = BTreeMap::new();
+    for excl in &excluded {
+        _ = table.add_row([
+            excl.path.display().to_string(),
+            excl.rule.clone(),
+            excl.entries.to_string(),
+            bytes_size_to_string(excl.size),
+        ]);
+        let (paths, entries, size) = per_rule.entry(&excl.rule).or_default();
+        *paths += 1;
+        *entries += excl.entries;
+        *size += excl.size;
+    }
+    println!("{table}");
+
+    let mut table = table_right_from(1, ["Rule", "Paths", "Entries", "Size"]);
+    for (rule, (paths, entries, size)) in per_rule {
+        _ = table.add_row([
+            rule.to_string(),
+            paths.to_string(),
+            entries.to_string(),
+            bytes_size_to_string(size),
+        ]);
+    }
+    let total: u64 = excluded.iter().map(|excl| excl.size).sum();
+    println!("{table}");
+    println!(
+        "excluded {} paths with {} in total.",
+        excluded.len(),
+        bytes_size_to_string(total)
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytesize::ByteSize;
+
+    #[test]
+    fn rules_of_excluded_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let write = |path: &str, size: usize| {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, vec![0; size]).unwrap();
+        };
+        write("keep.txt", 10);
+        write("temp.tmp", 10);
+        write("UPPER.BAK", 10);
+        write("big.bin", 5000);
+        write("cache/CACHEDIR.TAG", 0);
+        write("cache/data", 100);
+        write("ignored/file", 10);
+        fs::write(root.join(".gitignore"), "ignored/\n").unwrap();
+
+        let excludes = Excludes::default()
+            .globs(vec!["!*.tmp".to_string()])
+            .iglobs(vec!["!*.bak".to_string()]);
+        let filter_opts = LocalSourceFilterOptions::default()
+            .git_ignore(true)
+            .no_require_git(true)
+            .exclude_if_present(vec![CACHEDIR_TAG.to_string()])
+            .exclude_larger_than(ByteSize::kib(4));
+
+        let excluded = excluded_paths(&excludes, &filter_opts, &[root.to_path_buf()]).unwrap();
+        let rules: Vec<_> = excluded
+            .iter()
+            .map(|excl| {
+                (
+                    excl.path.strip_prefix(root).unwrap().to_path_buf(),
+                    excl.rule.as_str(),
+                    excl.entries,
+                )
+            })
+            .collect();
+        assert_eq!(
+            rules,
+            vec![
+                (PathBuf::from("UPPER.BAK"), "iglob !*.bak", 1),
+                (PathBuf::from("big.bin"), "exclude-larger-than 4.0 KiB", 1),
+                (
+                    PathBuf::from("cache"),
+                    "exclude-if-present CACHEDIR.TAG (cache dir tag)",
+                    3
+                ),
+                (PathBuf::from("ignored"), ".gitignore", 2),
+                (PathBuf::from("temp.tmp"), "glob !*.tmp", 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn first_excluding_rule_is_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("sub/nested")).unwrap();
+        fs::write(root.join("both.log"), "x").unwrap();
+        fs::write(root.join("sub/nested/file"), "x").unwrap();
+        fs::write(root.join("sub/.rusticignore"), "nested/\n").unwrap();
+        fs::write(root.join(".gitignore"), "*.log\n").unwrap();
+
+        // globs have precedence over ignore files
+        let excludes = Excludes::default().globs(vec!["!*.log".to_string()]);
+        let filter_opts = LocalSourceFilterOptions::default()
+            .git_ignore(true)
+            .no_require_git(true)
+            .custom_ignorefiles(vec![".rusticignore".to_string()]);
+
+        let excluded = excluded_paths(&excludes, &filter_opts, &[root.to_path_buf()]).unwrap();
+        let rules: Vec<_> = excluded
+            .iter()
+            .map(|excl| {
+                (
+                    excl.path.strip_prefix(root).unwrap().to_path_buf(),
+                    excl.rule.as_str(),
+                    excl.entries,
+                )
+            })
+            .collect();
+        assert_eq!(
+            rules,
+            vec![
+                (PathBuf::from("both.log"), "glob !*.log", 1),
+                (
+                    PathBuf::from("sub/nested"),
+                    "custom-ignorefile .rusticignore",
+                    2
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,141 @@
+//! Read the paths to back up from files, e.g. generated by `find` or a database query
+
+use std::{
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+use log::{error, warn};
+
+/// Format of a file containing paths to back up
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum ListFormat {
+    /// One path per line; surrounding whitespace, empty lines and lines starting with `#` are ignored
+    Lines,
+    /// One path per line which is taken as-is; only empty lines are ignored
+    Verbatim,
+    /// NUL-separated paths, e.g. generated by `find -print0`
+    Raw,
+}
+
+/// Read the paths from all given files
+///
+/// Paths which don't exist are removed with a warning or, if `fail_on_missing` is set, result in an error.
+///
+/// # Errors
+///
+/// * If a file could not be read or contains invalid paths
+/// * If `fail_on_missing` is set and a path doesn't exist
+pub(super) fn read_lists<'a>(
+    lists: impl IntoIterator<Item = (&'a [PathBuf], ListFormat)>,
+    fail_on_missing: bool,
+) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for (files, format) in lists {
+        for file in files {
+            let content = if file == Path::new("-") {
+                let mut content = Vec::new();
+                _ = io::stdin().read_to_end(&mut content)?;
+                content
+            } else {
+                fs::read(file).with_context(|| format!("error reading {}", file.display()))?
+            };
+            paths.extend(
+                parse_list(&content, format)
+                    .with_context(|| format!("error parsing {}", file.display()))?,
+            );
+        }
+    }
+
+    let mut missing = 0;
+    paths.retain(|path| match fs::symlink_metadata(path) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            if fail_on_missing {
+                error!("{}: path does not exist", path.display());
+            } else {
+                warn!("{}: path does not exist, skipping it", path.display());
+            }
+            missing += 1;
+            false
+        }
+        // other errors are reported when backing up the path
+        _ => true,
+    });
+    if fail_on_missing && missing > 0 {
+        bail!("{missing} paths given by files-from lists do not exist.");
+    }
+    Ok(paths)
+}
+
+/// Parse the paths contained in a file using the given format
+fn parse_list(content: &[u8], format: ListFormat) -> Result<Vec<PathBuf>> {
+    let separator = if format == ListFormat::Raw {
+        b'\0'
+    } else {
+        b'\n'
+    };
+    content
+        .split(|b| *b == separator)
+        .filter_map(|entry| {
+            let entry = match format {
+                ListFormat::Lines => entry.trim_ascii(),
+                ListFormat::Verbatim => entry.strip_suffix(b"\r").unwrap_or(entry),
+                ListFormat::Raw => entry,
+            };
+            let ignore = entry.is_empty() || format == ListFormat::Lines && entry.starts_with(b"#");
+            (!ignore).then(|| path_from_bytes(entry))
+        })
+        .collect()
+}
+
+#[cfg(not(windows))]
+#[allow(clippy::unnecessary_wraps)]
+fn path_from_bytes(bytes: &[u8]) -> Result<PathBuf> {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+    Ok(OsStr::from_bytes(bytes).into())
+}
+
+#[cfg(windows)]
+fn path_from_bytes(bytes: &[u8]) -> Result<PathBuf> {
+    Ok(std::str::from_utf8(bytes)?.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(ListFormat::Lines, "a\n  b c \n\n# comment\r\nd\r\n", &["a", "b c", "d"])]
+    #[case(ListFormat::Verbatim, "a\n  b c \n\n# comment\r\n", &["a", "  b c ", "# comment"])]
+    #[case(ListFormat::Raw, "a\0b\nc \0\0", &["a", "b\nc "])]
+    fn parse_lists(#[case] format: ListFormat, #[case] content: &str, #[case] expected: &[&str]) {
+        let paths = parse_list(content.as_bytes(), format).unwrap();
+        let expected: Vec<_> = expected.iter().map(PathBuf::from).collect();
+        assert_eq!(paths, expected);
+    }
+
+    #[test]
+    fn missing_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let existing = dir.path().join("existing");
+        fs::write(&existing, "").unwrap();
+        let list = dir.path().join("list");
+        fs::write(
+            &list,
+            format!(
+                "{}\n{}\n",
+                existing.display(),
+                dir.path().join("missing").display()
+            ),
+        )
+        .unwrap();
+
+        let files = [list];
+        let lists = [(&files[..], ListFormat::Lines)];
+        assert_eq!(read_lists(lists, false).unwrap(), [existing]);
+        assert!(read_lists(lists, true).is_err());
+    }
+}
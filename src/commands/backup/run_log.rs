@@ -0,0 +1,140 @@
+//! Save the warnings and errors logged during a backup within the snapshot
+//!
+//! The log is appended to the description of the snapshot, separated by [`LOG_SEPARATOR`]. So it is
+//! kept exactly as long as the snapshot and the backed up tree is not modified. The log cannot be
+//! saved as separate blob, as `prune` only keeps blobs which are referenced by snapshot trees.
+//! Modifying the description with `rewrite` keeps the saved log, see [`keep_log`].
+
+use std::fs;
+
+use anyhow::{Context, Result};
+use rustic_core::repofile::{SnapshotFile, SnapshotModification};
+
+/// Line separating the description given by the user from the saved log
+const LOG_SEPARATOR: &str = "--- backup log ---\n";
+
+/// Split the description into the part given by the user and the saved log
+fn split(description: &str) -> (&str, Option<&str>) {
+    if let Some(log) = description.strip_prefix(LOG_SEPARATOR) {
+        return ("", Some(log));
+    }
+    description
+        .split_once(&format!("\n{LOG_SEPARATOR}"))
+        .map_or((description, None), |(description, log)| {
+            (description, Some(log))
+        })
+}
+
+/// The description given by the user, i.e. without a saved log
+pub(crate) fn plain_description(snap: &SnapshotFile) -> Option<&str> {
+    snap.description
+        .as_deref()
+        .map(|description| split(description).0)
+        .filter(|description| !description.is_empty())
+}
+
+/// The log saved within the snapshot, if any
+pub(crate) fn read_log(snap: &SnapshotFile) -> Option<&str> {
+    split(snap.description.as_deref()?).1
+}
+
+/// The description to save the given user description together with the log
+pub(crate) fn with_log(description: Option<&str>, log: Option<&str>) -> Option<String> {
+    match (description.filter(|d| !d.is_empty()), log) {
+        (description, None) => description.map(ToString::to_string),
+        (None, Some(log)) => Some(format!("{LOG_SEPARATOR}{log}")),
+        (Some(description), Some(log)) => Some(format!("{description}\n{LOG_SEPARATOR}{log}")),
+    }
+}
+
+/// Check if `modification` sets or removes the description
+pub(crate) fn modifies_description(modification: &SnapshotModification) -> bool {
+    modification.remove_description
+        || modification.set_description.is_some()
+        || modification.set_description_from.is_some()
+}
+
+/// The modification to apply to snapshots having the saved `log`
+///
+/// If `modification` sets or removes the description, it is changed to keep the saved log.
+///
+/// # Errors
+///
+/// * If the description to set cannot be read from the given file
+pub(crate) fn keep_log(
+    modification: &SnapshotModification,
+    log: Option<&str>,
+) -> Result<SnapshotModification> {
+    let Some(log) = log.filter(|_| modifies_description(modification)) else {
+        return Ok(modification.clone());
+    };
+
+    let description = if modification.remove_description {
+        None
+    } else if let Some(path) = &modification.set_description_from {
+        Some(
+            fs::read_to_string(path)
+                .with_context(|| format!("error reading description from {}", path.display()))?,
+        )
+    } else {
+        modification.set_description.clone()
+    };
+
+    let mut modification = modification.clone();
+    modification.set_description = with_log(description.as_deref(), Some(log));
+    modification.set_description_from = None;
+    modification.remove_description = false;
+    Ok(modification)
+}
+
+/// Save `log` within the snapshot
+pub(super) fn attach_log(snap: &mut SnapshotFile, log: &[u8]) {
+    let log = String::from_utf8_lossy(log);
+    snap.description = with_log(plain_description(snap), Some(&log));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(None)]
+    #[case(Some("my description"))]
+    #[case(Some("multi\nline\n"))]
+    fn log_is_kept_separately(#[case] description: Option<&str>) {
+        let mut snap = SnapshotFile {
+            description: description.map(ToString::to_string),
+            ..Default::default()
+        };
+        assert_eq!(read_log(&snap), None);
+        assert_eq!(plain_description(&snap), description);
+
+        attach_log(&mut snap, b"[WARN] file not found\n");
+        assert_eq!(read_log(&snap), Some("[WARN] file not found\n"));
+        assert_eq!(plain_description(&snap), description);
+
+        snap.description = with_log(Some("changed"), read_log(&snap));
+        assert_eq!(read_log(&snap), Some("[WARN] file not found\n"));
+        assert_eq!(plain_description(&snap), Some("changed"));
+    }
+
+    #[test]
+    fn rewriting_description_keeps_log() -> Result<()> {
+        let mut snap = SnapshotFile::default();
+        attach_log(&mut snap, b"[WARN] file not found\n");
+
+        let set = SnapshotModification::default().set_description("new".to_string());
+        _ = snap.modify(&keep_log(&set, read_log(&snap))?)?;
+        assert_eq!(plain_description(&snap), Some("new"));
+        assert_eq!(read_log(&snap), Some("[WARN] file not found\n"));
+
+        let remove = SnapshotModification::default().remove_description(true);
+        _ = snap.modify(&keep_log(&remove, read_log(&snap))?)?;
+        assert_eq!(plain_description(&snap), None);
+        assert_eq!(read_log(&snap), Some("[WARN] file not found\n"));
+
+        assert!(keep_log(&remove, None)?.remove_description);
+        Ok(())
+    }
+}
@@ -0,0 +1,377 @@
+//! Back up the output of several commands as separate files within one snapshot
+
+use std::{
+    ffi::OsStr,
+    io,
+    path::{PathBuf, is_separator},
+    process::{Child, ChildStdout, Command, ExitStatus, Stdio},
+    str::FromStr,
+    sync::{Arc, Condvar, Mutex},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Result, anyhow, bail};
+use jiff::{SignedDuration, Timestamp};
+use log::{info, warn};
+use rustic_core::{
+    CommandInput, ErrorKind, ReadSource, ReadSourceEntry, ReadSourceOpen, RusticError,
+    RusticResult,
+    repofile::{Metadata, Node, NodeType},
+};
+use serde::{Deserialize, Serialize};
+use serde_with::{DisplayFromStr, serde_as};
+
+/// Default number of commands to run in parallel
+pub(super) const DEFAULT_CONCURRENCY: usize = 4;
+
+/// Interval to check if a command with a timeout has finished
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A command whose output is saved as a file in the snapshot
+#[serde_as]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct StdinStream {
+    /// Filename of the output within the snapshot
+    filename: String,
+
+    /// Command to call
+    command: CommandInput,
+
+    /// Kill the command if it runs longer than the given duration
+    #[serde(default)]
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    timeout: Option<SignedDuration>,
+}
+
+impl FromStr for StdinStream {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        let (filename, command) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("invalid stdin stream `{s}`, please use FILENAME=COMMAND"))?;
+        Ok(Self {
+            filename: filename.to_string(),
+            command: command.parse()?,
+            timeout: None,
+        })
+    }
+}
+
+/// Result of calling a command
+struct Outcome {
+    status: io::Result<ExitStatus>,
+    timed_out: bool,
+}
+
+/// Handles of the threads waiting for the commands together with the index of the stream
+type Waiters = Arc<Mutex<Vec<(usize, JoinHandle<Outcome>)>>>;
+
+/// Limits the number of commands running at the same time
+struct Slots {
+    free: Mutex<usize>,
+    released: Condvar,
+}
+
+impl Slots {
+    fn acquire(self: &Arc<Self>) -> SlotGuard {
+        let mut free = self.free.lock().unwrap();
+        while *free == 0 {
+            free = self.released.wait(free).unwrap();
+        }
+        *free -= 1;
+        drop(free);
+        SlotGuard(self.clone())
+    }
+}
+
+/// A running command; the slot is released when this is dropped
+struct SlotGuard(Arc<Slots>);
+
+impl Drop for SlotGuard {
+    fn drop(&mut self) {
+        *self.0.free.lock().unwrap() += 1;
+        self.0.released.notify_one();
+    }
+}
+
+/// [`ReadSource`] which saves the output of each command as a separate file
+///
+/// Commands are started when the archiver reads the file, so they run in parallel as far as the
+/// archiver and the concurrency limit allow.
+pub(super) struct StdinStreamsSource {
+    streams: Vec<StdinStream>,
+    default_timeout: Option<SignedDuration>,
+    slots: Arc<Slots>,
+    waiters: Waiters,
+}
+
+impl StdinStreamsSource {
+    pub(super) fn new(
+        mut streams: Vec<StdinStream>,
+        concurrency: usize,
+        default_timeout: Option<SignedDuration>,
+    ) -> Result<Self> {
+        for stream in &streams {
+            let name = &stream.filename;
+            if name.is_empty() || name == "." || name == ".." || name.chars().any(is_separator) {
+                bail!("invalid filename `{name}` for stdin stream, please use a plain filename.");
+            }
+        }
+        // the archiver needs the entries sorted by path
+        streams.sort_by(|s1, s2| s1.filename.cmp(&s2.filename));
+        if let Some(dup) = streams.windows(2).find(|s| s[0].filename == s[1].filename) {
+            bail!(
+                "filename `{}` is used by several stdin streams.",
+                dup[0].filename
+            );
+        }
+        if concurrency == 0 {
+            bail!("stdin concurrency must be at least 1.");
+        }
+        Ok(Self {
+            streams,
+            default_timeout,
+            slots: Arc::new(Slots {
+                free: Mutex::new(concurrency),
+                released: Condvar::new(),
+            }),
+            waiters: Arc::default(),
+        })
+    }
+
+    /// The paths of the files within the snapshot
+    pub(super) fn paths(&self) -> Vec<PathBuf> {
+        self.streams
+            .iter()
+            .map(|stream| PathBuf::from(&stream.filename))
+            .collect()
+    }
+
+    /// Wait for all commands and handle their exit status
+    ///
+    /// # Errors
+    ///
+    /// * If a command failed, was killed due to a timeout or was not called at all
+    pub(super) fn finish(self) -> Result<()> {
+        let mut outcomes: Vec<Option<Outcome>> = self.streams.iter().map(|_| None).collect();
+        let waiters = std::mem::take(&mut *self.waiters.lock().unwrap());
+        for (index, handle) in waiters {
+            outcomes[index] = Some(handle.join().expect("waiting thread should not panic"));
+        }
+
+        let mut failed = Vec::new();
+        for (stream, outcome) in self.streams.iter().zip(outcomes) {
+            let status = match outcome {
+                Some(Outcome { status, timed_out }) => {
+                    if timed_out {
+                        warn!("{}: command timed out and was killed", stream.filename);
+                    }
+                    status
+                }
+                None => Err(io::Error::other("command was not called")),
+            };
+            if stream
+                .command
+                .on_failure()
+                .handle_status(status, &stream.filename, "stdin-stream")
+                .is_err()
+            {
+                failed.push(stream.filename.as_str());
+            }
+        }
+        if !failed.is_empty() {
+            bail!("stdin streams failed: {}", failed.join(", "));
+        }
+        Ok(())
+    }
+}
+
+impl ReadSource for StdinStreamsSource {
+    type Open = StdinStreamOpen;
+    type Iter = std::vec::IntoIter<RusticResult<ReadSourceEntry<StdinStreamOpen>>>;
+
+    fn size(&self) -> RusticResult<Option<u64>> {
+        Ok(None)
+    }
+
+    fn entries(&self) -> Self::Iter {
+        // set the mtime so that the files are never taken from a parent snapshot
+        let meta = Metadata {
+            mtime: Some(Timestamp::now()),
+            ..Default::default()
+        };
+        self.streams
+            .iter()
+            .enumerate()
+            .map(|(index, stream)| {
+                Ok(ReadSourceEntry {
+                    path: PathBuf::from(&stream.filename),
+                    node: Node::new_node(
+                        OsStr::new(&stream.filename),
+                        NodeType::File,
+                        meta.clone(),
+                    ),
+                    open: Some(StdinStreamOpen {
+                        index,
+                        stream: stream.clone(),
+                        timeout: stream.timeout.or(self.default_timeout),
+                        slots: self.slots.clone(),
+                        waiters: self.waiters.clone(),
+                    }),
+                })
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+}
+
+/// Starts the command of a stdin stream once the archiver reads the file
+pub(super) struct StdinStreamOpen {
+    index: usize,
+    stream: StdinStream,
+    timeout: Option<SignedDuration>,
+    slots: Arc<Slots>,
+    waiters: Waiters,
+}
+
+impl ReadSourceOpen for StdinStreamOpen {
+    type Reader = ChildStdout;
+
+    #[allow(clippy::literal_string_with_formatting_args)]
+    fn open(self) -> RusticResult<Self::Reader> {
+        let slot = self.slots.acquire();
+        let StdinStream {
+            filename, command, ..
+        } = &self.stream;
+        info!("{filename}: calling {command}");
+        let mut cmd = Command::new(command.command());
+        _ = cmd.args(command.args()).stdout(Stdio::piped());
+        // use an own process group to be able to kill subprocesses on timeout
+        #[cfg(not(windows))]
+        {
+            use std::os::unix::process::CommandExt;
+            _ = cmd.process_group(0);
+        }
+        let child = cmd.spawn();
+        let (handle, res) = match child {
+            Ok(mut child) => {
+                let stdout = child.stdout.take().expect("stdout should be piped");
+                let timeout = self.timeout.and_then(|t| t.try_into().ok());
+                let handle = thread::spawn(move || {
+                    let outcome = wait(child, timeout);
+                    drop(slot);
+                    outcome
+                });
+                (handle, Ok(stdout))
+            }
+            Err(err) => {
+                let status = Err(io::Error::new(err.kind(), err.to_string()));
+                let handle = thread::spawn(move || Outcome {
+                    status,
+                    timed_out: false,
+                });
+                let err = RusticError::with_source(
+                    ErrorKind::ExternalCommand,
+                    "Failed to call the command of stdin stream `{filename}`.",
+                    err,
+                )
+                .attach_context("filename", filename.clone());
+                (handle, Err(err))
+            }
+        };
+        self.waiters.lock().unwrap().push((self.index, handle));
+        res
+    }
+}
+
+/// Wait for the command to finish; kill it if it runs longer than the timeout
+fn wait(mut child: Child, timeout: Option<Duration>) -> Outcome {
+    let Some(timeout) = timeout else {
+        return Outcome {
+            status: child.wait(),
+            timed_out: false,
+        };
+    };
+    let deadline = Instant::now() + timeout;
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => {
+                return Outcome {
+                    status: Ok(status),
+                    timed_out: false,
+                };
+            }
+            Ok(None) if Instant::now() >= deadline => {
+                kill(&mut child);
+                return Outcome {
+                    status: child.wait(),
+                    timed_out: true,
+                };
+            }
+            Ok(None) => thread::sleep(POLL_INTERVAL),
+            Err(err) => {
+                return Outcome {
+                    status: Err(err),
+                    timed_out: false,
+                };
+            }
+        }
+    }
+}
+
+/// Kill the command including all of its subprocesses which might still write to the output
+fn kill(child: &mut Child) {
+    #[cfg(not(windows))]
+    if let Ok(pid) = libc::pid_t::try_from(child.id()) {
+        // SAFETY: kill has no memory safety requirements; the process group was created on spawn
+        _ = unsafe { libc::kill(-pid, libc::SIGKILL) };
+    }
+    _ = child.kill();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn streams(names: &[&str]) -> Vec<StdinStream> {
+        names
+            .iter()
+            .map(|name| format!("{name}=echo {name}").parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn parse_stdin_stream() {
+        let stream: StdinStream = "db.sql=pg_dump -d db".parse().unwrap();
+        assert_eq!(stream.filename, "db.sql");
+        assert_eq!(stream.command.command(), "pg_dump");
+        assert_eq!(stream.command.args(), ["-d", "db"]);
+        assert!("pg_dump".parse::<StdinStream>().is_err());
+    }
+
+    #[rstest]
+    #[case(&["b", "a"], 1, true)]
+    #[case(&["a", "a"], 1, false)]
+    #[case(&["a/b"], 1, false)]
+    #[case(&[".."], 1, false)]
+    #[case(&[""], 1, false)]
+    #[case(&["a"], 0, false)]
+    fn validate_streams(#[case] names: &[&str], #[case] concurrency: usize, #[case] ok: bool) {
+        assert_eq!(
+            StdinStreamsSource::new(streams(names), concurrency, None).is_ok(),
+            ok
+        );
+    }
+
+    #[test]
+    fn streams_are_sorted() {
+        let src = StdinStreamsSource::new(streams(&["b", "c", "a"]), 1, None).unwrap();
+        assert_eq!(
+            src.paths(),
+            [PathBuf::from("a"), PathBuf::from("b"), PathBuf::from("c")]
+        );
+    }
+}
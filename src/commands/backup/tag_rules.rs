@@ -0,0 +1,216 @@
+//! Rules to add tags to the snapshot of a backup
+//!
+//! The rules are evaluated once the summary of the snapshot is known, so conditions may depend on
+//! e.g. the size added to the repository. If tags are added, the snapshot written by the backup is
+//! replaced.
+
+use std::{
+    env,
+    fmt::{self, Display},
+    process::{Command, Stdio},
+    str::FromStr,
+};
+
+use anyhow::{Result, anyhow};
+use bytesize::ByteSize;
+use jiff::civil::Weekday;
+use log::info;
+use rustic_core::{CommandInput, StringList, repofile::SnapshotFile};
+use serde::{Deserialize, Serialize};
+use serde_with::{DisplayFromStr, serde_as};
+
+use crate::filtering::parse_weekday;
+
+/// A rule adding tags to the snapshot if all of its conditions are met
+#[serde_as]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct TagRule {
+    /// Tags to add
+    tags: Vec<String>,
+
+    /// Add the values of these environment variables as tags (unset or empty variables are ignored)
+    tags_from_env: Vec<String>,
+
+    /// Add each line of the output of this command as tag
+    tags_from_command: Option<CommandInput>,
+
+    /// Only apply if the snapshot is taken on one of these weekdays
+    #[serde_as(as = "Vec<DisplayFromStr>")]
+    weekdays: Vec<RuleWeekday>,
+
+    /// Only apply if at least this size was added to the repository
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    min_added: Option<ByteSize>,
+
+    /// Only apply if less than this size was added to the repository
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    max_added: Option<ByteSize>,
+
+    /// Only apply if all of these environment variables are set
+    if_env: Vec<String>,
+}
+
+/// A weekday given by its name, e.g. "sunday" or "sun"
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RuleWeekday(Weekday);
+
+impl FromStr for RuleWeekday {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        parse_weekday(&s.to_lowercase())
+            .map(Self)
+            .ok_or_else(|| anyhow!("invalid weekday `{s}`"))
+    }
+}
+
+impl Display for RuleWeekday {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.0)
+    }
+}
+
+impl TagRule {
+    /// Whether all conditions of this rule are met for the given snapshot
+    fn matches(&self, snap: &SnapshotFile) -> bool {
+        let added = snap
+            .summary
+            .as_ref()
+            .map_or(0, |summary| summary.data_added_packed);
+        (self.weekdays.is_empty() || self.weekdays.contains(&RuleWeekday(snap.time.weekday())))
+            && self.min_added.is_none_or(|min| added >= min.as_u64())
+            && self.max_added.is_none_or(|max| added < max.as_u64())
+            && self.if_env.iter().all(|var| env::var_os(var).is_some())
+    }
+
+    /// The tags given by this rule
+    ///
+    /// # Errors
+    ///
+    /// * If the command fails and is not configured to only warn or ignore failures
+    fn tags(&self) -> Result<Vec<String>> {
+        let mut tags = self.tags.clone();
+        tags.extend(
+            self.tags_from_env
+                .iter()
+                .filter_map(|var| env::var(var).ok())
+                .flat_map(|value| split_tags(&value)),
+        );
+        if let Some(command) = self.tags_from_command.as_ref().filter(|c| c.is_set()) {
+            let output = Command::new(command.command())
+                .args(command.args())
+                .stderr(Stdio::inherit())
+                .output();
+            let success = output.as_ref().is_ok_and(|output| output.status.success());
+            let (status, stdout) = match output {
+                Ok(output) => (Ok(output.status), output.stdout),
+                Err(err) => (Err(err), Vec::new()),
+            };
+            command
+                .on_failure()
+                .handle_status(status, "tag-rules", "tags-from-command")?;
+            if success {
+                tags.extend(split_tags(&String::from_utf8_lossy(&stdout)));
+            }
+        }
+        Ok(tags)
+    }
+}
+
+/// Split the given output into tags; lines and commas separate tags
+fn split_tags(s: &str) -> Vec<String> {
+    s.split(['\n', ','])
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .map(ToString::to_string)
+        .collect()
+}
+
+/// Evaluate the rules for the given snapshot and add the resulting tags
+///
+/// # Returns
+///
+/// Whether the tags of the snapshot changed
+///
+/// # Errors
+///
+/// * If a command given by a rule fails
+pub(super) fn apply_tag_rules(rules: &[TagRule], snap: &mut SnapshotFile) -> Result<bool> {
+    let mut tags = StringList::default();
+    for rule in rules.iter().filter(|rule| rule.matches(snap)) {
+        for tag in rule.tags()? {
+            tags.add(tag);
+        }
+    }
+    if tags.iter().next().is_none() {
+        return Ok(false);
+    }
+    let changed = snap.add_tags(vec![tags.clone()]);
+    if changed {
+        info!("adding tags {tags} by tagging rules");
+    }
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use rustic_core::repofile::SnapshotSummary;
+
+    fn rule(toml: &str) -> TagRule {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[rstest]
+    #[case("", true)]
+    #[case("weekdays = [\"Sun\"]", true)]
+    #[case("weekdays = [\"monday\", \"tue\"]", false)]
+    #[case("min-added = \"1 MiB\"", true)]
+    #[case("min-added = \"2 MiB\"", false)]
+    #[case("max-added = \"2 MiB\"", true)]
+    #[case("max-added = \"1 MiB\"", false)]
+    fn rule_matches(#[case] conditions: &str, #[case] expected: bool) {
+        let mut snap = SnapshotFile {
+            time: "2026-10-18T12:00:00[UTC]".parse().unwrap(),
+            ..Default::default()
+        };
+        let mut summary = SnapshotSummary::default();
+        summary.data_added_packed = 1024 * 1024;
+        snap.summary = Some(summary);
+        assert_eq!(rule(conditions).matches(&snap), expected);
+    }
+
+    #[test]
+    fn invalid_weekday() {
+        assert!(toml::from_str::<TagRule>("weekdays = [\"someday\"]").is_err());
+    }
+
+    #[test]
+    fn tags_are_added_by_matching_rules() -> Result<()> {
+        let rules = [
+            rule("tags = [\"weekly\", \"sunday\"]\nweekdays = [\"sun\"]"),
+            rule("tags = [\"monday\"]\nweekdays = [\"mon\"]"),
+            rule("tags = [\"big\"]\nmin-added = \"1 MiB\""),
+        ];
+        let mut snap = SnapshotFile {
+            time: "2026-10-18T12:00:00[UTC]".parse()?,
+            tags: "sunday".parse()?,
+            ..Default::default()
+        };
+        assert!(apply_tag_rules(&rules, &mut snap)?);
+        assert_eq!(snap.tags.to_string(), "sunday,weekly");
+        // applying again doesn't change the snapshot
+        assert!(!apply_tag_rules(&rules, &mut snap)?);
+        assert!(!apply_tag_rules(&[], &mut snap)?);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("", &[])]
+    #[case("job-42\n", &["job-42"])]
+    #[case(" a, b\n\nc ", &["a", "b", "c"])]
+    fn split(#[case] output: &str, #[case] expected: &[&str]) {
+        assert_eq!(split_tags(output), expected);
+    }
+}
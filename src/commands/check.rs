@@ -2,13 +2,18 @@
 
 use crate::{
     Application, RUSTIC_APP,
-    repository::{OpenRepo, get_global_grouped_snapshots},
+    commands::status::CheckState,
+    metrics::{CommandMetrics, MetricValue::Int},
+    repository::{OpenRepo, get_global_grouped_snapshots, parity::ParityCheck},
     status_err,
 };
 
+use std::sync::Arc;
+
 use abscissa_core::{Command, Runnable, Shutdown};
-use anyhow::Result;
-use rustic_core::{CheckOptions, repofile::SnapshotFile};
+use anyhow::{Result, bail};
+use jiff::Timestamp;
+use rustic_core::{CheckOptions, CheckResults, repofile::SnapshotFile};
 
 /// `check` subcommand
 #[derive(clap::Parser, Command, Debug)]
@@ -26,11 +31,16 @@ pub(crate) struct CheckCmd {
 
 impl Runnable for CheckCmd {
     fn run(&self) {
-        if let Err(err) = RUSTIC_APP
-            .config()
-            .repository
-            .run_open(|repo| self.inner_run(repo))
-        {
+        let config = RUSTIC_APP.config();
+        let mut metrics = CommandMetrics::new("check");
+        // verify packs against their parity files while reading the pack data
+        let parity_check = self.opts.read_data.then(Arc::<ParityCheck>::default);
+        let mut repo_opts = config.repository.clone();
+        repo_opts.parity.check.clone_from(&parity_check);
+        let res =
+            repo_opts.run_open(|repo| self.inner_run(repo, parity_check.as_deref(), &mut metrics));
+        metrics.publish(res.is_ok());
+        if let Err(err) = res {
             status_err!("{}", err);
             RUSTIC_APP.shutdown(Shutdown::Crash);
         };
@@ -38,10 +48,57 @@ impl Runnable for CheckCmd {
 }
 
 impl CheckCmd {
-    fn inner_run(&self, repo: OpenRepo) -> Result<()> {
+    fn inner_run(
+        &self,
+        repo: OpenRepo,
+        parity_check: Option<&ParityCheck>,
+        metrics: &mut CommandMetrics,
+    ) -> Result<()> {
         let snaps: Vec<SnapshotFile> = get_global_grouped_snapshots(&repo, &self.ids)?.into();
         let trees = snaps.into_iter().map(|snap| snap.tree).collect();
-        repo.check_with_trees(self.opts, trees)?.is_ok()?;
+        let results = repo.check_with_trees(self.opts, trees)?;
+        let damaged_packs = parity_check.map_or(0, ParityCheck::report);
+
+        let (errors, warnings) = count_findings(&results);
+        // the counts are only informational, the result is determined by `rustic_core`
+        let found_errors = results.is_ok().is_err();
+        let errors = if found_errors { errors.max(1) } else { errors };
+        metrics.add("errors", "Number of errors found", Int(errors));
+        metrics.add("warnings", "Number of warnings found", Int(warnings));
+        metrics.add(
+            "damaged_packs",
+            "Number of packs found damaged using parity files",
+            Int(damaged_packs),
+        );
+
+        // remember the result for the `status` command
+        CheckState {
+            time: Timestamp::now(),
+            success: !found_errors && damaged_packs == 0,
+            errors: errors + damaged_packs,
+            warnings,
+            read_data: self.opts.read_data,
+        }
+        .save(&repo);
+
+        results.is_ok()?;
+        if damaged_packs > 0 {
+            bail!("parity check found damaged packs!");
+        }
         Ok(())
     }
 }
+
+/// Count the findings of a check as `(errors, warnings)`
+///
+/// `rustic_core` doesn't export the type of the severity level, so it is identified by its name.
+/// Unknown levels are counted as errors.
+fn count_findings(results: &CheckResults) -> (u64, u64) {
+    let warnings = results
+        .0
+        .iter()
+        .filter(|(level, _)| format!("{level:?}") == "Warn")
+        .count();
+    let errors = results.0.len() - warnings;
+    (errors as u64, warnings as u64)
+}
@@ -4,6 +4,7 @@ use crate::{
     Application, RUSTIC_APP, RusticConfig,
     commands::init::init_credentials,
     helpers::table_with_titles,
+    metrics::{CommandMetrics, MetricValue::Int},
     repository::{AllRepositoryOptions, IndexedRepo, Repo, get_snapots_from_ids},
     status_err,
 };
@@ -13,7 +14,7 @@ use conflate::Merge;
 use log::{Level, error, info, log};
 use serde::{Deserialize, Serialize};
 
-use rustic_core::{CopySnapshot, Id, KeyOptions, repofile::SnapshotFile};
+use rustic_core::{CopySnapshot, FileType, Id, KeyOptions, Repository, repofile::SnapshotFile};
 
 /// `copy` subcommand
 #[derive(clap::Parser, Command, Default, Clone, Debug, Serialize, Deserialize, Merge)]
@@ -72,7 +73,13 @@ impl Runnable for CopyCmd {
             );
             RUSTIC_APP.shutdown(Shutdown::Crash);
         }
-        if let Err(err) = config.repository.run_indexed(|repo| self.inner_run(repo)) {
+        let mut metrics = CommandMetrics::new("copy");
+        let res = config
+            .repository
+            .run_indexed(|repo| self.inner_run(repo, &mut metrics));
+        // copying failed if the command or any target failed
+        metrics.publish(matches!(res, Ok(0)));
+        if let Err(err) = res {
             status_err!("{}", err);
             RUSTIC_APP.shutdown(Shutdown::Crash);
         };
@@ -80,13 +87,15 @@ impl Runnable for CopyCmd {
 }
 
 impl CopyCmd {
-    fn inner_run(&self, repo: IndexedRepo) -> Result<()> {
+    /// Copy to all targets; returns the number of targets which failed
+    fn inner_run(&self, repo: IndexedRepo, metrics: &mut CommandMetrics) -> Result<u64> {
         let config = RUSTIC_APP.config();
         let config = config;
         let mut snapshots = get_snapots_from_ids(&repo, &self.ids)?;
         // sort for nicer output
         snapshots.sort_unstable();
 
+        let (mut copied, mut bytes, mut failed) = (0, 0, 0);
         for target in &config.copy.targets {
             let mut merge_logs = Vec::new();
             let mut target_config = RusticConfig::default();
@@ -96,22 +105,45 @@ impl CopyCmd {
                 log!(level, "{merge_log}");
             }
             let target_opt = &target_config.repository;
-            if let Err(err) =
-                target_opt.run(|target_repo| self.copy(&repo, target_repo, target_opt, &snapshots))
+            match target_opt
+                .run(|target_repo| self.copy(&repo, target_repo, target_opt, &snapshots))
             {
-                error!("error copying to target: {err}");
+                Ok((target_copied, target_bytes)) => {
+                    copied += target_copied;
+                    bytes += target_bytes;
+                }
+                Err(err) => {
+                    error!("error copying to target: {err}");
+                    failed += 1;
+                }
             }
         }
-        Ok(())
+        metrics.add(
+            "snapshots_copied",
+            "Number of snapshots copied to all targets",
+            Int(copied),
+        );
+        metrics.add(
+            "bytes_transferred",
+            "Total size of pack files written to all targets",
+            Int(bytes),
+        );
+        metrics.add(
+            "targets_failed",
+            "Number of targets where copying failed",
+            Int(failed),
+        );
+        Ok(failed)
     }
 
+    /// Copy to a target; returns the number of copied snapshots and the size of the written pack files
     fn copy(
         &self,
         repo: &IndexedRepo,
         target_repo: Repo,
         target_opt: &AllRepositoryOptions,
         snapshots: &[SnapshotFile],
-    ) -> Result<()> {
+    ) -> Result<(u64, u64)> {
         let config = RUSTIC_APP.config();
 
         info!("copying to target {}...", target_repo.name);
@@ -139,6 +171,8 @@ impl CopyCmd {
                 .map(|sn| CopySnapshot { sn, relevant: true })
                 .collect()
         } else {
+            // the snapshots to copy are already selected using all filters; copies of them in the
+            // target have the same contents, so content-aware filters need not be applied there
             target_repo.relevant_copy_snapshots(
                 |sn| !self.ids.is_empty() || config.snapshot_filter.matches(sn),
                 snapshots,
@@ -168,16 +202,41 @@ impl CopyCmd {
             if config.global.dry_run {
                 info!("would have copied {count} snapshots.");
             } else {
+                // only list the target files if the size is needed for the metrics
+                let with_size = config.global.is_metrics_configured();
+                let size_before = if with_size {
+                    packs_size(&target_repo)?
+                } else {
+                    0
+                };
+                let target_repo = target_repo.to_indexed_ids()?;
                 repo.copy(
-                    &target_repo.to_indexed_ids()?,
+                    &target_repo,
                     snaps
                         .iter()
                         .filter_map(|CopySnapshot { relevant, sn }| relevant.then_some(sn)),
                 )?;
+                let bytes = if with_size {
+                    packs_size(&target_repo)?.saturating_sub(size_before)
+                } else {
+                    0
+                };
+                return Ok((count as u64, bytes));
             }
         } else {
             info!("nothing to copy.");
         }
-        Ok(())
+        Ok((0, 0))
     }
 }
+
+/// Total size of all pack files in the repository
+fn packs_size<S>(repo: &Repository<S>) -> Result<u64> {
+    Ok(repo
+        .infos_files()?
+        .repo
+        .iter()
+        .filter(|info| info.tpe == FileType::Pack)
+        .map(|info| info.size)
+        .sum())
+}
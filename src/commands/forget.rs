@@ -1,7 +1,12 @@
 //! `forget` subcommand
 
 use crate::repository::{OpenRepo, get_grouped_snapshots};
-use crate::{Application, RUSTIC_APP, RusticConfig, helpers::table_with_titles, status_err};
+use crate::{
+    Application, RUSTIC_APP, RusticConfig,
+    helpers::table_with_titles,
+    metrics::{CommandMetrics, MetricValue::Int},
+    status_err,
+};
 
 use abscissa_core::{Command, FrameworkError, Runnable};
 use abscissa_core::{Shutdown, config::Override};
@@ -9,11 +14,13 @@ use anyhow::Result;
 use conflate::Merge;
 use jiff::Zoned;
 use log::info;
-use rustic_core::repofile::RusticTime;
 use serde::{Deserialize, Serialize};
 use serde_with::{DisplayFromStr, serde_as};
 
-use crate::{commands::prune::PruneCmd, filtering::SnapshotFilter};
+use crate::{
+    commands::prune::PruneCmd,
+    filtering::{SnapshotFilter, parse_time_system},
+};
 
 use rustic_core::{ForgetGroups, ForgetSnapshot, KeepOptions, SnapshotGroupCriterion};
 
@@ -26,8 +33,8 @@ pub(super) struct ForgetCmd {
     #[clap(value_name = "ID")]
     ids: Vec<String>,
 
-    /// Set the date/time (e.g. "2021-01-21) to use when evaluating retention rules; can be used to test the rules (default: now)
-    #[clap(long,value_parser = RusticTime::parse_system)]
+    /// Set the date/time (e.g. "2021-01-21" or "2 days ago") to use when evaluating retention rules; can be used to test the rules (default: now)
+    #[clap(long, value_parser = parse_time_system)]
     pub forget_time: Option<Zoned>,
 
     /// Show infos in json format
@@ -90,11 +97,13 @@ pub struct ForgetOptions {
 
 impl Runnable for ForgetCmd {
     fn run(&self) {
-        if let Err(err) = RUSTIC_APP
-            .config()
+        let config = RUSTIC_APP.config();
+        let mut metrics = CommandMetrics::new("forget");
+        let res = config
             .repository
-            .run_open(|repo| self.inner_run(repo))
-        {
+            .run_open(|repo| self.inner_run(repo, &mut metrics));
+        metrics.publish(res.is_ok());
+        if let Err(err) = res {
             status_err!("{}", err);
             RUSTIC_APP.shutdown(Shutdown::Crash);
         };
@@ -105,7 +114,7 @@ impl ForgetCmd {
     /// be careful about self vs `RUSTIC_APP.config()` usage
     /// only the `RUSTIC_APP.config()` involves the TOML and ENV merged configurations
     /// see <https://github.com/rustic-rs/rustic/issues/1242>
-    fn inner_run(&self, repo: OpenRepo) -> Result<()> {
+    fn inner_run(&self, repo: OpenRepo, metrics: &mut CommandMetrics) -> Result<()> {
         let config = RUSTIC_APP.config();
 
         let group_by = config
@@ -139,6 +148,12 @@ impl ForgetCmd {
             print_groups(&groups);
         }
 
+        let kept = groups
+            .0
+            .iter()
+            .flat_map(|group| &group.items)
+            .filter(|snap| snap.keep)
+            .count();
         let forget_snaps = groups.into_forget_ids();
 
         match (forget_snaps.is_empty(), config.global.dry_run, self.json) {
@@ -151,6 +166,21 @@ impl ForgetCmd {
             }
             (_, _, true) => {}
         }
+        let removed = if config.global.dry_run {
+            0
+        } else {
+            forget_snaps.len()
+        };
+        metrics.add(
+            "snapshots_kept",
+            "Number of snapshots kept",
+            Int(kept as u64),
+        );
+        metrics.add(
+            "snapshots_removed",
+            "Number of snapshots removed",
+            Int(removed as u64),
+        );
 
         if config.forget.prune {
             let mut prune_opts = self.prune_opts.clone();
@@ -1,12 +1,22 @@
 //! `init` subcommand
 
-use abscissa_core::{Command, Runnable, Shutdown, status_err};
-use anyhow::{Result, bail};
+mod wizard;
+
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use abscissa_core::{Command, Runnable, Shutdown, config::Config, status_err};
+use anyhow::{Result, anyhow, bail};
 use dialoguer::Password;
+use log::{info, warn};
 
 use crate::{
     Application, RUSTIC_APP,
-    repository::{OpenRepo, Repo},
+    config::{RusticConfig, user_profile_path},
+    repository::{AllRepositoryOptions, OpenRepo, Repo},
 };
 
 use rustic_core::{ConfigOptions, CredentialOptions, Credentials, KeyOptions};
@@ -18,6 +28,14 @@ pub(crate) struct InitCmd {
     #[clap(long)]
     hot_only: bool,
 
+    /// Write the repository options into a new profile with the given name in the user config dir
+    #[clap(long, value_name = "NAME")]
+    write_profile: Option<String>,
+
+    /// Interactively create a profile (repository, credentials, sources, excludes and retention) and initialize its repository
+    #[clap(long, conflicts_with = "hot_only")]
+    wizard: bool,
+
     /// Key options
     #[clap(flatten, next_help_heading = "Key options")]
     key_opts: KeyOptions,
@@ -29,11 +47,15 @@ pub(crate) struct InitCmd {
 
 impl Runnable for InitCmd {
     fn run(&self) {
-        if let Err(err) = RUSTIC_APP
-            .config()
-            .repository
-            .run(|repo| self.inner_run(repo))
-        {
+        let res = if self.wizard {
+            wizard::run(self)
+        } else {
+            RUSTIC_APP
+                .config()
+                .repository
+                .run(|repo| self.inner_run(repo))
+        };
+        if let Err(err) = res {
             status_err!("{}", err);
             RUSTIC_APP.shutdown(Shutdown::Crash);
         };
@@ -73,16 +95,92 @@ impl InitCmd {
             bail!("Config file already exists. Aborting.");
         }
 
+        // check the profile before initializing so that we don't fail after the init
+        let profile = self
+            .write_profile
+            .as_deref()
+            .map(|name| -> Result<_> {
+                let path = new_profile_path(name)?;
+                if path.exists() {
+                    bail!("profile {} already exists. Aborting.", path.display());
+                }
+                Ok((path, repository_table(&config.repository)?))
+            })
+            .transpose()?;
+
         let _ = init(
             repo,
             &config.repository.credential_opts,
             &self.key_opts,
             &self.config_opts,
         )?;
+
+        if let Some((path, repository)) = profile {
+            let mut profile = toml::Table::new();
+            _ = profile.insert("repository".to_string(), repository.into());
+            write_profile(&path, &profile_contents(&profile)?)?;
+        }
         Ok(())
     }
 }
 
+/// Get the path of a new profile within the user config dir
+fn new_profile_path(name: &str) -> Result<PathBuf> {
+    user_profile_path(name).ok_or_else(|| anyhow!("cannot determine the user config dir"))
+}
+
+/// Get the repository options as toml table, only containing the values which are not the default
+///
+/// A password is never written, use password-file or password-command instead.
+fn repository_table(opts: &AllRepositoryOptions) -> Result<toml::Table> {
+    let default = toml::Table::try_from(AllRepositoryOptions::default())?;
+    let mut table = toml::Table::try_from(opts)?;
+    table.retain(|key, value| default.get(key) != Some(value));
+    if table.remove("password").is_some() {
+        warn!(
+            "not writing the password into the profile, please use password-file or password-command."
+        );
+    }
+    Ok(table)
+}
+
+/// Get the contents of a new profile and check that it is a valid config
+fn profile_contents(profile: &toml::Table) -> Result<String> {
+    let contents = format!(
+        "# rustic config file generated by `rustic init`\n\n{}",
+        toml::to_string(profile)?
+    );
+    _ = RusticConfig::load_toml(&contents)?;
+    Ok(contents)
+}
+
+/// Write a new profile; it is only readable by the user as it may contain backend credentials
+fn write_profile(path: &Path, contents: &str) -> Result<()> {
+    create_private_file(path)?.write_all(contents.as_bytes())?;
+    info!("profile written to {}.", path.display());
+    Ok(())
+}
+
+/// Create or truncate a file which is only readable by the user; missing parent dirs are created
+fn create_private_file(path: &Path) -> Result<fs::File> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut options = fs::OpenOptions::new();
+    _ = options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        _ = options.mode(0o600);
+        let file = options.open(path)?;
+        // the mode is only used for new files
+        file.set_permissions(fs::Permissions::from_mode(0o600))?;
+        Ok(file)
+    }
+    #[cfg(not(unix))]
+    Ok(options.open(path)?)
+}
+
 /// Initialize repository
 ///
 /// # Arguments
@@ -127,3 +225,66 @@ pub(crate) fn init_credentials(credential_opts: &CredentialOptions) -> Result<Cr
 
     Ok(credentials)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profile_contents_are_valid_config() -> Result<()> {
+        let profile: toml::Table = toml::from_str(
+            r#"
+            [repository]
+            repository = "/srv/backup"
+            password-file = "/srv/backup.pass"
+
+            [forget]
+            keep-daily = 7
+            "#,
+        )?;
+        let contents = profile_contents(&profile)?;
+        assert!(contents.starts_with("# rustic config file generated by `rustic init`\n"));
+        let config = RusticConfig::load_toml(&contents)?;
+        assert_eq!(
+            config.repository.be.repository.as_deref(),
+            Some("/srv/backup")
+        );
+        assert_eq!(toml::from_str::<toml::Table>(&contents)?, profile);
+        Ok(())
+    }
+
+    #[test]
+    fn profile_contents_rejects_invalid_config() {
+        let mut profile = toml::Table::new();
+        _ = profile.insert("no-such-section".to_string(), toml::Table::new().into());
+        assert!(profile_contents(&profile).is_err());
+    }
+
+    #[test]
+    fn repository_table_omits_password_and_defaults() -> Result<()> {
+        let mut opts = AllRepositoryOptions::default();
+        opts.be.repository = Some("/srv/backup".to_string());
+        opts.credential_opts.password = Some("secret".to_string());
+        let table = repository_table(&opts)?;
+        assert_eq!(table.len(), 1);
+        assert_eq!(table["repository"].as_str(), Some("/srv/backup"));
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn profile_is_only_readable_by_the_user() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("config").join("profile.toml");
+        write_profile(&path, "[repository]\n")?;
+        assert_eq!(fs::metadata(&path)?.permissions().mode() & 0o777, 0o600);
+        // overwriting an existing profile also restricts its permissions
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644))?;
+        write_profile(&path, "[repository]\n")?;
+        assert_eq!(fs::metadata(&path)?.permissions().mode() & 0o777, 0o600);
+        assert_eq!(fs::read_to_string(&path)?, "[repository]\n");
+        Ok(())
+    }
+}
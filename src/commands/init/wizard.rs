@@ -0,0 +1,238 @@
+//! Interactive wizard to create a profile and initialize its repository
+
+use std::{io::Write, path::Path, process};
+
+use abscissa_core::config::Config;
+use anyhow::{Result, bail};
+use dialoguer::{Confirm, Input, Password, Select};
+use log::info;
+use toml::{Table, Value};
+
+use super::{
+    InitCmd, create_private_file, init, new_profile_path, profile_contents, write_profile,
+};
+use crate::{Application, RUSTIC_APP, config::RusticConfig};
+
+/// Ways to provide the repository password
+const CREDENTIALS: [&str; 3] = [
+    "enter the password on each run",
+    "read the password from a file",
+    "get the password from a command",
+];
+
+/// Retention options and their suggested defaults; 0 means not set
+const RETENTION: [(&str, i32); 4] = [
+    ("keep-daily", 7),
+    ("keep-weekly", 4),
+    ("keep-monthly", 12),
+    ("keep-yearly", 0),
+];
+
+/// Run the wizard: ask for all settings, initialize the repository, write the profile and optionally run a first backup
+pub(super) fn run(cmd: &InitCmd) -> Result<()> {
+    let config = RUSTIC_APP.config();
+
+    let name = match &cmd.write_profile {
+        Some(name) => name.clone(),
+        None => Input::new()
+            .with_prompt("profile name")
+            .default("rustic".to_string())
+            .interact_text()?,
+    };
+    let path = new_profile_path(&name)?;
+    if path.exists()
+        && !Confirm::new()
+            .with_prompt(format!(
+                "profile {} already exists. Overwrite?",
+                path.display()
+            ))
+            .default(false)
+            .interact()?
+    {
+        bail!("Aborting.");
+    }
+
+    let repository = ask_repository(
+        config
+            .repository
+            .be
+            .repository
+            .as_deref()
+            .unwrap_or_default(),
+        &path,
+    )?;
+    let sources = ask_list("backup sources (comma-separated, leave empty to not define any)")?;
+    let excludes = ask_list("exclude globs (comma-separated, e.g. *.tmp,/home/*/.cache)")?;
+    let forget = ask_retention()?;
+
+    let mut profile = Table::new();
+    _ = profile.insert("repository".to_string(), repository.into());
+    if !sources.is_empty() || !excludes.is_empty() {
+        let mut backup = Table::new();
+        if !excludes.is_empty() {
+            let globs = excludes
+                .into_iter()
+                .map(|glob| {
+                    if glob.starts_with('!') {
+                        glob
+                    } else {
+                        format!("!{glob}")
+                    }
+                })
+                .collect::<Vec<_>>();
+            _ = backup.insert("globs".to_string(), globs.into());
+        }
+        if !sources.is_empty() {
+            let mut snapshot = Table::new();
+            _ = snapshot.insert("sources".to_string(), sources.clone().into());
+            _ = backup.insert("snapshots".to_string(), vec![Value::from(snapshot)].into());
+        }
+        _ = profile.insert("backup".to_string(), backup.into());
+    }
+    if !forget.is_empty() {
+        _ = profile.insert("forget".to_string(), forget.into());
+    }
+    let contents = profile_contents(&profile)?;
+
+    if config.global.dry_run {
+        println!("would have written the profile {}:\n", path.display());
+        println!("{contents}");
+        return Ok(());
+    }
+
+    let repo_opts = RusticConfig::load_toml(&contents)?.repository;
+    repo_opts.run(|repo| {
+        if repo.config_id()?.is_some() {
+            if !Confirm::new()
+                .with_prompt(format!(
+                    "repository {} is already initialized. Use it for the profile?",
+                    repo.name
+                ))
+                .default(true)
+                .interact()?
+            {
+                bail!("Aborting.");
+            }
+            // check that the credentials are correct
+            _ = repo.open(&repo_opts.credential_opts)?;
+        } else {
+            _ = init(
+                repo,
+                &repo_opts.credential_opts,
+                &cmd.key_opts,
+                &cmd.config_opts,
+            )?;
+        }
+        Ok(())
+    })?;
+    write_profile(&path, &contents)?;
+
+    if !sources.is_empty()
+        && Confirm::new()
+            .with_prompt("run a first backup now?")
+            .default(true)
+            .interact()?
+    {
+        let status = process::Command::new(std::env::current_exe()?)
+            .args(["-P", &name, "backup"])
+            .status()?;
+        if !status.success() {
+            bail!("first backup failed: {status}");
+        }
+    } else {
+        info!("run `rustic -P {name} backup` to start backing up.");
+    }
+    Ok(())
+}
+
+/// Ask for the repository location, backend options and how to store the credentials
+fn ask_repository(default: &str, profile_path: &Path) -> Result<Table> {
+    let mut repository = Table::new();
+    let location: String = Input::new()
+        .with_prompt("repository (e.g. /srv/backup, rclone:remote:path or opendal:s3)")
+        .with_initial_text(default)
+        .interact_text()?;
+    _ = repository.insert("repository".to_string(), location.into());
+
+    let mut options = Table::new();
+    for option in ask_list("backend options (comma-separated KEY=VALUE, leave empty for none)")? {
+        let Some((key, value)) = option.split_once('=') else {
+            bail!("backend option {option} is not of the form KEY=VALUE");
+        };
+        _ = options.insert(key.trim().to_string(), value.trim().into());
+    }
+    if !options.is_empty() {
+        _ = repository.insert("options".to_string(), options.into());
+    }
+
+    match Select::new()
+        .with_prompt("how to provide the repository password")
+        .items(CREDENTIALS)
+        .default(0)
+        .interact()?
+    {
+        1 => {
+            let file: String = Input::new()
+                .with_prompt("password file")
+                .default(profile_path.with_extension("pass").display().to_string())
+                .interact_text()?;
+            if !Path::new(&file).exists() {
+                create_password_file(Path::new(&file))?;
+            }
+            _ = repository.insert("password-file".to_string(), file.into());
+        }
+        2 => {
+            let command: String = Input::new()
+                .with_prompt("password command")
+                .interact_text()?;
+            _ = repository.insert("password-command".to_string(), command.into());
+        }
+        _ => {}
+    }
+    Ok(repository)
+}
+
+/// Ask for a comma-separated list
+fn ask_list(prompt: &str) -> Result<Vec<String>> {
+    let input: String = Input::new()
+        .with_prompt(prompt)
+        .allow_empty(true)
+        .interact_text()?;
+    Ok(input
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(ToString::to_string)
+        .collect())
+}
+
+/// Ask for the retention options used by `forget`
+fn ask_retention() -> Result<Table> {
+    let mut forget = Table::new();
+    for (option, default) in RETENTION {
+        let value: i32 = Input::new()
+            .with_prompt(format!("{option} (0: not set, -1: keep all)"))
+            .default(default)
+            .interact_text()?;
+        if value != 0 {
+            _ = forget.insert(option.to_string(), i64::from(value).into());
+        }
+    }
+    Ok(forget)
+}
+
+fn ask_password() -> Result<String> {
+    Ok(Password::new()
+        .with_prompt("enter password for new key")
+        .allow_empty_password(true)
+        .with_confirmation("confirm password", "passwords do not match")
+        .interact()?)
+}
+
+/// Create a password file which is only readable by the user
+fn create_password_file(path: &Path) -> Result<()> {
+    let pass = ask_password()?;
+    writeln!(create_private_file(path)?, "{pass}")?;
+    info!("password written to {}.", path.display());
+    Ok(())
+}
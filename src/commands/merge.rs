@@ -1,23 +1,40 @@
 //! `merge` subcommand
 
-use crate::commands::program_version;
+use std::{
+    cmp::Ordering,
+    collections::BTreeMap,
+    ffi::OsString,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::commands::{program_version, rewrite::relative};
 use crate::{
     Application, RUSTIC_APP,
-    repository::{OpenRepo, get_snapots_from_ids},
+    helpers::table_with_titles,
+    metrics::{CommandMetrics, MetricValue::Int},
+    repository::{
+        IndexedIdsRepo, OpenRepo, get_snapots_from_ids, snapshot_source::SnapshotPathSource,
+    },
     status_err,
 };
 use abscissa_core::{Command, Runnable, Shutdown};
-use anyhow::Result;
+use anyhow::{Result, anyhow, bail};
 use jiff::Zoned;
-use log::info;
+use log::{info, warn};
 
-use rustic_core::{SnapshotOptions, last_modified_node, repofile::SnapshotFile};
+use rustic_core::{
+    BackupOptions, SnapshotGroupCriterion, SnapshotOptions, StringList, TreeId,
+    repofile::{Node, SnapshotFile},
+};
 
 /// `merge` subcommand
 #[derive(clap::Parser, Default, Command, Debug)]
 pub(super) struct MergeCmd {
     /// Snapshots to merge. If none is given, use filter options to filter from all snapshots.
-    #[clap(value_name = "ID")]
+    ///
+    /// Use ID:PATH to only take PATH from the snapshot ID, e.g. `latest:/home/user`
+    #[clap(value_name = "ID[:PATH]")]
     ids: Vec<String>,
 
     /// Output generated snapshot in json format
@@ -28,18 +45,138 @@ pub(super) struct MergeCmd {
     #[clap(long)]
     delete: bool,
 
+    /// Strategy to resolve conflicts, i.e. paths existing with different contents in several inputs
+    #[clap(long, value_enum, default_value_t)]
+    strategy: MergeStrategy,
+
     /// Snapshot options
     #[clap(flatten, next_help_heading = "Snapshot options")]
     snap_opts: SnapshotOptions,
 }
 
+/// Strategy to resolve merge conflicts
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+enum MergeStrategy {
+    /// Use the entry with the newest modification time
+    #[default]
+    NewestMtime,
+    /// Use the entry of the first input
+    First,
+    /// Use the entry of the last input
+    Last,
+    /// Use the largest entry
+    Largest,
+    /// Abort the merge if there is any conflict
+    Fail,
+}
+
+impl MergeStrategy {
+    /// Compare two candidates; the greater one wins. `rank` is the number of the input a candidate
+    /// is taken from, ties are resolved in favor of the first input.
+    fn cmp(self, (rank1, node1): (usize, &Node), (rank2, node2): (usize, &Node)) -> Ordering {
+        let by_rank = rank2.cmp(&rank1);
+        match self {
+            Self::NewestMtime => node1.meta.mtime.cmp(&node2.meta.mtime).then(by_rank),
+            Self::Largest => node1.meta.size.cmp(&node2.meta.size).then(by_rank),
+            Self::First | Self::Fail => by_rank,
+            Self::Last => by_rank.reverse(),
+        }
+    }
+
+    /// Choose the winning candidate
+    fn resolve(self, candidates: &[(usize, Node)]) -> usize {
+        candidates
+            .iter()
+            .enumerate()
+            .max_by(|(_, (rank1, node1)), (_, (rank2, node2))| {
+                self.cmp((*rank1, node1), (*rank2, node2))
+            })
+            .map_or(0, |(i, _)| i)
+    }
+}
+
+/// The differing nodes found at a path together with the number of the input they are taken from
+type Candidates = Vec<(usize, Node)>;
+
+/// An input of the merge: a snapshot, optionally restricted to a path
+struct MergeInput {
+    snap: SnapshotFile,
+    path: Option<PathBuf>,
+}
+
+impl MergeInput {
+    fn name(&self) -> String {
+        self.path.as_ref().map_or_else(
+            || self.snap.id.to_string(),
+            |path| format!("{}:/{}", self.snap.id, path.display()),
+        )
+    }
+
+    /// Check if the entry at `path` belongs to this input, i.e. is the selected path, one of its
+    /// parent dirs or contained in it
+    fn contains(&self, path: &Path) -> bool {
+        self.path
+            .as_ref()
+            .is_none_or(|selected| selected.starts_with(path) || path.starts_with(selected))
+    }
+
+    /// The snapshot to merge: for a selected path, the tree only contains this path and its parent dirs
+    ///
+    /// The tree is saved by the archiver using the snapshot as parent, so no file contents are
+    /// read. Returns the snapshot saved by the archiver, if any.
+    fn snapshot(&self, repo: &Arc<IndexedIdsRepo>) -> Result<(SnapshotFile, Option<SnapshotFile>)> {
+        let mut snap = self.snap.clone();
+        let Some(path) = &self.path else {
+            return Ok((snap, None));
+        };
+        let mut opts = BackupOptions::default();
+        opts.parent_opts.group_by = Some(SnapshotGroupCriterion::new());
+        opts.parent_opts.parents = vec![snap.id.to_string()];
+        let archived = SnapshotFile {
+            label: "merge".to_string(),
+            ..Default::default()
+        };
+        let src = SnapshotPathSource::new(repo.clone(), snap.tree, path.clone());
+        let archived = repo.archive(&opts, &src, archived, &[PathBuf::from("/")])?;
+        snap.tree = archived.tree;
+        snap.paths = StringList::default();
+        snap.paths
+            .add(Path::new("/").join(path).display().to_string());
+        Ok((snap, Some(archived)))
+    }
+
+    /// Check that the selected path of the snapshot `snap` to merge is identical to the original one
+    fn verify(&self, repo: &IndexedIdsRepo, snap: &SnapshotFile) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let (original, saved) = (
+            repo.node_from_path(self.snap.tree, path)?,
+            repo.node_from_path(snap.tree, path)?,
+        );
+        if original.subtree != saved.subtree || original.content != saved.content {
+            bail!("could not save /{} of {}.", path.display(), self.snap.id);
+        }
+        Ok(())
+    }
+}
+
+/// A path which exists with different contents in several inputs
+struct Conflict {
+    path: PathBuf,
+    candidates: Candidates,
+    winner: usize,
+}
+
 impl Runnable for MergeCmd {
     fn run(&self) {
-        if let Err(err) = RUSTIC_APP
+        let mut metrics = CommandMetrics::new("merge");
+        let res = RUSTIC_APP
             .config()
             .repository
-            .run_open(|repo| self.inner_run(repo))
-        {
+            .run_open(|repo| self.inner_run(repo, &mut metrics));
+        metrics.publish(res.is_ok());
+        if let Err(err) = res {
             status_err!("{}", err);
             RUSTIC_APP.shutdown(Shutdown::Crash);
         };
@@ -47,21 +184,65 @@ impl Runnable for MergeCmd {
 }
 
 impl MergeCmd {
-    fn inner_run(&self, repo: OpenRepo) -> Result<()> {
+    fn inner_run(&self, repo: OpenRepo, metrics: &mut CommandMetrics) -> Result<()> {
         let config = RUSTIC_APP.config();
         let repo = repo.to_indexed_ids()?;
 
-        let snapshots = get_snapots_from_ids(&repo, &self.ids)?;
+        let inputs = self.inputs(&repo)?;
+        if inputs.is_empty() {
+            info!("no snapshots to merge.");
+            return Ok(());
+        }
+        let mut conflicts = Vec::new();
+        let trees = (0..inputs.len())
+            .map(|num| (num, inputs[num].snap.tree))
+            .collect();
+        self.conflicts(&repo, &inputs, PathBuf::new(), trees, &mut conflicts)?;
+        metrics.add(
+            "conflicts",
+            "Number of conflicting paths",
+            Int(conflicts.len() as u64),
+        );
+        if !conflicts.is_empty() {
+            self.report(&inputs, &conflicts);
+            if self.strategy == MergeStrategy::Fail {
+                bail!("{} conflicting paths found, aborting.", conflicts.len());
+            }
+        }
 
         // Handle dry-run mode
         if config.global.dry_run {
+            let snapshots: Vec<_> = inputs.iter().map(|input| &input.snap).collect();
             println!("would have modified the following snapshots:\n {snapshots:?}");
             return Ok(());
         }
 
+        // the conflicting nodes by name together with their input number; nodes of other paths
+        // are identical or dirs, so it doesn't matter which one is used
+        let mut ranks: BTreeMap<OsString, Candidates> = BTreeMap::new();
+        for conflict in conflicts {
+            if let Some(name) = conflict.path.file_name() {
+                ranks
+                    .entry(name.to_os_string())
+                    .or_default()
+                    .extend(conflict.candidates);
+            }
+        }
+        let rank = |node: &Node| {
+            ranks
+                .get(&node.name().to_os_string())
+                .and_then(|candidates| candidates.iter().find(|(_, n)| n == node))
+                .map_or(0, |(rank, _)| *rank)
+        };
+        let cmp = |node1: &Node, node2: &Node| {
+            self.strategy
+                .cmp((rank(node1), node1), (rank(node2), node2))
+        };
+
+        let (repo, snaps) = merge_snapshots(repo, &inputs)?;
         let mut snap = SnapshotFile::from_options(&self.snap_opts)?;
         snap.program_version = program_version();
-        let snap = repo.merge_snapshots(&snapshots, &last_modified_node, snap)?;
+        let snap = repo.merge_snapshots(&snaps, &cmp, snap)?;
 
         if self.json {
             let mut stdout = std::io::stdout();
@@ -72,14 +253,223 @@ impl MergeCmd {
         if self.delete {
             let now = Zoned::now();
             // TODO: Maybe use this check in repo.delete_snapshots?
-            let snap_ids: Vec<_> = snapshots
+            let mut snap_ids: Vec<_> = inputs
                 .iter()
-                .filter(|sn| !sn.must_keep(&now))
-                .map(|sn| sn.id)
+                .filter(|input| !input.snap.must_keep(&now))
+                .map(|input| input.snap.id)
                 .collect();
+            snap_ids.sort_unstable();
+            snap_ids.dedup();
             repo.delete_snapshots(&snap_ids)?;
         }
 
         Ok(())
     }
+
+    /// Get the snapshots to merge together with the selected paths
+    fn inputs(&self, repo: &IndexedIdsRepo) -> Result<Vec<MergeInput>> {
+        if self.ids.is_empty() {
+            return Ok(get_snapots_from_ids(repo, &[])?
+                .into_iter()
+                .map(|snap| MergeInput { snap, path: None })
+                .collect());
+        }
+        let mut inputs = Vec::new();
+        for id in &self.ids {
+            let (id, path) = id
+                .split_once(':')
+                .map_or((id.as_str(), None), |(id, path)| {
+                    (id, Some(relative(Path::new(path))))
+                });
+            let snaps = get_snapots_from_ids(repo, &[id.to_string()])?;
+            if snaps.is_empty() {
+                warn!("snapshot {id} does not match the filter, ignoring.");
+            }
+            inputs.extend(snaps.into_iter().map(|snap| MergeInput {
+                snap,
+                path: path.clone().filter(|p| !p.as_os_str().is_empty()),
+            }));
+        }
+        Ok(inputs)
+    }
+
+    /// Find all paths with conflicting candidates within the dir `path` and which input wins using
+    /// the merge strategy
+    ///
+    /// `trees` contains the trees of the dir together with the input number. The trees are visited
+    /// dir by dir, only dirs which are used in the merged snapshot are visited.
+    fn conflicts(
+        &self,
+        repo: &IndexedIdsRepo,
+        inputs: &[MergeInput],
+        path: PathBuf,
+        trees: Vec<(usize, TreeId)>,
+        conflicts: &mut Vec<Conflict>,
+    ) -> Result<()> {
+        let mut entries: BTreeMap<OsString, Candidates> = BTreeMap::new();
+        for (num, tree) in trees {
+            for node in repo.get_tree(&tree)?.nodes {
+                if !inputs[num].contains(&path.join(node.name())) {
+                    continue;
+                }
+                let nodes = entries.entry(node.name().to_os_string()).or_default();
+                // an input may select overlapping paths of the same snapshot
+                if !nodes.iter().any(|(_, n)| n == &node) {
+                    nodes.push((num, node));
+                }
+            }
+        }
+
+        for (name, nodes) in entries {
+            let path = path.join(name);
+            let winner = self.strategy.resolve(&nodes);
+            // contents of dirs which lost against a non-dir are not visited
+            let subtrees: Vec<_> = nodes
+                .iter()
+                .filter(|(_, node)| node.is_dir() && nodes[winner].1.is_dir())
+                .filter_map(|(num, node)| Some((*num, node.subtree?)))
+                .collect();
+            let is_conflict = nodes.iter().any(|(_, node)| {
+                let other = &nodes[0].1;
+                !(node.is_dir() && other.is_dir())
+                    && (node.node_type != other.node_type || node.content != other.content)
+            });
+            if is_conflict {
+                conflicts.push(Conflict {
+                    path: path.clone(),
+                    winner: nodes[winner].0,
+                    candidates: nodes,
+                });
+            }
+            if !subtrees.is_empty() {
+                self.conflicts(repo, inputs, path, subtrees, conflicts)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Print all conflicting paths and which input won
+    fn report(&self, inputs: &[MergeInput], conflicts: &[Conflict]) {
+        let mut table = table_with_titles(["Path", "Used input", "Other inputs"]);
+        for conflict in conflicts {
+            let others: Vec<_> = conflict
+                .candidates
+                .iter()
+                .map(|(num, _)| *num)
+                .filter(|num| *num != conflict.winner)
+                .map(|num| inputs[num].name())
+                .collect();
+            _ = table.add_row([
+                format!("/{}", conflict.path.display()),
+                inputs[conflict.winner].name(),
+                others.join("\n"),
+            ]);
+        }
+        if self.json {
+            // keep stdout for the json output
+            eprintln!("{table}");
+        } else {
+            println!("{table}");
+        }
+        warn!(
+            "{} conflicting paths found, resolved using strategy {:?}.",
+            conflicts.len(),
+            self.strategy
+        );
+    }
+}
+
+/// Get the snapshots to merge; trees of inputs restricted to a path are saved first
+///
+/// Returns the repository with an index containing the saved trees.
+fn merge_snapshots(
+    repo: IndexedIdsRepo,
+    inputs: &[MergeInput],
+) -> Result<(IndexedIdsRepo, Vec<SnapshotFile>)> {
+    let repo = Arc::new(repo);
+    let mut snaps = Vec::new();
+    let mut archived = Vec::new();
+    let mut res = Ok(());
+    for input in inputs {
+        match input.snapshot(&repo) {
+            Ok((snap, saved)) => {
+                snaps.push(snap);
+                archived.extend(saved.map(|saved| saved.id));
+            }
+            Err(err) => {
+                res = Err(err);
+                break;
+            }
+        }
+    }
+    // only the trees saved by the archiver are needed
+    if !archived.is_empty()
+        && let Err(err) = repo.delete_snapshots(&archived)
+    {
+        warn!("could not remove the temporary snapshots {archived:?}: {err}");
+    }
+    res?;
+    let repo = Arc::into_inner(repo).ok_or_else(|| anyhow!("repository is still in use"))?;
+    if archived.is_empty() {
+        return Ok((repo, snaps));
+    }
+
+    // the saved trees must be indexed to be merged
+    let repo = repo.drop_index().to_indexed_ids()?;
+    for (input, snap) in inputs.iter().zip(&snaps) {
+        input.verify(&repo, snap)?;
+    }
+    Ok((repo, snaps))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use rustic_core::{
+        Id,
+        repofile::{Metadata, NodeType},
+    };
+
+    fn file(name: &str, mtime: i64, size: u64) -> Node {
+        let meta = Metadata {
+            mtime: Some(jiff::Timestamp::from_second(mtime).unwrap()),
+            size,
+            ..Default::default()
+        };
+        let mut node = Node::new_node(name.as_ref(), NodeType::File, meta);
+        // files with different metadata also get different contents
+        let content = [u8::try_from(mtime).unwrap() ^ u8::try_from(size).unwrap(); 32];
+        node.content = Some(vec![Id::new(content).into()]);
+        node
+    }
+
+    #[rstest]
+    #[case(MergeStrategy::NewestMtime, 1)]
+    #[case(MergeStrategy::Largest, 2)]
+    #[case(MergeStrategy::First, 0)]
+    #[case(MergeStrategy::Last, 3)]
+    fn resolve(#[case] strategy: MergeStrategy, #[case] expected: usize) {
+        let candidates = [
+            (0, file("file", 10, 5)),
+            (1, file("file", 30, 5)),
+            (2, file("file", 20, 8)),
+            (3, file("file", 30, 8)),
+        ];
+        assert_eq!(strategy.resolve(&candidates), expected);
+    }
+
+    #[rstest]
+    #[case(None, "a/b", true)]
+    #[case(Some("a/b"), "a", true)]
+    #[case(Some("a/b"), "a/b/c", true)]
+    #[case(Some("a/b"), "a/c", false)]
+    #[case(Some("a/b"), "a/bc", false)]
+    fn input_contains(#[case] selected: Option<&str>, #[case] path: &str, #[case] expected: bool) {
+        let input = MergeInput {
+            snap: SnapshotFile::default(),
+            path: selected.map(PathBuf::from),
+        };
+        assert_eq!(input.contains(Path::new(path)), expected);
+    }
 }
@@ -0,0 +1,216 @@
+//! `metrics` subcommand
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    net::ToSocketAddrs,
+    sync::Arc,
+    time::Instant,
+};
+
+use abscissa_core::{Command, Runnable, Shutdown};
+use anyhow::{Result, anyhow};
+use axum::{
+    Router,
+    extract::State,
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use jiff::Timestamp;
+use log::{info, warn};
+use prometheus::{Encoder, GaugeVec, Opts, Registry, TEXT_FORMAT, TextEncoder};
+
+use crate::{Application, RUSTIC_APP, metrics::timestamp_secs, repository::OpenRepo, status_err};
+
+/// `metrics` subcommand
+#[derive(clap::Parser, Command, Debug)]
+pub(crate) struct MetricsCmd {
+    /// Subcommand to run
+    #[clap(subcommand)]
+    cmd: MetricsSubCmd,
+}
+
+impl Runnable for MetricsCmd {
+    fn run(&self) {
+        self.cmd.run();
+    }
+}
+
+#[derive(clap::Subcommand, Debug, Runnable)]
+enum MetricsSubCmd {
+    /// Serve metrics about the repository state on a /metrics endpoint to be scraped by Prometheus
+    Serve(ServeCmd),
+}
+
+#[derive(clap::Parser, Debug)]
+pub(crate) struct ServeCmd {
+    /// Address to bind the metrics server to
+    #[clap(long, value_name = "ADDRESS", default_value = "localhost:9122")]
+    address: String,
+}
+
+impl Runnable for ServeCmd {
+    fn run(&self) {
+        if let Err(err) = RUSTIC_APP
+            .config()
+            .repository
+            .run_open(|repo| self.inner_run(repo))
+        {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl ServeCmd {
+    fn inner_run(&self, repo: OpenRepo) -> Result<()> {
+        let addr = self
+            .address
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| anyhow!("no address given"))?;
+
+        let app = Router::new()
+            .route("/metrics", get(handle_metrics))
+            .with_state(Arc::new(repo));
+
+        info!("serving metrics on http://{addr}/metrics");
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?
+            .block_on(async {
+                axum::serve(tokio::net::TcpListener::bind(addr).await?, app).await
+            })?;
+
+        Ok(())
+    }
+}
+
+async fn handle_metrics(State(repo): State<Arc<OpenRepo>>) -> Response {
+    // reading the repository blocks, so don't run it on the runtime thread serving the requests
+    let res = tokio::task::spawn_blocking(move || scrape(&repo))
+        .await
+        .unwrap_or_else(|err| Err(anyhow!("scraping task failed: {err}")));
+    match res {
+        Ok(body) => (StatusCode::OK, [(header::CONTENT_TYPE, TEXT_FORMAT)], body).into_response(),
+        Err(err) => {
+            warn!("error scraping metrics: {err}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("error scraping metrics: {err}\n"),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Snapshot statistics of a host/label group
+#[derive(Default)]
+struct GroupStats {
+    count: u64,
+    locked: u64,
+    oldest: Option<Timestamp>,
+    latest: Option<Timestamp>,
+}
+
+/// Read the current repository state and encode it as Prometheus metrics
+#[allow(clippy::cast_precision_loss)]
+fn scrape(repo: &OpenRepo) -> Result<String> {
+    let start = Instant::now();
+    let const_labels: HashMap<_, _> = RUSTIC_APP
+        .config()
+        .global
+        .metrics_labels
+        .iter()
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+
+    let registry = Registry::new();
+    let gauge = |name: &str, help: &str, label_names: &[&str]| -> Result<GaugeVec> {
+        let gauge = GaugeVec::new(
+            Opts::new(name, help).const_labels(const_labels.clone()),
+            label_names,
+        )?;
+        registry.register(Box::new(gauge.clone()))?;
+        Ok(gauge)
+    };
+
+    let now = Timestamp::now();
+    let zoned_now = now.to_zoned(jiff::tz::TimeZone::UTC);
+    let mut groups: BTreeMap<_, GroupStats> = BTreeMap::new();
+    for snap in repo.get_all_snapshots()? {
+        let stats = groups
+            .entry((snap.hostname.clone(), snap.label.clone()))
+            .or_default();
+        let time = snap.time.timestamp();
+        stats.count += 1;
+        if snap.must_keep(&zoned_now) {
+            stats.locked += 1;
+        }
+        stats.oldest = Some(stats.oldest.map_or(time, |oldest| oldest.min(time)));
+        stats.latest = Some(stats.latest.map_or(time, |latest| latest.max(time)));
+    }
+
+    let group = ["hostname", "label"];
+    let snapshots = gauge("rustic_repo_snapshots", "Number of snapshots", &group)?;
+    let locked = gauge(
+        "rustic_repo_snapshots_locked",
+        "Number of snapshots which are protected from being removed",
+        &group,
+    )?;
+    let oldest = gauge(
+        "rustic_repo_snapshot_oldest_time",
+        "Timestamp of the oldest snapshot",
+        &group,
+    )?;
+    let latest = gauge(
+        "rustic_repo_snapshot_latest_time",
+        "Timestamp of the latest snapshot",
+        &group,
+    )?;
+    let age = gauge(
+        "rustic_repo_snapshot_latest_age",
+        "Age of the latest snapshot in seconds",
+        &group,
+    )?;
+    for ((hostname, label), stats) in &groups {
+        let values = [hostname.as_str(), label.as_str()];
+        snapshots.with_label_values(&values).set(stats.count as f64);
+        locked.with_label_values(&values).set(stats.locked as f64);
+        if let (Some(first), Some(last)) = (stats.oldest, stats.latest) {
+            oldest.with_label_values(&values).set(timestamp_secs(first));
+            latest.with_label_values(&values).set(timestamp_secs(last));
+            age.with_label_values(&values)
+                .set(now.duration_since(last).as_secs_f64());
+        }
+    }
+
+    let files = gauge(
+        "rustic_repo_files",
+        "Number of repository files by type",
+        &["type"],
+    )?;
+    let size = gauge(
+        "rustic_repo_size",
+        "Total size of repository files in bytes by type",
+        &["type"],
+    )?;
+    for info in repo.infos_files()?.repo {
+        let tpe = [info.tpe.to_string().to_lowercase()];
+        files.with_label_values(&tpe).set(info.count as f64);
+        size.with_label_values(&tpe).set(info.size as f64);
+    }
+
+    gauge(
+        "rustic_repo_scrape_duration",
+        "Duration of reading the repository state in seconds",
+        &[],
+    )?
+    .with_label_values::<&str>(&[])
+    .set(start.elapsed().as_secs_f64());
+
+    let mut buf = Vec::new();
+    TextEncoder::new().encode(&registry.gather(), &mut buf)?;
+    Ok(String::from_utf8(buf)?)
+}
@@ -0,0 +1,434 @@
+//! `migrate` subcommand
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    Application, RUSTIC_APP, RusticConfig,
+    commands::init::init_credentials,
+    config::profile_path,
+    helpers::table_with_titles,
+    metrics::{CommandMetrics, MetricValue::Int},
+    repository::{
+        IndexedIdsRepo, IndexedRepo, get_snapots_from_ids, snapshot_source::SnapshotSource,
+    },
+    status_err,
+};
+use abscissa_core::{Command, Runnable, Shutdown};
+use anyhow::{Result, anyhow, bail};
+use log::{Level, info, log, warn};
+
+use rustic_core::{
+    BackupOptions, CheckOptions, ConfigOptions, CopySnapshot, Id, KeyOptions, LsOptions,
+    repofile::{ConfigFile, SnapshotFile},
+};
+
+/// `migrate` subcommand
+///
+/// Migrate all (or the given) snapshots into a new repository, e.g. using a different backend or
+/// different repository config options. If the chunker parameters change, all data is re-chunked.
+/// The original snapshot ids are kept in the `original` field of the migrated snapshots.
+#[derive(clap::Parser, Command, Debug)]
+pub(crate) struct MigrateCmd {
+    /// Snapshots to migrate. If none is given, use filter options to filter from all snapshots
+    ///
+    /// Snapshots can be identified the following ways: "01a2b3c4" or "latest" or "latest~N" (N >= 0)
+    #[clap(value_name = "ID")]
+    ids: Vec<String>,
+
+    /// Profile of the target repository
+    #[clap(long, value_name = "PROFILE")]
+    to: String,
+
+    /// Use a new random chunker polynomial for the target repository (requires re-chunking all data)
+    #[clap(long)]
+    new_chunker_polynomial: bool,
+
+    /// After a successful migration, let the current profile use the target repository.
+    /// The original profile is kept as `<PROFILE>.toml.pre-migrate`
+    #[clap(long)]
+    switch_profile: bool,
+
+    /// Key options (when initializing the target repository)
+    #[clap(flatten, next_help_heading = "Key options")]
+    key_opts: KeyOptions,
+
+    /// Config options for the target repository
+    #[clap(flatten, next_help_heading = "Config options")]
+    config_opts: ConfigOptions,
+}
+
+impl Runnable for MigrateCmd {
+    fn run(&self) {
+        let config = RUSTIC_APP.config();
+        let mut metrics = CommandMetrics::new("migrate");
+        let res = config
+            .repository
+            .run_indexed(|repo| self.inner_run(repo, &mut metrics));
+        metrics.publish(res.is_ok());
+        if let Err(err) = res {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl MigrateCmd {
+    fn inner_run(&self, repo: IndexedRepo, metrics: &mut CommandMetrics) -> Result<()> {
+        let config = RUSTIC_APP.config();
+        let dry_run = config.global.dry_run;
+
+        let mut merge_logs = Vec::new();
+        let mut target_config = RusticConfig::default();
+        target_config.merge_profile(&self.to, &mut merge_logs, Level::Error)?;
+        for (level, merge_log) in merge_logs {
+            log!(level, "{merge_log}");
+        }
+        let target_opt = &target_config.repository;
+        let target_repo = target_opt.repository(config.global.progress_options)?;
+
+        let mut snapshots = get_snapots_from_ids(&repo, &self.ids)?;
+        snapshots.sort_unstable();
+
+        let target_repo = match target_repo.config_id()? {
+            Some(_) => {
+                info!("target repository is already initialized, continuing migration.");
+                target_repo.open(&target_opt.credential_opts)?
+            }
+            None if dry_run => {
+                let target_config = self.target_config(repo.config())?;
+                info!(
+                    "would have initialized the target repository{}.",
+                    if repo.config().has_same_chunker(&target_config) {
+                        ""
+                    } else {
+                        " with different chunker parameters; all data would be re-chunked"
+                    }
+                );
+                print_snapshots(snapshots.iter().map(|sn| (sn, true)));
+                return Ok(());
+            }
+            None => {
+                let target_config = self.target_config(repo.config())?;
+                let pass = init_credentials(&target_opt.credential_opts)?;
+                info!("initializing target repository...");
+                target_repo
+                    .0
+                    .init_with_config(&pass, &self.key_opts, target_config)?
+            }
+        };
+        let rechunk = !repo.config().has_same_chunker(target_repo.config());
+
+        let snaps = target_repo.relevant_copy_snapshots(|_| true, &snapshots)?;
+        print_snapshots(
+            snaps
+                .iter()
+                .map(|CopySnapshot { sn, relevant }| (sn, *relevant)),
+        );
+        let snaps: Vec<_> = snaps
+            .into_iter()
+            .filter_map(|CopySnapshot { relevant, sn }| relevant.then_some(sn))
+            .collect();
+
+        metrics.add(
+            "snapshots",
+            "Number of migrated snapshots",
+            Int(snaps.len() as u64),
+        );
+
+        if dry_run {
+            info!(
+                "would have migrated {} snapshots{}.",
+                snaps.len(),
+                if rechunk { " re-chunking all data" } else { "" }
+            );
+            return Ok(());
+        }
+
+        let target_repo = target_repo.to_indexed_ids()?;
+        let repo = Arc::new(repo);
+        let target_repo = if snaps.is_empty() {
+            info!("nothing to migrate.");
+            target_repo.drop_index()
+        } else if rechunk {
+            info!("chunker parameters differ, re-chunking all data...");
+            let combined = rechunk_snapshots(&repo, &target_repo, &snaps)?;
+            // the trees saved while re-chunking are only available after reading the index again
+            let target_repo = target_repo.drop_index().to_indexed_ids()?;
+            split_snapshots(&target_repo, &combined, snaps)?;
+            target_repo.drop_index()
+        } else {
+            repo.copy(&target_repo, snaps.iter())?;
+            target_repo.drop_index()
+        };
+
+        // verify the migrated repository
+        target_repo.check(CheckOptions::default())?.is_ok()?;
+        let target_repo = target_repo.to_indexed_ids()?;
+        verify_snapshots(&repo, &target_repo, &snapshots, rechunk)?;
+        info!("all {} snapshots have been verified.", snapshots.len());
+
+        if self.switch_profile {
+            self.switch_profile()?;
+        }
+        Ok(())
+    }
+
+    /// Config of the target repository: the source config with the given options applied
+    fn target_config(&self, source: &ConfigFile) -> Result<ConfigFile> {
+        let mut config = source.clone();
+        config.id = Id::random().into();
+        if self.new_chunker_polynomial {
+            config.chunker_polynomial = format!("{:x}", random_polynomial()?);
+        }
+        self.config_opts.apply(&mut config)?;
+        Ok(config)
+    }
+
+    /// Replace the repository options of the current profile by the ones of the target profile
+    fn switch_profile(&self) -> Result<()> {
+        let config = RUSTIC_APP.config();
+        let profile = match config.global.use_profiles.as_slice() {
+            [] => "rustic",
+            [profile] => profile,
+            _ => bail!("cannot switch profiles when using multiple profiles."),
+        };
+        let path = profile_path(profile)
+            .ok_or_else(|| anyhow!("config file for profile {profile} not found."))?;
+        let target_path = profile_path(&self.to)
+            .ok_or_else(|| anyhow!("config file for profile {} not found.", self.to))?;
+
+        let mut profile_config: toml::Table = fs::read_to_string(&path)?.parse()?;
+        let target_config: toml::Table = fs::read_to_string(&target_path)?.parse()?;
+        let repository = target_config
+            .get("repository")
+            .ok_or_else(|| anyhow!("{} contains no repository options.", target_path.display()))?;
+        _ = profile_config.insert("repository".to_string(), repository.clone());
+
+        _ = fs::copy(&path, path.with_extension("toml.pre-migrate"))?;
+        replace_file(&path, &toml::to_string(&profile_config)?)?;
+        info!(
+            "profile {profile} now uses the repository of profile {}.",
+            self.to
+        );
+        Ok(())
+    }
+}
+
+/// Name of the dir containing the contents of the snapshot with number `num` in the combined snapshot
+fn snapshot_dir(num: usize) -> PathBuf {
+    // pad the number so that the dirs are sorted as the snapshots
+    PathBuf::from(format!("{num:010}"))
+}
+
+/// Re-chunk snapshots by saving their contents within a single combined snapshot in the target
+/// repository
+///
+/// All contents are saved within one archiver run, so data is deduplicated using one index of the
+/// target repository. Returns the combined snapshot; the migrated snapshots are created from it
+/// by [`split_snapshots`].
+fn rechunk_snapshots(
+    repo: &Arc<IndexedRepo>,
+    target_repo: &IndexedIdsRepo,
+    snaps: &[SnapshotFile],
+) -> Result<SnapshotFile> {
+    let mut opts = BackupOptions::default();
+    opts.parent_opts.force = true;
+    let snap = SnapshotFile {
+        label: "migrate".to_string(),
+        ..Default::default()
+    };
+    let snapshots = snaps
+        .iter()
+        .enumerate()
+        .map(|(num, sn)| (snapshot_dir(num), sn.clone()))
+        .collect();
+    let src = SnapshotSource::new(repo.clone(), snapshots);
+    Ok(target_repo.archive(&opts, &src, snap, &[PathBuf::from("/")])?)
+}
+
+/// Save the migrated snapshots, each using the tree of its dir within the combined snapshot, and
+/// remove the combined snapshot
+fn split_snapshots(
+    target_repo: &IndexedIdsRepo,
+    combined: &SnapshotFile,
+    snaps: Vec<SnapshotFile>,
+) -> Result<()> {
+    let snaps = snaps
+        .into_iter()
+        .enumerate()
+        .map(|(num, mut snap)| {
+            let node = target_repo.node_from_path(combined.tree, &snapshot_dir(num))?;
+            snap.tree = node
+                .subtree
+                .ok_or_else(|| anyhow!("snapshot {} has not been migrated.", snap.id))?;
+            snap.parent = None;
+            snap.parents = Vec::new();
+            Ok(snap)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    target_repo.save_snapshots(snaps)?;
+    if let Err(err) = target_repo.delete_snapshots(&[combined.id]) {
+        warn!(
+            "could not remove the temporary snapshot {}: {err}",
+            combined.id
+        );
+    }
+    Ok(())
+}
+
+/// Check that all snapshots exist in the target repository with identical contents
+///
+/// If the snapshots have been re-chunked, the contents cannot be compared by their blobs; then
+/// only a metadata check is done, i.e. paths, types, sizes and mtimes of all entries are compared.
+fn verify_snapshots(
+    repo: &IndexedRepo,
+    target_repo: &IndexedIdsRepo,
+    snapshots: &[SnapshotFile],
+    rechunked: bool,
+) -> Result<()> {
+    let target_snaps = target_repo.get_all_snapshots()?;
+    let p = repo.progress_counter("verifying snapshots...");
+    p.set_length(snapshots.len() as u64);
+    if rechunked {
+        info!("data has been re-chunked; only checking the metadata of the migrated snapshots.");
+    }
+    for sn in snapshots {
+        let target_sn = target_snaps
+            .iter()
+            .find(|target| target.time == sn.time && target.original == sn.original)
+            .ok_or_else(|| anyhow!("snapshot {} is missing in the target repository.", sn.id))?;
+        if target_sn.tree != sn.tree {
+            let root = repo.node_from_snapshot_and_path(sn, "")?;
+            let target_root = target_repo.node_from_snapshot_and_path(target_sn, "")?;
+            let ls_opts = LsOptions::default().recursive(true);
+            let mut nodes = repo.ls(&root, &ls_opts)?;
+            let mut target_nodes = target_repo.ls(&target_root, &ls_opts)?;
+            loop {
+                match (nodes.next().transpose()?, target_nodes.next().transpose()?) {
+                    (None, None) => break,
+                    (Some((path, node)), Some((target_path, target_node)))
+                        if path == target_path
+                            && node.node_type == target_node.node_type
+                            && node.meta.size == target_node.meta.size
+                            && node.meta.mtime == target_node.meta.mtime
+                            && (rechunked || node.content == target_node.content) => {}
+                    _ => bail!(
+                        "snapshot {} differs from the migrated snapshot {}.",
+                        sn.id,
+                        target_sn.id
+                    ),
+                }
+            }
+        }
+        p.inc(1);
+    }
+    p.finish();
+    Ok(())
+}
+
+fn print_snapshots<'a>(snaps: impl Iterator<Item = (&'a SnapshotFile, bool)>) {
+    let config = RUSTIC_APP.config();
+    let mut table = table_with_titles(["ID", "Time", "Host", "Label", "Tags", "Paths", "Status"]);
+    for (sn, relevant) in snaps {
+        let tags = sn.tags.formatln();
+        let paths = sn.paths.formatln();
+        let time = config.global.format_time(&sn.time).to_string();
+        _ = table.add_row([
+            &sn.id.to_string(),
+            &time,
+            &sn.hostname,
+            &sn.label,
+            &tags,
+            &paths,
+            &(if relevant { "to migrate" } else { "existing" }).to_string(),
+        ]);
+    }
+    println!("{table}");
+}
+
+/// Atomically replace the contents of a file
+fn replace_file(path: &Path, contents: &str) -> Result<()> {
+    let tmp_path = path.with_extension("toml.tmp");
+    fs::write(&tmp_path, contents)?;
+    if let Err(err) = fs::rename(&tmp_path, path) {
+        warn!("could not replace {}: {err}", path.display());
+        _ = fs::remove_file(&tmp_path);
+        return Err(err.into());
+    }
+    Ok(())
+}
+
+/// Number of tries to find an irreducible polynomial
+const RAND_POLY_MAX_TRIES: usize = 1_000_000;
+
+/// Get a random irreducible polynomial of degree 53 to be used as chunker polynomial
+fn random_polynomial() -> Result<u64> {
+    for _ in 0..RAND_POLY_MAX_TRIES {
+        let random = u64::from_str_radix(&Id::random().to_hex()[..16], 16)?;
+        // use degree 53 and set the lowest bit so that the polynomial is not trivially reducible
+        let poly = (random & ((1 << 54) - 1)) | (1 << 53) | 1;
+        if polynomial::irreducible(poly) {
+            return Ok(poly);
+        }
+    }
+    bail!("no suitable polynomial found, please try again.")
+}
+
+/// Arithmetic of polynomials over `F_2` as used by the rabin chunker
+mod polynomial {
+    fn degree(x: u64) -> i32 {
+        63 - x.leading_zeros() as i32
+    }
+
+    fn modulo(mut x: u64, m: u64) -> u64 {
+        while degree(x) >= degree(m) {
+            x ^= m << (degree(x) - degree(m));
+        }
+        x
+    }
+
+    fn gcd(x: u64, y: u64) -> u64 {
+        if y == 0 { x } else { gcd(y, modulo(x, y)) }
+    }
+
+    fn mulmod(x: u64, y: u64, m: u64) -> u64 {
+        let (mut res, mut a, mut b) = (0, x, y);
+        while b != 0 {
+            if b & 1 == 1 {
+                res = modulo(res ^ a, m);
+            }
+            a = modulo(a << 1, m);
+            b >>= 1;
+        }
+        res
+    }
+
+    /// Ben Or's irreducibility test
+    pub(super) fn irreducible(x: u64) -> bool {
+        (1..=degree(x) / 2).all(|i| {
+            // compute x^(2^i) + x mod f
+            let mut res = 2;
+            for _ in 0..i {
+                res = mulmod(res, res, x);
+            }
+            gcd(x, modulo(res ^ 2, x)) == 1
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(0x3DA3358B4DC173, true)]
+    #[case(0x38B6A23D4C5A2D, false)]
+    fn irreducible(#[case] poly: u64, #[case] expected: bool) {
+        assert_eq!(polynomial::irreducible(poly), expected);
+    }
+}
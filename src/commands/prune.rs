@@ -1,7 +1,11 @@
 //! `prune` subcommand
 
 use crate::{
-    Application, RUSTIC_APP, helpers::bytes_size_to_string, repository::OpenRepo, status_err,
+    Application, RUSTIC_APP,
+    helpers::bytes_size_to_string,
+    metrics::{CommandMetrics, MetricValue::Int},
+    repository::OpenRepo,
+    status_err,
 };
 use abscissa_core::{Command, Runnable, Shutdown};
 use log::{debug, info};
@@ -21,11 +25,13 @@ pub(crate) struct PruneCmd {
 
 impl Runnable for PruneCmd {
     fn run(&self) {
-        if let Err(err) = RUSTIC_APP
+        let mut metrics = CommandMetrics::new("prune");
+        let res = RUSTIC_APP
             .config()
             .repository
-            .run_open(|repo| self.inner_run(repo))
-        {
+            .run_open(|repo| self.inner_run(repo, &mut metrics));
+        metrics.publish(res.is_ok());
+        if let Err(err) = res {
             status_err!("{}", err);
             RUSTIC_APP.shutdown(Shutdown::Crash);
         };
@@ -33,12 +39,13 @@ impl Runnable for PruneCmd {
 }
 
 impl PruneCmd {
-    fn inner_run(&self, repo: OpenRepo) -> Result<()> {
+    fn inner_run(&self, repo: OpenRepo, metrics: &mut CommandMetrics) -> Result<()> {
         let config = RUSTIC_APP.config();
 
         let prune_plan = repo.prune_plan(&self.opts)?;
 
         print_stats(&prune_plan.stats);
+        add_metrics(metrics, &prune_plan.stats);
 
         let dry_run = config.global.dry_run;
         if dry_run && config.global.dry_run_warmup {
@@ -54,6 +61,49 @@ impl PruneCmd {
     }
 }
 
+/// Add the statistics about the prune operation to the metrics
+fn add_metrics(metrics: &mut CommandMetrics, stats: &PruneStats) {
+    let pack_stat = &stats.packs;
+    let blob_stat = stats.blobs_sum();
+    let size_stat = stats.size_sum();
+
+    metrics.add(
+        "packs_repacked",
+        "Number of packs to repack",
+        Int(pack_stat.repack),
+    );
+    metrics.add(
+        "packs_removed",
+        "Number of unused packs to delete",
+        Int(pack_stat.unused),
+    );
+    metrics.add(
+        "blobs_removed",
+        "Number of blobs removed by repacking or deleting packs",
+        Int(blob_stat.repackrm + blob_stat.remove),
+    );
+    metrics.add(
+        "bytes_repacked",
+        "Total size of all blobs to repack",
+        Int(size_stat.repack),
+    );
+    metrics.add(
+        "bytes_freed",
+        "Total size removed from the repository",
+        Int(size_stat.repackrm + size_stat.remove + stats.size_unref),
+    );
+    metrics.add(
+        "bytes_remaining",
+        "Total size of all blobs remaining in the repository",
+        Int(size_stat.total_after_prune()),
+    );
+    metrics.add(
+        "bytes_unused_remaining",
+        "Total size of unused blobs remaining in the repository",
+        Int(size_stat.unused_after_prune()),
+    );
+}
+
 /// Print statistics about the prune operation
 ///
 /// # Arguments
@@ -1,15 +1,29 @@
 //! `repair` subcommand
 
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
 use crate::{
     Application, RUSTIC_APP,
-    repository::{IndexedRepo, OpenRepo, get_snapots_from_ids},
+    metrics::{CommandMetrics, MetricValue::Int},
+    repository::{
+        IndexedIdsRepo, IndexedRepo, OpenRepo, get_snapots_from_ids, parity::reconstruct_pack,
+    },
     status_err,
 };
 use abscissa_core::{Command, Runnable, Shutdown};
 
-use anyhow::Result;
+use anyhow::{Result, bail};
+use log::{info, warn};
 
-use rustic_core::{RepairIndexOptions, RepairSnapshotsOptions};
+use rustic_core::{
+    BackupOptions, BlobId, DataId, PathList, RepairIndexOptions, RepairSnapshotsOptions,
+    WriteBackend,
+    repofile::{BlobType, FileType, IndexBlob, IndexFile, PackId, SnapshotFile},
+};
 
 /// `repair` subcommand
 #[derive(clap::Parser, Command, Debug)]
@@ -25,6 +39,8 @@ enum RepairSubCmd {
     Index(IndexSubCmd),
     /// Repair snapshots
     Snapshots(SnapSubCmd),
+    /// Repair damaged packs using parity files, the hot/cold counterpart or by salvaging all readable blobs
+    Packs(PacksSubCmd),
 }
 
 #[derive(Default, Debug, clap::Parser, Command)]
@@ -48,6 +64,19 @@ struct SnapSubCmd {
     ids: Vec<String>,
 }
 
+/// `repair packs` subcommand
+#[derive(Default, Debug, clap::Parser, Command)]
+struct PacksSubCmd {
+    /// Damaged packs to repair, e.g. as reported by `check --read-data`
+    #[clap(value_name = "PACK_ID", required = true)]
+    ids: Vec<String>,
+
+    /// Local path to look for blobs which cannot be salvaged (can be specified multiple times).
+    /// All files below PATH are chunked and missing blobs are recovered regardless of the file names
+    #[clap(long, value_name = "PATH")]
+    from: Vec<PathBuf>,
+}
+
 impl Runnable for RepairCmd {
     fn run(&self) {
         self.cmd.run();
@@ -57,7 +86,10 @@ impl Runnable for RepairCmd {
 impl Runnable for IndexSubCmd {
     fn run(&self) {
         let config = RUSTIC_APP.config();
-        if let Err(err) = config.repository.run_open(|repo| self.inner_run(repo)) {
+        let metrics = CommandMetrics::new("repair_index");
+        let res = config.repository.run_open(|repo| self.inner_run(repo));
+        metrics.publish(res.is_ok());
+        if let Err(err) = res {
             status_err!("{}", err);
             RUSTIC_APP.shutdown(Shutdown::Crash);
         };
@@ -75,7 +107,10 @@ impl IndexSubCmd {
 impl Runnable for SnapSubCmd {
     fn run(&self) {
         let config = RUSTIC_APP.config();
-        if let Err(err) = config.repository.run_indexed(|repo| self.inner_run(repo)) {
+        let metrics = CommandMetrics::new("repair_snapshots");
+        let res = config.repository.run_indexed(|repo| self.inner_run(repo));
+        metrics.publish(res.is_ok());
+        if let Err(err) = res {
             status_err!("{}", err);
             RUSTIC_APP.shutdown(Shutdown::Crash);
         };
@@ -90,3 +125,269 @@ impl SnapSubCmd {
         Ok(())
     }
 }
+
+impl Runnable for PacksSubCmd {
+    fn run(&self) {
+        let config = RUSTIC_APP.config();
+        let mut metrics = CommandMetrics::new("repair_packs");
+        let res = config
+            .repository
+            .run_open(|repo| self.inner_run(repo, &mut metrics));
+        metrics.publish(res.is_ok());
+        if let Err(err) = res {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl PacksSubCmd {
+    fn inner_run(&self, repo: OpenRepo, metrics: &mut CommandMetrics) -> Result<()> {
+        let config = RUSTIC_APP.config();
+        let dry_run = config.global.dry_run;
+        let backends = config.repository.backends()?;
+        let be = backends.repository();
+        let be_hot = backends.repo_hot();
+        let parity_be = config.repository.parity_backend()?;
+
+        let pack_ids: BTreeSet<PackId> = repo.find_ids(&self.ids)?.collect();
+
+        // collect the blobs contained in the damaged packs and all blobs which are also saved elsewhere
+        let mut damaged: BTreeMap<PackId, Vec<IndexBlob>> = BTreeMap::new();
+        let mut elsewhere = BTreeSet::new();
+        for index in repo.stream_files::<IndexFile>()? {
+            let (_, index) = index?;
+            for pack in index.packs {
+                if pack_ids.contains(&pack.id) {
+                    damaged.entry(pack.id).or_default().extend(pack.blobs);
+                } else {
+                    elsewhere.extend(pack.blobs.into_iter().map(|blob| blob.id));
+                }
+            }
+        }
+
+        let (mut salvage_packs, mut tree_packs) = (Vec::new(), Vec::new());
+        let mut hotcold_repaired = false;
+        for id in &pack_ids {
+            let Some(blobs) = damaged.get(id) else {
+                warn!("pack {id} is not contained in the index, please run `repair index` first.");
+                continue;
+            };
+            let is_tree = blobs.iter().any(|blob| blob.tpe == BlobType::Tree);
+            let mut cold_ok = pack_matches(be.as_ref(), id);
+            let mut reconstructed = false;
+            if !cold_ok && let Some(parity_be) = &parity_be {
+                match reconstruct_pack(be.as_ref(), parity_be.as_ref(), id) {
+                    Ok(pack) => {
+                        info!(
+                            "pack {id} is damaged and will be reconstructed using its parity file."
+                        );
+                        if !dry_run {
+                            be.write_bytes(FileType::Pack, id, false, pack.into())?;
+                        }
+                        (cold_ok, reconstructed) = (true, true);
+                    }
+                    Err(err) => {
+                        warn!("pack {id} cannot be reconstructed using its parity file: {err}");
+                    }
+                }
+            }
+            let hot_ok = match (&be_hot, is_tree) {
+                (Some(be_hot), true) => Some(pack_matches(be_hot.as_ref(), id)),
+                _ => None,
+            };
+            match (cold_ok, hot_ok) {
+                (true, None | Some(true)) => {
+                    if !reconstructed {
+                        info!("pack {id} is not damaged, skipping.");
+                    }
+                }
+                // the hot/cold counterpart is intact: remove the damaged copy and restore it from the counterpart
+                (true, Some(false)) | (false, Some(true)) => {
+                    let (damaged_be, part) = if cold_ok {
+                        (be_hot.as_ref().unwrap(), "hot")
+                    } else {
+                        (&be, "cold")
+                    };
+                    info!(
+                        "pack {id} is damaged in the {part} repository and will be restored from its counterpart."
+                    );
+                    if !dry_run {
+                        damaged_be.remove(FileType::Pack, id, true)?;
+                    }
+                    hotcold_repaired = true;
+                }
+                // tree blobs can only be saved by backing up, so damaged tree packs cannot be salvaged
+                (false, _) if is_tree => {
+                    warn!("pack {id} contains trees and cannot be repaired, keeping it.");
+                    tree_packs.push(*id);
+                }
+                (false, _) => salvage_packs.push(*id),
+            }
+        }
+        if hotcold_repaired {
+            repo.repair_hotcold_packs(dry_run)?;
+        }
+        metrics.add(
+            "salvaged_packs",
+            "Number of damaged packs whose blobs are salvaged",
+            Int(salvage_packs.len() as u64),
+        );
+        if !salvage_packs.is_empty() {
+            self.salvage(
+                repo.to_indexed()?,
+                be.as_ref(),
+                &damaged,
+                &elsewhere,
+                &salvage_packs,
+                metrics,
+            )?;
+        }
+        if !tree_packs.is_empty() {
+            bail!(
+                "{} packs containing trees could not be repaired. Please run `repair index` and `repair snapshots` to remove them from the repository.",
+                tree_packs.len()
+            );
+        }
+        Ok(())
+    }
+
+    /// Salvage all readable blobs from the damaged data packs and replace the packs
+    fn salvage(
+        &self,
+        repo: IndexedRepo,
+        be: &dyn WriteBackend,
+        damaged: &BTreeMap<PackId, Vec<IndexBlob>>,
+        elsewhere: &BTreeSet<BlobId>,
+        salvage_packs: &[PackId],
+        metrics: &mut CommandMetrics,
+    ) -> Result<()> {
+        let dry_run = RUSTIC_APP.config().global.dry_run;
+        let dir = tempfile::tempdir()?;
+        let (blobs_dir, packs_dir) = (dir.path().join("blobs"), dir.path().join("packs"));
+        fs::create_dir_all(&blobs_dir)?;
+        fs::create_dir_all(&packs_dir)?;
+        let (mut needed, mut missing) = (BTreeSet::new(), BTreeSet::new());
+        let (mut salvaged, mut duplicates) = (0, 0);
+        let p = repo.progress_counter("salvaging blobs...");
+        p.set_length(
+            salvage_packs
+                .iter()
+                .map(|id| damaged[id].len() as u64)
+                .sum(),
+        );
+        for blob in salvage_packs.iter().flat_map(|id| &damaged[id]) {
+            p.inc(1);
+            if elsewhere.contains(&blob.id) {
+                duplicates += 1;
+                continue;
+            }
+            _ = needed.insert(DataId::from(blob.id));
+            match repo.get_blob_cached(&blob.id, blob.tpe) {
+                Ok(data)
+                    if blob
+                        .id
+                        .blob_matches_reader(data.len() as u64, &mut data.as_ref()) =>
+                {
+                    fs::write(blobs_dir.join(blob.id.to_string()), &data)?;
+                    salvaged += 1;
+                }
+                _ => _ = missing.insert(DataId::from(blob.id)),
+            }
+        }
+        p.finish();
+        info!(
+            "{salvaged} blobs salvaged, {duplicates} blobs found in other packs, {} blobs missing.",
+            missing.len()
+        );
+        if dry_run {
+            info!("would have replaced {} packs.", salvage_packs.len());
+            return Ok(());
+        }
+
+        // keep the damaged packs, so they can be restored if saving the blobs fails
+        for id in salvage_packs {
+            fs::write(
+                packs_dir.join(id.to_string()),
+                be.read_full(FileType::Pack, id)?,
+            )?;
+        }
+        for id in salvage_packs {
+            info!("removing damaged pack {id}...");
+            be.remove(FileType::Pack, id, false)?;
+        }
+        let repo = repo.drop_index();
+        repo.repair_index(&RepairIndexOptions::default(), false)?;
+
+        // each salvaged blob is saved as a file of its own, which is chunked to the identical blob;
+        // missing blobs are recovered if they are found when chunking the files from the given paths
+        let repo = repo.to_indexed_ids()?;
+        if let Err(err) = save_blobs(&repo, &blobs_dir, &self.from) {
+            warn!("restoring the damaged packs...");
+            for id in salvage_packs {
+                let data = fs::read(packs_dir.join(id.to_string()))?;
+                be.write_bytes(FileType::Pack, id, false, data.into())?;
+            }
+            repo.drop_index()
+                .repair_index(&RepairIndexOptions::default(), false)?;
+            return Err(err);
+        }
+
+        // check which blobs are still missing
+        let repo = repo.drop_index().to_indexed()?;
+        let lost: Vec<_> = needed
+            .iter()
+            .filter(|id| repo.get_index_entry(*id).is_err())
+            .collect();
+        metrics.add(
+            "lost_blobs",
+            "Number of blobs which could not be recovered",
+            Int(lost.len() as u64),
+        );
+        if !self.from.is_empty() {
+            let recovered = missing.iter().filter(|id| !lost.contains(id)).count();
+            info!("{recovered} blobs recovered from the given paths.");
+            info!(
+                "other blobs read from the given paths are not used, run `prune` to remove them."
+            );
+        }
+        if lost.is_empty() {
+            info!("all blobs of the damaged packs have been recovered.");
+        } else {
+            for id in &lost {
+                warn!("blob {id} could not be recovered.");
+            }
+            bail!(
+                "{} blobs could not be recovered. Please run `repair snapshots` to remove the affected files from snapshots.",
+                lost.len()
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Save the blobs from all files below `blobs_dir` and `from` by backing them up
+///
+/// Only blobs which are not contained in the repository are saved. The snapshot saved by the
+/// backup is removed afterwards.
+fn save_blobs(repo: &IndexedIdsRepo, blobs_dir: &Path, from: &[PathBuf]) -> Result<()> {
+    let mut opts = BackupOptions::default();
+    // all files must be read
+    opts.parent_opts.force = true;
+    let paths: PathList = std::iter::once(blobs_dir.to_path_buf())
+        .chain(from.iter().cloned())
+        .collect();
+    let snap = SnapshotFile {
+        label: "repair".to_string(),
+        ..Default::default()
+    };
+    let snap = repo.backup(&opts, &paths.sanitize()?.merge(), snap)?;
+    repo.delete_snapshots(&[snap.id])?;
+    Ok(())
+}
+
+/// Check if the pack file saved in the backend matches its id
+fn pack_matches(be: &dyn WriteBackend, id: &PackId) -> bool {
+    be.read_full(FileType::Pack, id)
+        .is_ok_and(|data| id.blob_matches_reader(data.len() as u64, &mut data.as_ref()))
+}
@@ -1,19 +1,34 @@
 //! `rewrite` subcommand
 
+use std::{
+    collections::{BTreeMap, BTreeSet, btree_map},
+    ffi::OsStr,
+    ops::Bound,
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
+
 use crate::{
     Application, RUSTIC_APP,
-    commands::snapshots::print_snapshots,
-    repository::{IndexedRepo, OpenRepo, get_snapots_from_ids},
+    annotations::AnnotatedModification,
+    commands::{
+        backup::{keep_log, modifies_description, read_log},
+        snapshots::print_snapshots,
+    },
+    metrics::{CommandMetrics, MetricValue::Int},
+    repository::{IndexedRepo, OpenRepo, get_snapots_from_ids, snapshot_source::NodeSource},
     status_err,
 };
 
 use abscissa_core::{Command, Runnable, Shutdown};
-use anyhow::Result;
-use log::info;
+use anyhow::{Result, anyhow, bail};
+use log::{info, warn};
 
 use rustic_core::{
-    Excludes, NodeModification, RewriteOptions, RewriteTreesOptions, StringList,
-    repofile::{SnapshotFile, SnapshotModification},
+    BackupOptions, Excludes, LsOptions, NodeModification, RewriteOptions, RewriteTreesOptions,
+    SnapshotGroupCriterion, StringList,
+    repofile::{Node, SnapshotFile, SnapshotId, SnapshotModification},
 };
 
 /// `rewrite` subcommand
@@ -34,7 +49,7 @@ pub(crate) struct RewriteCmd {
     pub tags_rewritten: Option<StringList>,
 
     #[clap(flatten, next_help_heading = "Snapshot options")]
-    pub modification: SnapshotModification,
+    pub modification: AnnotatedModification,
 
     /// treat all trees as changed (i.e. serialize all and rebuild summary)
     #[clap(long, help_heading = "Tree rewrite options")]
@@ -45,61 +60,263 @@ pub(crate) struct RewriteCmd {
 
     #[clap(flatten, next_help_heading = "Node modification options")]
     pub node_modification: NodeModification,
+
+    /// Move a path inside the snapshots to a new location (can be specified multiple times)
+    ///
+    /// The contents of moved files are read from the repository to save them at their new location.
+    #[clap(
+        long = "move",
+        value_name = "FROM=TO",
+        help_heading = "Path rewrite options"
+    )]
+    pub moves: Vec<PathMove>,
+
+    /// Remove the given prefix from all paths below it
+    #[clap(long, value_name = "PATH", help_heading = "Path rewrite options")]
+    pub strip_prefix: Option<PathBuf>,
+
+    /// Add the given prefix to all paths
+    #[clap(long, value_name = "PATH", help_heading = "Path rewrite options")]
+    pub add_prefix: Option<PathBuf>,
+}
+
+/// A path relocation given as `FROM=TO`
+#[derive(Clone, Debug)]
+pub struct PathMove {
+    from: PathBuf,
+    to: PathBuf,
+}
+
+impl FromStr for PathMove {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        let (from, to) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("invalid move `{s}`, please use FROM=TO"))?;
+        let (from, to) = (relative(Path::new(from)), relative(Path::new(to)));
+        if from.as_os_str().is_empty() || to.as_os_str().is_empty() {
+            bail!("invalid move `{s}`: FROM and TO must not be empty or the root dir");
+        }
+        Ok(Self { from, to })
+    }
 }
 
 impl Runnable for RewriteCmd {
     fn run(&self) {
+        self.run_as("rewrite");
+    }
+}
+
+impl RewriteCmd {
+    /// Run the rewrite and publish its metrics under the given command name
+    pub(crate) fn run_as(&self, command: &'static str) {
         let repo = &RUSTIC_APP.config().repository;
+        let mut metrics = CommandMetrics::new(command);
 
-        if let Err(err) =
-            if self.excludes.is_empty() && self.node_modification.is_empty() && !self.all_trees {
-                repo.run_open(|repo| self.inner_run_open(repo))
-            } else {
-                repo.run_indexed(|repo| self.inner_run_indexed(repo))
-            }
-        {
+        let res = if self.path_rewrite().is_some() {
+            repo.run_indexed(|repo| self.inner_run_paths(repo, &mut metrics))
+        } else if self.excludes.is_empty() && self.node_modification.is_empty() && !self.all_trees {
+            repo.run_open(|repo| self.inner_run_open(repo, &mut metrics))
+        } else {
+            repo.run_indexed(|repo| self.inner_run_indexed(repo, &mut metrics))
+        };
+        metrics.publish(res.is_ok());
+        if let Err(err) = res {
             status_err!("{}", err);
             RUSTIC_APP.shutdown(Shutdown::Crash);
         }
     }
-}
 
-impl RewriteCmd {
-    fn opts(&self) -> RewriteOptions {
+    fn opts(&self, modification: SnapshotModification) -> RewriteOptions {
         let config = RUSTIC_APP.config();
         RewriteOptions::default()
             .forget(self.forget)
             .tags_rewritten(self.tags_rewritten.clone())
-            .modification(self.modification.clone())
+            .modification(modification)
             .dry_run(config.global.dry_run)
     }
 
-    fn inner_run_open(&self, repo: OpenRepo) -> Result<()> {
+    /// Group the snapshots by the rewrite options to use
+    ///
+    /// If the description is modified, snapshots with a saved backup log are rewritten separately
+    /// to keep their log.
+    fn grouped_opts(
+        &self,
+        snapshots: Vec<SnapshotFile>,
+    ) -> Result<Vec<(RewriteOptions, Vec<SnapshotFile>)>> {
+        let modification = self.modification.resolve(&snapshots);
+        let mut groups: BTreeMap<Option<String>, Vec<SnapshotFile>> = BTreeMap::new();
+        for sn in snapshots {
+            let log = read_log(&sn).filter(|_| modifies_description(&modification));
+            groups
+                .entry(log.map(ToString::to_string))
+                .or_default()
+                .push(sn);
+        }
+        groups
+            .into_iter()
+            .map(|(log, snapshots)| {
+                let modification = keep_log(&modification, log.as_deref())?;
+                Ok((self.opts(modification), snapshots))
+            })
+            .collect()
+    }
+
+    fn inner_run_open(&self, repo: OpenRepo, metrics: &mut CommandMetrics) -> Result<()> {
         let snapshots = get_snapots_from_ids(&repo, &self.ids)?;
 
-        let snaps = repo.rewrite_snapshots(snapshots, &self.opts())?;
+        let mut snaps = Vec::new();
+        for (opts, snapshots) in self.grouped_opts(snapshots)? {
+            snaps.extend(repo.rewrite_snapshots(snapshots, &opts)?);
+        }
 
-        self.output(snaps);
+        self.output(snaps, metrics);
 
         Ok(())
     }
 
-    fn inner_run_indexed(&self, repo: IndexedRepo) -> Result<()> {
+    fn inner_run_indexed(&self, repo: IndexedRepo, metrics: &mut CommandMetrics) -> Result<()> {
         let snapshots = get_snapots_from_ids(&repo, &self.ids)?;
         let tree_opts = RewriteTreesOptions::default()
             .all_trees(self.all_trees)
             .excludes(self.excludes.clone())
             .node_modification(self.node_modification.clone());
 
-        let snaps = repo.rewrite_snapshots_and_trees(snapshots, &self.opts(), &tree_opts)?;
+        let mut snaps = Vec::new();
+        for (opts, snapshots) in self.grouped_opts(snapshots)? {
+            snaps.extend(repo.rewrite_snapshots_and_trees(snapshots, &opts, &tree_opts)?);
+        }
 
-        self.output(snaps);
+        self.output(snaps, metrics);
 
         Ok(())
     }
 
-    fn output(&self, snaps: Vec<SnapshotFile>) {
+    fn path_rewrite(&self) -> Option<PathRewrite> {
+        if self.moves.is_empty() && self.strip_prefix.is_none() && self.add_prefix.is_none() {
+            return None;
+        }
+        Some(PathRewrite {
+            moves: self
+                .moves
+                .iter()
+                .map(|m| (m.from.clone(), m.to.clone()))
+                .collect(),
+            strip_prefix: self.strip_prefix.as_deref().map(relative),
+            add_prefix: self.add_prefix.as_deref().map(relative),
+        })
+    }
+
+    fn inner_run_paths(&self, repo: IndexedRepo, metrics: &mut CommandMetrics) -> Result<()> {
         let config = RUSTIC_APP.config();
+        let dry_run = config.global.dry_run;
+        let rewrite = self.path_rewrite().unwrap();
+        if !self.excludes.is_empty() || !self.node_modification.is_empty() {
+            bail!(
+                "moving paths cannot be combined with exclude or node modification options. Please run rewrite twice."
+            );
+        }
+        if self.forget && repo.config().append_only == Some(true) {
+            bail!(
+                "Removing snapshots is not allowed in append-only repositories. Please disable append-only mode first, if you know what you are doing. Aborting."
+            );
+        }
+
+        let repo = Arc::new(repo);
+        let mut snapshots = get_snapots_from_ids(&repo, &self.ids)?;
+        snapshots.sort_unstable();
+        let modification = self.modification.resolve(&snapshots);
+        // temporary snapshots saved by the archiver; only their trees are used
+        let mut archived = Vec::new();
+        let snaps = self.rewrite_paths(&repo, &rewrite, &modification, snapshots, &mut archived);
+        if !archived.is_empty()
+            && let Err(err) = repo.delete_snapshots(&archived)
+        {
+            warn!("could not remove the temporary snapshots {archived:?}: {err}");
+        }
+        let snaps = snaps?;
+
+        if !dry_run && !snaps.is_empty() {
+            repo.save_snapshots(snaps.clone())?;
+            if self.forget {
+                let ids: Vec<_> = snaps.iter().map(|sn| sn.id).collect();
+                repo.delete_snapshots(&ids)?;
+            }
+        }
+
+        self.output(snaps, metrics);
+
+        Ok(())
+    }
+
+    /// Relocate the paths of the snapshots and return the changed snapshots
+    ///
+    /// The relocated entries are saved by the archiver using the original snapshot as parent, so
+    /// only the contents of moved files are read from the repository again. The ids of the
+    /// snapshots saved by the archiver are added to `archived`.
+    fn rewrite_paths(
+        &self,
+        repo: &Arc<IndexedRepo>,
+        rewrite: &PathRewrite,
+        modification: &SnapshotModification,
+        snapshots: Vec<SnapshotFile>,
+        archived: &mut Vec<SnapshotId>,
+    ) -> Result<Vec<SnapshotFile>> {
+        let dry_run = RUSTIC_APP.config().global.dry_run;
+        // snapshots with identical trees get identical rewritten trees
+        let mut rewritten_trees = BTreeMap::new();
+        let mut snaps = Vec::new();
+        for mut sn in snapshots {
+            let mut paths = StringList::default();
+            for path in sn.paths.iter() {
+                paths.add(rewrite.path(Path::new(path)).display().to_string());
+            }
+            let relocated = rewrite
+                .relocate_snapshot(repo, &sn)
+                .map_err(|err| anyhow!("snapshot {}: {err}", sn.id))?;
+            if !relocated.changed && paths == sn.paths {
+                info!("snapshot {} is unchanged, skipping.", sn.id);
+                continue;
+            }
+            _ = sn.modify(&keep_log(modification, read_log(&sn))?)?;
+
+            if dry_run {
+                println!("snapshot {}:", sn.id);
+                for (from, to) in &relocated.moved {
+                    println!("  /{} -> /{}", from.display(), to.display());
+                }
+                if paths != sn.paths {
+                    println!("  paths: {} -> {}", sn.paths, paths);
+                }
+            } else {
+                let tree = match rewritten_trees.entry(sn.tree) {
+                    btree_map::Entry::Occupied(tree) => *tree.get(),
+                    btree_map::Entry::Vacant(vacant) => {
+                        let snap = archive_entries(repo, &sn, relocated.entries)?;
+                        archived.push(snap.id);
+                        *vacant.insert(snap.tree)
+                    }
+                };
+                match (&self.tags_rewritten, self.forget) {
+                    (Some(tags), _) => _ = sn.add_tags(vec![tags.clone()]),
+                    (None, false) => sn.tags.add("rewrite".to_string()),
+                    (None, true) => {}
+                }
+                sn.tree = tree;
+            }
+            sn.paths = paths;
+            snaps.push(sn);
+        }
+        Ok(snaps)
+    }
+
+    fn output(&self, snaps: Vec<SnapshotFile>, metrics: &mut CommandMetrics) {
+        let config = RUSTIC_APP.config();
+        metrics.add(
+            "snapshots",
+            "Number of rewritten snapshots",
+            Int(snaps.len() as u64),
+        );
         if config.global.dry_run {
             println!("Would have rewritten the following snapshots:");
             print_snapshots(snaps, false, true);
@@ -108,3 +325,219 @@ impl RewriteCmd {
         }
     }
 }
+
+/// Relocation rules for paths within snapshots. All paths are handled relative to the root dir.
+#[derive(Debug)]
+struct PathRewrite {
+    moves: Vec<(PathBuf, PathBuf)>,
+    strip_prefix: Option<PathBuf>,
+    add_prefix: Option<PathBuf>,
+}
+
+impl PathRewrite {
+    /// Apply the moves to a path relative to the root dir
+    fn moved(&self, path: &Path) -> PathBuf {
+        // the most specific move wins
+        self.moves
+            .iter()
+            .filter(|(from, _)| path.starts_with(from))
+            .max_by_key(|(from, _)| from.components().count())
+            .map_or_else(
+                || path.to_path_buf(),
+                |(from, to)| join(to, path.strip_prefix(from).unwrap()),
+            )
+    }
+
+    /// Relocate a path relative to the root dir; an empty result denotes the root dir itself
+    fn relocate(&self, path: &Path) -> PathBuf {
+        let mut path = self.moved(path);
+        if let Some(prefix) = &self.strip_prefix
+            && let Ok(stripped) = path.strip_prefix(prefix)
+        {
+            path = stripped.to_path_buf();
+        }
+        if let Some(prefix) = &self.add_prefix {
+            path = join(prefix, &path);
+        }
+        path
+    }
+
+    /// Relocate a path given in the `paths` field of a snapshot
+    fn path(&self, path: &Path) -> PathBuf {
+        let relocated = self.relocate(&relative(path));
+        match (path.has_root(), relocated.as_os_str().is_empty()) {
+            (true, _) => Path::new("/").join(relocated),
+            (false, true) => PathBuf::from("."),
+            (false, false) => relocated,
+        }
+    }
+
+    /// List the entries of a snapshot at their new location
+    fn relocate_snapshot(&self, repo: &IndexedRepo, sn: &SnapshotFile) -> Result<Relocated> {
+        let root = repo.node_from_snapshot_and_path(sn, "")?;
+        let mut relocated = Relocated::default();
+        // new paths of the dirs above the stripped prefix
+        let mut above_prefix = BTreeSet::new();
+        for item in repo.ls(&root, &LsOptions::default().recursive(true))? {
+            let (path, node) = item?;
+            let new_path = self.relocate(&path);
+            if new_path != path {
+                relocated.changed = true;
+                let with_parent = path
+                    .parent()
+                    .map(|parent| self.relocate(parent))
+                    .filter(|parent| !parent.as_os_str().is_empty())
+                    .zip(path.file_name())
+                    .is_some_and(|(parent, name)| parent.join(name) == new_path);
+                if !with_parent && !new_path.as_os_str().is_empty() {
+                    relocated.moved.push((path.clone(), new_path.clone()));
+                }
+            }
+            // the dir which becomes the root dir is omitted; its contents are kept
+            let Some(name) = new_path.file_name() else {
+                continue;
+            };
+            let node = renamed(node, name);
+            if node.is_dir()
+                && self
+                    .strip_prefix
+                    .as_ref()
+                    .is_some_and(|prefix| prefix.starts_with(self.moved(&path)))
+            {
+                _ = above_prefix.insert(new_path.clone());
+            }
+            match relocated.entries.entry(new_path) {
+                btree_map::Entry::Vacant(vacant) => _ = vacant.insert(node),
+                // identical dirs are merged
+                btree_map::Entry::Occupied(existing)
+                    if existing.get().is_dir() && node.is_dir() => {}
+                btree_map::Entry::Occupied(existing) => bail!(
+                    "rewriting paths results in multiple entries at /{}",
+                    existing.key().display()
+                ),
+            }
+        }
+
+        // remove dirs above the stripped prefix which are empty now; subdirs are checked first
+        for dir in above_prefix.iter().rev() {
+            let is_empty = relocated
+                .entries
+                .range::<PathBuf, _>((Bound::Excluded(dir), Bound::Unbounded))
+                .next()
+                .is_none_or(|(path, _)| !path.starts_with(dir));
+            if is_empty {
+                _ = relocated.entries.remove(dir);
+                relocated.changed = true;
+            }
+        }
+        Ok(relocated)
+    }
+}
+
+/// The entries of a snapshot at their new location
+#[derive(Debug, Default)]
+struct Relocated {
+    /// All entries sorted by their new path
+    entries: BTreeMap<PathBuf, Node>,
+    /// Relocated entries; entries moved together with their parent dir are omitted
+    moved: Vec<(PathBuf, PathBuf)>,
+    /// Whether any entry is relocated or removed
+    changed: bool,
+}
+
+/// Save the entries with the archiver and return the saved snapshot
+///
+/// The original snapshot `sn` is used as parent, so only files at a new location are read.
+fn archive_entries(
+    repo: &Arc<IndexedRepo>,
+    sn: &SnapshotFile,
+    entries: BTreeMap<PathBuf, Node>,
+) -> Result<SnapshotFile> {
+    let files = entries.values().filter(|node| !node.is_dir()).count();
+    let mut opts = BackupOptions::default();
+    opts.parent_opts.group_by = Some(SnapshotGroupCriterion::new());
+    opts.parent_opts.parents = vec![sn.id.to_string()];
+    let snap = SnapshotFile {
+        label: "rewrite".to_string(),
+        ..Default::default()
+    };
+    let src = NodeSource::new(repo.clone(), entries.into_iter().collect());
+    let snap = repo.archive(&opts, &src, snap, &[PathBuf::from("/")])?;
+    // the archiver skips entries it cannot read
+    let processed = snap
+        .summary
+        .as_ref()
+        .map_or(0, |summary| summary.total_files_processed);
+    if processed != files as u64 {
+        bail!(
+            "snapshot {}: only {processed} of {files} files could be saved at their new location.",
+            sn.id
+        );
+    }
+    Ok(snap)
+}
+
+/// Set the name of a node
+fn renamed(node: Node, name: &OsStr) -> Node {
+    if name == node.name() {
+        return node;
+    }
+    let mut renamed = Node::new_node(name, node.node_type.clone(), node.meta.clone());
+    renamed.content = node.content;
+    renamed.subtree = node.subtree;
+    renamed
+}
+
+/// Get the normal components of a path, i.e. the path relative to the root dir
+pub(super) fn relative(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|comp| matches!(comp, Component::Normal(_)))
+        .collect()
+}
+
+/// Join paths without adding a trailing separator for an empty `rest`
+fn join(base: &Path, rest: &Path) -> PathBuf {
+    if rest.as_os_str().is_empty() {
+        base.to_path_buf()
+    } else {
+        base.join(rest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("srv/old", "data/new")]
+    #[case("srv/old/a/b", "data/new/a/b")]
+    #[case("srv/old/x/y", "y")]
+    #[case("srv/older", "srv/older")]
+    #[case("home/user/f", "f")]
+    #[case("home/user", "")]
+    #[case("home/other", "home/other")]
+    fn relocate(#[case] path: &str, #[case] expected: &str) {
+        let rewrite = PathRewrite {
+            moves: vec![
+                ("srv/old".into(), "data/new".into()),
+                ("srv/old/x".into(), "home/user".into()),
+            ],
+            strip_prefix: Some("home/user".into()),
+            add_prefix: None,
+        };
+        assert_eq!(rewrite.relocate(Path::new(path)), PathBuf::from(expected));
+    }
+
+    #[rstest]
+    #[case("/home/user", "/backup/home/user")]
+    #[case("src", "backup/src")]
+    fn snapshot_path(#[case] path: &str, #[case] expected: &str) {
+        let rewrite = PathRewrite {
+            moves: Vec::new(),
+            strip_prefix: None,
+            add_prefix: Some(relative(Path::new("/backup"))),
+        };
+        assert_eq!(rewrite.path(Path::new(path)), PathBuf::from(expected));
+    }
+}
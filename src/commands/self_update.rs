@@ -1,5 +1,7 @@
 //! `self-update` subcommand
 
+use std::path::PathBuf;
+
 use crate::{Application, RUSTIC_APP};
 
 use abscissa_core::{Command, Runnable, Shutdown, status_err};
@@ -12,6 +14,42 @@ pub(crate) struct SelfUpdateCmd {
     /// Do not ask before processing the self-update
     #[clap(long, conflicts_with = "dry_run")]
     force: bool,
+
+    /// Release channel to update from
+    #[clap(long, value_enum, default_value_t)]
+    channel: Channel,
+
+    /// Only update to versions matching the given requirement, e.g. "~0.11" or ">=0.10, <0.12"
+    #[clap(long, value_name = "REQ")]
+    version_req: Option<String>,
+
+    /// Install from a downloaded release archive, e.g. on hosts without internet access
+    #[clap(long, value_name = "FILE", conflicts_with_all = ["channel", "version_req", "rollback"])]
+    bundle: Option<PathBuf>,
+
+    /// Minisign signature of the bundle [default: bundle file name with ".sig" appended]
+    #[clap(long, value_name = "FILE", requires = "bundle")]
+    signature: Option<PathBuf>,
+
+    /// Verify releases with the given minisign public key instead of the rustic release key
+    #[clap(long, value_name = "KEY")]
+    public_key: Option<String>,
+
+    /// Restore the rustic binary which was installed before the last self-update
+    #[clap(long, conflicts_with_all = ["channel", "version_req"])]
+    rollback: bool,
+}
+
+/// Release channel
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum, derive_more::Display)]
+enum Channel {
+    /// Stable releases
+    #[default]
+    #[display("stable")]
+    Stable,
+    /// Stable releases and pre-releases like betas or release candidates
+    #[display("beta")]
+    Beta,
 }
 
 impl Runnable for SelfUpdateCmd {
@@ -26,41 +64,141 @@ impl Runnable for SelfUpdateCmd {
 impl SelfUpdateCmd {
     #[cfg(feature = "self-update")]
     fn inner_run(&self) -> Result<()> {
-        let current_version = semver::Version::parse(self_update::cargo_crate_version!())?;
+        use std::fs;
+
+        use anyhow::bail;
+        use dialoguer::Confirm;
+        use minisign_verify::PublicKey;
+        use semver::Version;
 
-        let release = self_update::backends::github::Update::configure()
-            .repo_owner("rustic-rs")
-            .repo_name("rustic")
-            .bin_name("rustic")
-            .show_download_progress(true)
-            .current_version(current_version.to_string().as_str())
-            .no_confirm(self.force)
-            .build()?;
+        use release::{
+            RELEASE_PUBLIC_KEY, asset_name, asset_version, download, install, rollback, verify,
+            with_suffix,
+        };
 
-        let latest_release = release.get_latest_release()?;
+        let dry_run = RUSTIC_APP.config().global.dry_run;
+        let exe = std::env::current_exe()?;
+        if self.rollback {
+            return rollback(&exe, dry_run);
+        }
 
-        let upstream_version = semver::Version::parse(&latest_release.version)?;
+        let current_version = Version::parse(self_update::cargo_crate_version!())?;
+        let target = self_update::get_target();
+        let public_key =
+            PublicKey::from_base64(self.public_key.as_deref().unwrap_or(RELEASE_PUBLIC_KEY))?;
 
-        match current_version.cmp(&upstream_version) {
-            std::cmp::Ordering::Greater => {
+        let (version, archive) = if let Some(bundle) = &self.bundle {
+            let archive = fs::read(bundle)?;
+            let signature = fs::read_to_string(
+                self.signature
+                    .clone()
+                    .unwrap_or_else(|| with_suffix(bundle, ".sig")),
+            )?;
+            // only trust the signed file name, not the name of the bundle file
+            let name = verify(&public_key, &archive, &signature)?;
+            let version = asset_version(&name, target)?;
+            let expected = asset_name(&version, target);
+            if name != expected {
+                bail!("signature is for {name} instead of {expected}, not installing.");
+            }
+            if version < current_version {
                 println!(
-                    "Your rustic version {current_version} is newer than the stable version {upstream_version} on upstream!"
+                    "Note: the bundle contains rustic {version} which is older than {current_version}."
                 );
             }
-            std::cmp::Ordering::Equal => {
-                println!("rustic version {current_version} is up-to-date!");
+            if dry_run {
+                println!("would have installed rustic {version} from verified bundle.");
+                return Ok(());
             }
-            std::cmp::Ordering::Less => {
-                let status = release.update()?;
-
-                if let self_update::Status::Updated(str) = status {
-                    println!("rustic version has been updated to: {str}");
-                }
+            (version, archive)
+        } else {
+            let Some((version, release)) = self.select_release(&current_version)? else {
+                return Ok(());
+            };
+            if dry_run {
+                println!("would have updated rustic {current_version} to {version}.");
+                return Ok(());
+            }
+            let name = asset_name(&version, target);
+            let (Some(asset), Some(signature)) =
+                (release.asset(&name), release.asset(&format!("{name}.sig")))
+            else {
+                bail!("release {version} has no signed archive for {target}.");
+            };
+            let archive = download(&asset.browser_download_url)?;
+            let signature = String::from_utf8(download(&signature.browser_download_url)?)?;
+            let signed_name = verify(&public_key, &archive, &signature)?;
+            if signed_name != name {
+                bail!("signature is for {signed_name} instead of {name}, not updating.");
             }
+            (version, archive)
+        };
+
+        if !self.force
+            && !Confirm::new()
+                .with_prompt(format!("update rustic {current_version} to {version}?"))
+                .default(true)
+                .interact()?
+        {
+            bail!("Aborting.");
         }
 
+        install(&exe, &archive)?;
+        println!("rustic version has been updated to: {version}");
+
         Ok(())
     }
+
+    /// Select the newest release of the channel matching the version requirement.
+    ///
+    /// Returns `None` if no update is needed.
+    #[cfg(feature = "self-update")]
+    fn select_release(
+        &self,
+        current_version: &semver::Version,
+    ) -> Result<Option<(semver::Version, release::GithubRelease)>> {
+        use semver::{Prerelease, VersionReq};
+
+        let req = self
+            .version_req
+            .as_deref()
+            .map(VersionReq::parse)
+            .transpose()?;
+        // also allow pre-releases when matching the requirement
+        let matches = |version: &semver::Version| {
+            let mut version = version.clone();
+            version.pre = Prerelease::EMPTY;
+            req.as_ref().is_none_or(|req| req.matches(&version))
+        };
+
+        let Some((version, release)) = release::fetch_releases()?
+            .into_iter()
+            .filter_map(|release| Some((release.version()?, release)))
+            .filter(|(version, release)| {
+                self.channel != Channel::Stable || (!release.prerelease && version.pre.is_empty())
+            })
+            .filter(|(version, _)| matches(version))
+            .max_by(|(v1, _), (v2, _)| v1.cmp(v2))
+        else {
+            anyhow::bail!("no {} release found.", self.channel);
+        };
+
+        match current_version.cmp(&version) {
+            std::cmp::Ordering::Greater if matches(current_version) => {
+                println!(
+                    "Your rustic version {current_version} is newer than the {} version {version} on upstream!",
+                    self.channel
+                );
+                Ok(None)
+            }
+            std::cmp::Ordering::Equal => {
+                println!("rustic version {current_version} is up-to-date!");
+                Ok(None)
+            }
+            _ => Ok(Some((version, release))),
+        }
+    }
+
     #[cfg(not(feature = "self-update"))]
     fn inner_run(&self) -> Result<()> {
         anyhow::bail!(
@@ -68,3 +206,260 @@ impl SelfUpdateCmd {
         );
     }
 }
+
+#[cfg(feature = "self-update")]
+mod release {
+    use std::{
+        ffi::OsStr,
+        fs, io,
+        path::{Path, PathBuf},
+    };
+
+    use anyhow::{Result, anyhow, bail};
+    use flate2::read::GzDecoder;
+    use log::{info, warn};
+    use minisign_verify::{PublicKey, Signature};
+    use reqwest::blocking::Client;
+    use semver::Version;
+    use serde::Deserialize;
+
+    /// Minisign public key the release archives are signed with by the release workflow, see
+    /// `[package.metadata.binstall.signing]` in Cargo.toml
+    pub(super) const RELEASE_PUBLIC_KEY: &str =
+        "RWSWSCEJEEacVeCy0va71hlrVtiW8YzMzOyJeso0Bfy/ZXq5OryWi/8T";
+
+    /// A release as returned by the GitHub API
+    #[derive(Debug, Deserialize)]
+    pub(super) struct GithubRelease {
+        tag_name: String,
+        pub(super) prerelease: bool,
+        assets: Vec<GithubAsset>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub(super) struct GithubAsset {
+        name: String,
+        pub(super) browser_download_url: String,
+    }
+
+    impl GithubRelease {
+        pub(super) fn version(&self) -> Option<Version> {
+            Version::parse(self.tag_name.trim_start_matches('v')).ok()
+        }
+
+        pub(super) fn asset(&self, name: &str) -> Option<&GithubAsset> {
+            self.assets.iter().find(|asset| asset.name == name)
+        }
+    }
+
+    /// Get all releases including pre-releases
+    pub(super) fn fetch_releases() -> Result<Vec<GithubRelease>> {
+        let url = "https://api.github.com/repos/rustic-rs/rustic/releases";
+        let releases = client()?.get(url).send()?.error_for_status()?.text()?;
+        Ok(serde_json::from_str(&releases)?)
+    }
+
+    pub(super) fn download(url: &str) -> Result<Vec<u8>> {
+        info!("downloading {url}...");
+        Ok(client()?
+            .get(url)
+            .send()?
+            .error_for_status()?
+            .bytes()?
+            .to_vec())
+    }
+
+    fn client() -> Result<Client> {
+        Ok(Client::builder()
+            .user_agent(format!("rustic/{}", env!("CARGO_PKG_VERSION")))
+            .build()?)
+    }
+
+    /// Name of the release archive
+    pub(super) fn asset_name(version: &Version, target: &str) -> String {
+        format!("rustic-v{version}-{target}.tar.gz")
+    }
+
+    /// Get the version from the name of a release archive for the given target
+    pub(super) fn asset_version(name: &str, target: &str) -> Result<Version> {
+        let Some(version) = name
+            .strip_prefix("rustic-v")
+            .and_then(|name| name.strip_suffix(&format!("-{target}.tar.gz")))
+        else {
+            bail!("{name} is not a rustic release archive for {target}.");
+        };
+        Ok(Version::parse(version)?)
+    }
+
+    /// Verify the minisign signature of a release archive.
+    ///
+    /// Returns the file name contained in the trusted comment of the signature; signatures without
+    /// a file name are rejected. Both pre-hashed and legacy signatures are accepted, as the release
+    /// archives are signed by rsign.
+    pub(super) fn verify(key: &PublicKey, archive: &[u8], signature: &str) -> Result<String> {
+        let signature = Signature::decode(signature)?;
+        key.verify(archive, &signature, true)
+            .map_err(|err| anyhow!("signature verification failed: {err}"))?;
+        info!("signature verified: {}", signature.trusted_comment());
+        signature
+            .trusted_comment()
+            .split_whitespace()
+            .find_map(|part| part.strip_prefix("file:"))
+            .map(ToString::to_string)
+            .ok_or_else(|| anyhow!("the signature does not contain a file name."))
+    }
+
+    /// Extract the rustic binary from a release archive
+    pub(super) fn extract_binary(archive: &[u8], dest: &Path) -> Result<()> {
+        let bin_name = format!("rustic{}", std::env::consts::EXE_SUFFIX);
+        let mut tar = tar::Archive::new(GzDecoder::new(archive));
+        for entry in tar.entries()? {
+            let mut entry = entry?;
+            if entry.header().entry_type().is_file()
+                && entry.path()?.file_name() == Some(OsStr::new(&bin_name))
+            {
+                let mut file = fs::File::create(dest)?;
+                _ = io::copy(&mut entry, &mut file)?;
+                #[cfg(unix)]
+                {
+                    use std::os::unix::fs::PermissionsExt;
+                    fs::set_permissions(dest, fs::Permissions::from_mode(0o755))?;
+                }
+                return Ok(());
+            }
+        }
+        bail!("release archive does not contain {bin_name}.");
+    }
+
+    /// Replace the running binary by the one contained in the release archive.
+    ///
+    /// The current binary is kept to allow a rollback.
+    pub(super) fn install(exe: &Path, archive: &[u8]) -> Result<()> {
+        let new = with_suffix(exe, ".new");
+        extract_binary(archive, &new)?;
+        let runnable = std::process::Command::new(&new)
+            .arg("--version")
+            .output()
+            .is_ok_and(|output| output.status.success());
+        if !runnable {
+            _ = fs::remove_file(&new);
+            bail!("the new rustic binary cannot be run, not installing it.");
+        }
+        _ = fs::copy(exe, with_suffix(exe, ".previous"))?;
+        let res = self_update::self_replace::self_replace(&new);
+        _ = fs::remove_file(&new);
+        Ok(res?)
+    }
+
+    /// Restore the binary which was replaced by the last update.
+    ///
+    /// The current binary is kept, so another rollback reverts the rollback.
+    pub(super) fn rollback(exe: &Path, dry_run: bool) -> Result<()> {
+        let previous = with_suffix(exe, ".previous");
+        if !previous.exists() {
+            bail!("no previous rustic binary found at {}.", previous.display());
+        }
+        if dry_run {
+            println!("would have restored {}.", previous.display());
+            return Ok(());
+        }
+        let current = with_suffix(exe, ".rollback");
+        _ = fs::copy(exe, &current)?;
+        if let Err(err) = self_update::self_replace::self_replace(&previous) {
+            _ = fs::remove_file(&current);
+            return Err(err.into());
+        }
+        if let Err(err) = fs::rename(&current, &previous) {
+            warn!("could not keep the replaced binary: {err}");
+        }
+        println!("restored the previous rustic binary.");
+        Ok(())
+    }
+
+    /// Append a suffix to the file name
+    pub(super) fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+        let mut path = path.as_os_str().to_owned();
+        path.push(suffix);
+        path.into()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use rstest::rstest;
+
+        const FIXTURES: &str = "tests/self-update-fixtures";
+        const FIXTURE_NAME: &str = "rustic-v0.99.0-x86_64-unknown-linux-gnu.tar.gz";
+
+        fn fixture() -> (PublicKey, Vec<u8>, String) {
+            let dir = Path::new(FIXTURES);
+            let key = fs::read_to_string(dir.join("fixture.pub")).unwrap();
+            let archive = fs::read(dir.join(FIXTURE_NAME)).unwrap();
+            let signature = fs::read_to_string(dir.join(format!("{FIXTURE_NAME}.sig"))).unwrap();
+            (PublicKey::decode(&key).unwrap(), archive, signature)
+        }
+
+        #[test]
+        fn verify_fixture() {
+            let (key, archive, signature) = fixture();
+            let name = verify(&key, &archive, &signature).unwrap();
+            assert_eq!(name, FIXTURE_NAME);
+
+            let mut tampered = archive;
+            tampered[20] ^= 1;
+            assert!(verify(&key, &tampered, &signature).is_err());
+
+            let release_key = PublicKey::from_base64(RELEASE_PUBLIC_KEY).unwrap();
+            assert!(verify(&release_key, &fixture().1, &signature).is_err());
+        }
+
+        #[test]
+        fn release_key_is_binstall_key() {
+            let manifest: toml::Value =
+                toml::from_str(&fs::read_to_string("Cargo.toml").unwrap()).unwrap();
+            let pubkey = &manifest["package"]["metadata"]["binstall"]["signing"]["pubkey"];
+            assert_eq!(pubkey.as_str(), Some(RELEASE_PUBLIC_KEY));
+        }
+
+        #[test]
+        fn verify_requires_file_name() {
+            let (key, archive, _) = fixture();
+            let path = Path::new(FIXTURES).join(format!("{FIXTURE_NAME}.nofile.sig"));
+            let signature = fs::read_to_string(path).unwrap();
+            let err = verify(&key, &archive, &signature).unwrap_err();
+            assert!(err.to_string().contains("does not contain a file name"));
+        }
+
+        #[test]
+        fn extract_fixture() {
+            let (_, archive, _) = fixture();
+            let dir = tempfile::tempdir().unwrap();
+            let dest = dir.path().join("rustic");
+            extract_binary(&archive, &dest).unwrap();
+            let content = fs::read_to_string(dest).unwrap();
+            assert!(content.contains("rustic v0.99.0"));
+        }
+
+        #[rstest]
+        #[case(FIXTURE_NAME, "x86_64-unknown-linux-gnu", Some("0.99.0"))]
+        #[case(
+            "rustic-v0.12.0-beta.1-aarch64-apple-darwin.tar.gz",
+            "aarch64-apple-darwin",
+            Some("0.12.0-beta.1")
+        )]
+        #[case(FIXTURE_NAME, "aarch64-apple-darwin", None)]
+        #[case(
+            "restic-v0.99.0-x86_64-unknown-linux-gnu.tar.gz",
+            "x86_64-unknown-linux-gnu",
+            None
+        )]
+        fn version_of_asset(
+            #[case] name: &str,
+            #[case] target: &str,
+            #[case] expected: Option<&str>,
+        ) {
+            let version = asset_version(name, target).ok();
+            assert_eq!(version, expected.map(|v| Version::parse(v).unwrap()));
+        }
+    }
+}
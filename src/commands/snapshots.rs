@@ -2,6 +2,8 @@
 
 use crate::{
     Application, RUSTIC_APP,
+    annotations::{annotations, formatln, plain_tags},
+    commands::backup::{plain_description, read_log},
     helpers::{bold_cell, bytes_size_to_string, table, table_right_from},
     repository::{OpenRepo, get_global_grouped_snapshots},
     status_err,
@@ -13,6 +15,7 @@ use comfy_table::Cell;
 use derive_more::From;
 use itertools::Itertools;
 use jiff::SignedDuration;
+use log::info;
 
 use rustic_core::{
     Group, ProgressBars, ProgressType, SnapshotGroup,
@@ -44,6 +47,10 @@ pub(crate) struct SnapshotCmd {
     #[clap(long, conflicts_with_all = &["long", "json"])]
     all: bool,
 
+    /// Show the warnings and errors logged during the backup of the given snapshots (see `backup --capture-log`)
+    #[clap(long, requires = "ids", conflicts_with_all = &["long", "json", "all"])]
+    log: bool,
+
     #[cfg(feature = "tui")]
     /// Run in interactive UI mode
     #[clap(long, short)]
@@ -88,6 +95,10 @@ impl SnapshotCmd {
             });
         }
 
+        if self.log {
+            return show_logs(repo, &self.ids);
+        }
+
         let groups = get_global_grouped_snapshots(&repo, &self.ids)?.groups;
 
         if self.json {
@@ -125,6 +136,25 @@ impl SnapshotCmd {
     }
 }
 
+/// Print the logs saved within the given snapshots
+fn show_logs(repo: OpenRepo, ids: &[String]) -> Result<()> {
+    let config = RUSTIC_APP.config();
+    let snapshots = repo.get_snapshots_from_strs(ids, |sn| config.snapshot_filter.matches(sn))?;
+    let with_header = snapshots.len() > 1;
+    for snap in snapshots {
+        match read_log(&snap) {
+            Some(log) => {
+                if with_header {
+                    println!("log of snapshot {}:", snap.id);
+                }
+                print!("{log}");
+            }
+            None => info!("snapshot {} has no saved log.", snap.id),
+        }
+    }
+    Ok(())
+}
+
 pub fn print_snapshots(snapshots: Vec<SnapshotFile>, long: bool, all: bool) {
     let count = snapshots.len();
     if long {
@@ -167,7 +197,7 @@ pub fn print_snapshots(snapshots: Vec<SnapshotFile>, long: bool, all: bool) {
 
 pub fn snap_to_table(sn: &SnapshotFile, count: usize) -> [String; 9] {
     let config = RUSTIC_APP.config();
-    let tags = sn.tags.formatln();
+    let tags = plain_tags(&sn.tags).formatln();
     let paths = sn.paths.formatln();
     let time = config.global.format_time(&sn.time);
     let (files, dirs, size) = sn.summary.as_ref().map_or_else(
@@ -210,7 +240,11 @@ pub fn fill_table(snap: &SnapshotFile, mut add_entry: impl FnMut(&str, String))
     add_entry("Generated by", snap.program_version.clone());
     add_entry("Host", snap.hostname.clone());
     add_entry("Label", snap.label.clone());
-    add_entry("Tags", snap.tags.formatln());
+    add_entry("Tags", plain_tags(&snap.tags).formatln());
+    let annotations = annotations(&snap.tags);
+    if !annotations.is_empty() {
+        add_entry("Annotations", formatln(&annotations));
+    }
     let delete = match &snap.delete {
         DeleteOption::NotSet => "not set".to_string(),
         DeleteOption::Never => "never".to_string(),
@@ -275,7 +309,7 @@ pub fn fill_table(snap: &SnapshotFile, mut add_entry: impl FnMut(&str, String))
         );
         add_entry("Duration", duration);
     }
-    if let Some(ref description) = snap.description {
-        add_entry("Description", description.clone());
+    if let Some(description) = plain_description(snap) {
+        add_entry("Description", description.to_string());
     }
 }
@@ -0,0 +1,585 @@
+//! `status` subcommand
+
+use std::{fmt::Write, fs, path::PathBuf};
+
+use crate::{
+    Application, RUSTIC_APP, RusticConfig,
+    repository::{OpenRepo, get_global_grouped_snapshots},
+    status_err,
+};
+
+use abscissa_core::{Command, FrameworkError, Runnable, Shutdown, config::Override};
+use anyhow::{Context, Result};
+use bytesize::ByteSize;
+use clap::ValueEnum;
+use conflate::Merge;
+use derive_more::Display;
+use directories::ProjectDirs;
+use jiff::{Span, Timestamp, Zoned};
+use log::warn;
+use rustic_core::{
+    Group, Open, Repository,
+    repofile::{DeleteOption, SnapshotFile},
+};
+use serde::{Deserialize, Serialize};
+use serde_with::{DisplayFromStr, serde_as};
+
+/// Number of previous snapshots used to determine the usual growth of a group
+const GROWTH_HISTORY: usize = 10;
+
+/// `status` subcommand
+#[serde_as]
+#[derive(Clone, Command, Default, Debug, clap::Parser, Serialize, Deserialize, Merge)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct StatusCmd {
+    /// Warn if the latest snapshot of a group is older than the given duration [default: 1d]
+    #[clap(long, value_name = "DURATION")]
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[merge(strategy=conflate::option::overwrite_none)]
+    warn_age: Option<Span>,
+
+    /// Critical if the latest snapshot of a group is older than the given duration [default: 2d]
+    #[clap(long, value_name = "DURATION")]
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[merge(strategy=conflate::option::overwrite_none)]
+    crit_age: Option<Span>,
+
+    /// Warn if the last successful `check` is older than the given duration. A failed last check is always critical.
+    ///
+    /// Check results are recorded locally, so only `check` runs on this machine are considered.
+    #[clap(long, value_name = "DURATION")]
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[merge(strategy=conflate::option::overwrite_none)]
+    check_age: Option<Span>,
+
+    /// Warn if the latest snapshot of a group added more than FACTOR times the average of the previous snapshots [default: 5]
+    #[clap(long, value_name = "FACTOR")]
+    #[merge(strategy=conflate::option::overwrite_none)]
+    growth_factor: Option<f64>,
+
+    /// Output format
+    #[clap(long, value_enum, default_value_t)]
+    #[serde(skip)]
+    #[merge(skip)]
+    output: OutputFormat,
+
+    /// Thresholds for specific groups, used within config file
+    #[clap(skip)]
+    #[merge(strategy=conflate::vec::append)]
+    groups: Vec<GroupThresholds>,
+}
+
+/// Freshness thresholds which apply to all groups whose latest snapshot matches hostname and label
+#[serde_as]
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct GroupThresholds {
+    /// Hostname to match
+    hostname: Option<String>,
+
+    /// Label to match
+    label: Option<String>,
+
+    /// Warn if the latest snapshot is older than the given duration
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    warn_age: Option<Span>,
+
+    /// Critical if the latest snapshot is older than the given duration
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    crit_age: Option<Span>,
+}
+
+impl GroupThresholds {
+    fn matches(&self, snap: &SnapshotFile) -> bool {
+        self.hostname.as_ref().is_none_or(|h| h == &snap.hostname)
+            && self.label.as_ref().is_none_or(|l| l == &snap.label)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+enum OutputFormat {
+    /// Nagios/Icinga compatible plugin output
+    #[default]
+    Text,
+    /// JSON
+    Json,
+    /// Prometheus text format
+    Prometheus,
+}
+
+/// Status levels; the exit codes are compatible to Nagios/Icinga plugins
+#[derive(Clone, Copy, Debug, Display, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Level {
+    #[display("OK")]
+    Ok = 0,
+    #[display("WARNING")]
+    Warning = 1,
+    #[display("CRITICAL")]
+    Critical = 2,
+    #[display("UNKNOWN")]
+    Unknown = 3,
+}
+
+/// A problem found by `status`
+#[derive(Debug, Serialize)]
+struct Finding {
+    level: Level,
+    message: String,
+}
+
+/// Status of a snapshot group
+#[serde_as]
+#[derive(Debug, Serialize)]
+struct GroupStatus {
+    group: String,
+    status: Level,
+    snapshots: usize,
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    latest: Option<Timestamp>,
+    age_secs: Option<i64>,
+    #[serde(skip)]
+    warn_age_secs: Option<i64>,
+    #[serde(skip)]
+    crit_age_secs: Option<i64>,
+}
+
+/// Result of the status evaluation
+#[derive(Debug, Serialize)]
+struct StatusReport {
+    status: Level,
+    groups: Vec<GroupStatus>,
+    check: Option<CheckState>,
+    findings: Vec<Finding>,
+}
+
+impl StatusReport {
+    fn add(&mut self, level: Level, message: String) {
+        self.status = self.status.max(level);
+        self.findings.push(Finding { level, message });
+    }
+}
+
+impl Override<RusticConfig> for StatusCmd {
+    // Process the given command line options, overriding settings from
+    // a configuration file using explicit flags taken from command-line
+    // arguments.
+    fn override_config(&self, mut config: RusticConfig) -> Result<RusticConfig, FrameworkError> {
+        let mut self_config = self.clone();
+        // merge "status" section from config file, if given
+        self_config.merge(config.status);
+        config.status = self_config;
+        Ok(config)
+    }
+}
+
+impl Runnable for StatusCmd {
+    fn run(&self) {
+        let config = RUSTIC_APP.config();
+        let status = match config.repository.run_open(|repo| self.inner_run(repo)) {
+            Ok(status) => status,
+            Err(err) => {
+                status_err!("{}", err);
+                // plugin output must start with a single summary line
+                let summary = err.to_string();
+                let summary = summary.lines().next().unwrap_or_default();
+                println!("RUSTIC {} - {summary}", Level::Unknown);
+                Level::Unknown
+            }
+        };
+        RUSTIC_APP.shutdown_with_exitcode(Shutdown::Graceful, status as i32);
+    }
+}
+
+impl StatusCmd {
+    fn inner_run(&self, repo: OpenRepo) -> Result<Level> {
+        let config = RUSTIC_APP.config();
+        let opts = &config.status;
+        let now = Zoned::now();
+        let threshold_time =
+            |span: Span| -> Result<Timestamp> { Ok(now.checked_sub(span.abs())?.timestamp()) };
+
+        let mut report = StatusReport {
+            status: Level::Ok,
+            groups: Vec::new(),
+            check: None,
+            findings: Vec::new(),
+        };
+
+        let groups = get_global_grouped_snapshots(&repo, &[])?.groups;
+        if groups.iter().all(|group| group.items.is_empty()) {
+            report.add(Level::Critical, "no snapshots found".to_string());
+        }
+
+        let mut expired = 0;
+        for Group { group_key, items } in groups {
+            let Some(latest) = items.iter().max_by_key(|snap| &snap.time) else {
+                continue;
+            };
+            let (warn_age, crit_age) = opts.age_thresholds(latest);
+
+            let group = if group_key.is_empty() {
+                "all snapshots".to_string()
+            } else {
+                group_key.to_string()
+            };
+            let time = latest.time.timestamp();
+            let (warn_time, crit_time) = (threshold_time(warn_age)?, threshold_time(crit_age)?);
+            let status = age_level(time, warn_time, crit_time);
+            if status != Level::Ok {
+                report.add(
+                    status,
+                    format!(
+                        "{group}: latest snapshot {} is from {}",
+                        latest.id,
+                        config.global.format_time(&latest.time)
+                    ),
+                );
+            }
+
+            if let Some(message) = unusual_growth(&items, latest, opts.growth_factor.unwrap_or(5.0))
+            {
+                report.add(Level::Warning, format!("{group}: {message}"));
+            }
+
+            expired += items
+                .iter()
+                .filter(|snap| {
+                    matches!(&snap.delete, DeleteOption::After(_)) && snap.must_delete(&now)
+                })
+                .count();
+
+            let age = |ts: Timestamp| now.timestamp().duration_since(ts).as_secs();
+            report.groups.push(GroupStatus {
+                group,
+                status,
+                snapshots: items.len(),
+                latest: Some(time),
+                age_secs: Some(age(time)),
+                warn_age_secs: Some(age(warn_time)),
+                crit_age_secs: Some(age(crit_time)),
+            });
+        }
+
+        if expired > 0 {
+            report.add(
+                Level::Warning,
+                format!(
+                    "{expired} snapshots are past their delete-after time and not yet removed by `forget`"
+                ),
+            );
+        }
+
+        let check = CheckState::load(&repo)?;
+        match &check {
+            Some(check) if !check.success => report.add(
+                Level::Critical,
+                format!(
+                    "last check from {} failed with {} errors",
+                    config.global.format_timestamp(check.time),
+                    check.errors
+                ),
+            ),
+            Some(check) => {
+                if let Some(span) = opts.check_age
+                    && check.time < threshold_time(span)?
+                {
+                    report.add(
+                        Level::Warning,
+                        format!(
+                            "last check is from {}",
+                            config.global.format_timestamp(check.time)
+                        ),
+                    );
+                }
+            }
+            None if opts.check_age.is_some() => {
+                report.add(Level::Warning, "no check has been recorded".to_string());
+            }
+            None => {}
+        }
+        report.check = check;
+
+        match opts.output {
+            OutputFormat::Text => print_text(&report),
+            OutputFormat::Json => {
+                serde_json::to_writer_pretty(std::io::stdout(), &report)?;
+                println!();
+            }
+            OutputFormat::Prometheus => print!("{}", prometheus_text(&report)?),
+        }
+        Ok(report.status)
+    }
+}
+
+impl StatusCmd {
+    /// The age thresholds `(warn, crit)` of the group with the given latest snapshot
+    fn age_thresholds(&self, latest: &SnapshotFile) -> (Span, Span) {
+        let thresholds = self.groups.iter().find(|t| t.matches(latest));
+        let warn_age = thresholds
+            .and_then(|t| t.warn_age)
+            .or(self.warn_age)
+            .unwrap_or_else(|| Span::new().days(1));
+        let crit_age = thresholds
+            .and_then(|t| t.crit_age)
+            .or(self.crit_age)
+            .unwrap_or_else(|| Span::new().days(2));
+        (warn_age, crit_age)
+    }
+}
+
+/// Status of a group given the time of its latest snapshot and the threshold times
+fn age_level(time: Timestamp, warn_time: Timestamp, crit_time: Timestamp) -> Level {
+    if time < crit_time {
+        Level::Critical
+    } else if time < warn_time {
+        Level::Warning
+    } else {
+        Level::Ok
+    }
+}
+
+/// Check if the latest snapshot added much more data than the previous snapshots of the group
+#[allow(clippy::cast_precision_loss)]
+fn unusual_growth(items: &[SnapshotFile], latest: &SnapshotFile, factor: f64) -> Option<String> {
+    let added = latest.summary.as_ref()?.data_added_packed;
+    let mut previous: Vec<_> = items
+        .iter()
+        .filter(|snap| snap.time < latest.time)
+        .filter_map(|snap| Some((&snap.time, snap.summary.as_ref()?.data_added_packed)))
+        .collect();
+    previous.sort_unstable_by(|(t1, _), (t2, _)| t2.cmp(t1));
+    previous.truncate(GROWTH_HISTORY);
+    if previous.is_empty() {
+        return None;
+    }
+    let average =
+        previous.iter().map(|(_, size)| *size).sum::<u64>() as f64 / previous.len() as f64;
+    (average > 0.0 && added as f64 > factor * average).then(|| {
+        format!(
+            "latest snapshot {} added {}, the average of the previous snapshots is {}",
+            latest.id,
+            ByteSize(added).display().iec(),
+            ByteSize(average as u64).display().iec()
+        )
+    })
+}
+
+/// Print the report in the format of a Nagios/Icinga plugin
+fn print_text(report: &StatusReport) {
+    let summary = if report.findings.is_empty() {
+        format!("{} snapshot groups are fresh", report.groups.len())
+    } else {
+        report
+            .findings
+            .iter()
+            .map(|finding| finding.message.as_str())
+            .collect::<Vec<_>>()
+            .join("; ")
+    };
+    let perfdata = report
+        .groups
+        .iter()
+        .filter_map(|group| {
+            Some(format!(
+                "'{} age'={}s;{};{};0",
+                group.group.replace('\'', "\""),
+                group.age_secs?,
+                group.warn_age_secs?,
+                group.crit_age_secs?
+            ))
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    println!("RUSTIC {} - {summary} | {perfdata}", report.status);
+    for finding in &report.findings {
+        println!("{}: {}", finding.level, finding.message);
+    }
+}
+
+/// Format the report in the Prometheus text format
+#[allow(clippy::cast_precision_loss)]
+fn prometheus_text(report: &StatusReport) -> Result<String> {
+    let escape = |s: &str| {
+        s.replace('\\', r"\\")
+            .replace('"', "\\\"")
+            .replace('\n', r"\n")
+    };
+    let mut out = String::new();
+    let mut metric = |name: &str, help: &str, values: Vec<(String, f64)>| -> Result<()> {
+        writeln!(out, "# HELP {name} {help}")?;
+        writeln!(out, "# TYPE {name} gauge")?;
+        for (labels, value) in values {
+            writeln!(out, "{name}{labels} {value}")?;
+        }
+        Ok(())
+    };
+    let group_label = |group: &GroupStatus| format!("{{group=\"{}\"}}", escape(&group.group));
+
+    metric(
+        "rustic_status",
+        "Overall status: 0 = ok, 1 = warning, 2 = critical, 3 = unknown",
+        vec![(String::new(), f64::from(report.status as u8))],
+    )?;
+    metric(
+        "rustic_status_group",
+        "Status of the snapshot group: 0 = ok, 1 = warning, 2 = critical",
+        report
+            .groups
+            .iter()
+            .map(|group| (group_label(group), f64::from(group.status as u8)))
+            .collect(),
+    )?;
+    metric(
+        "rustic_status_group_snapshots",
+        "Number of snapshots in the group",
+        report
+            .groups
+            .iter()
+            .map(|group| (group_label(group), group.snapshots as f64))
+            .collect(),
+    )?;
+    metric(
+        "rustic_status_group_latest_age",
+        "Age of the latest snapshot of the group in seconds",
+        report
+            .groups
+            .iter()
+            .filter_map(|group| Some((group_label(group), group.age_secs? as f64)))
+            .collect(),
+    )?;
+    if let Some(check) = &report.check {
+        metric(
+            "rustic_status_check_success",
+            "Whether the last check was successful",
+            vec![(String::new(), f64::from(u8::from(check.success)))],
+        )?;
+        metric(
+            "rustic_status_check_time",
+            "Timestamp of the last check",
+            vec![(String::new(), check.time.as_second() as f64)],
+        )?;
+    }
+    Ok(out)
+}
+
+/// Result of the last `check` run, saved locally to be evaluated by `status`
+///
+/// `rustic_core` can't store additional files in the repository, so the result is kept in the local
+/// data directory. Checks run on other machines are therefore not seen by `status`.
+#[serde_as]
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct CheckState {
+    #[serde_as(as = "DisplayFromStr")]
+    pub(crate) time: Timestamp,
+    pub(crate) success: bool,
+    pub(crate) errors: u64,
+    pub(crate) warnings: u64,
+    pub(crate) read_data: bool,
+}
+
+impl CheckState {
+    /// The file storing the last check result of the repository
+    fn path<S: Open>(repo: &Repository<S>) -> Option<PathBuf> {
+        ProjectDirs::from("", "", "rustic").map(|dirs| {
+            dirs.data_local_dir()
+                .join("check")
+                .join(format!("{}.json", repo.config().id))
+        })
+    }
+
+    fn load<S: Open>(repo: &Repository<S>) -> Result<Option<Self>> {
+        let Some(path) = Self::path(repo) else {
+            return Ok(None);
+        };
+        if !path.exists() {
+            return Ok(None);
+        }
+        let state = fs::read(&path).with_context(|| format!("reading {}", path.display()))?;
+        Ok(Some(serde_json::from_slice(&state)?))
+    }
+
+    /// Save the check result; failures are only reported as warning
+    pub(crate) fn save<S: Open>(&self, repo: &Repository<S>) {
+        let save = || -> Result<()> {
+            let Some(path) = Self::path(repo) else {
+                return Ok(());
+            };
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(&path, serde_json::to_vec(self)?)?;
+            Ok(())
+        };
+        if let Err(err) = save() {
+            warn!("error saving check result: {err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snap(time: &str, added: u64) -> SnapshotFile {
+        let mut snap = SnapshotFile {
+            time: time.parse().unwrap(),
+            ..Default::default()
+        };
+        let mut summary = rustic_core::repofile::SnapshotSummary::default();
+        summary.data_added_packed = added;
+        snap.summary = Some(summary);
+        snap
+    }
+
+    #[test]
+    fn group_thresholds_override_defaults() {
+        let cmd: StatusCmd = toml::from_str(
+            r#"
+            warn-age = "3h"
+            [[groups]]
+            hostname = "db"
+            crit-age = "1h"
+            "#,
+        )
+        .unwrap();
+        let mut latest = SnapshotFile::default();
+        let (warn, crit) = cmd.age_thresholds(&latest);
+        assert_eq!(warn.fieldwise(), Span::new().hours(3));
+        assert_eq!(crit.fieldwise(), Span::new().days(2));
+
+        latest.hostname = "db".to_string();
+        let (warn, crit) = cmd.age_thresholds(&latest);
+        assert_eq!(warn.fieldwise(), Span::new().hours(3));
+        assert_eq!(crit.fieldwise(), Span::new().hours(1));
+    }
+
+    #[test]
+    fn age_levels() {
+        let ts = |s: &str| s.parse::<Timestamp>().unwrap();
+        let (warn, crit) = (ts("2025-01-02T00:00:00Z"), ts("2025-01-01T00:00:00Z"));
+        assert_eq!(age_level(ts("2025-01-03T00:00:00Z"), warn, crit), Level::Ok);
+        assert_eq!(
+            age_level(ts("2025-01-01T12:00:00Z"), warn, crit),
+            Level::Warning
+        );
+        assert_eq!(
+            age_level(ts("2024-12-31T00:00:00Z"), warn, crit),
+            Level::Critical
+        );
+        assert_eq!(Level::Ok as i32, 0);
+        assert_eq!(Level::Unknown as i32, 3);
+    }
+
+    #[test]
+    fn growth() {
+        let items = [
+            snap("2025-01-01T00:00:00Z[UTC]", 100),
+            snap("2025-01-02T00:00:00Z[UTC]", 200),
+            snap("2025-01-03T00:00:00Z[UTC]", 1000),
+        ];
+        assert!(unusual_growth(&items, &items[2], 5.0).is_some());
+        assert!(unusual_growth(&items, &items[2], 10.0).is_none());
+        assert!(unusual_growth(&items[..1], &items[0], 5.0).is_none());
+    }
+}
@@ -2,7 +2,10 @@
 use abscissa_core::{Command, Runnable};
 use rustic_core::{StringList, repofile::SnapshotModification};
 
-use crate::commands::rewrite::RewriteCmd;
+use crate::{
+    annotations::{AnnotatedModification, Annotation, parse_key},
+    commands::rewrite::RewriteCmd,
+};
 
 /// `tag` subcommand
 #[derive(clap::Parser, Command, Debug)]
@@ -34,20 +37,32 @@ pub(crate) struct TagCmd {
         help_heading = "Tag options"
     )]
     set: Vec<StringList>,
+
+    /// Set the annotation KEY to VALUE (can be specified multiple times)
+    #[clap(long, value_name = "KEY=VALUE", help_heading = "Annotation options")]
+    annotate: Vec<Annotation>,
+
+    /// Remove the annotation with the given KEY (can be specified multiple times)
+    #[clap(long, value_name = "KEY", value_parser = parse_key, help_heading = "Annotation options")]
+    remove_annotation: Vec<String>,
 }
 
 impl Runnable for TagCmd {
     fn run(&self) {
-        let modification = SnapshotModification::default()
-            .add_tags(self.add.clone())
-            .remove_tags(self.remove.clone())
-            .set_tags(self.set.clone());
+        let modification = AnnotatedModification {
+            modification: SnapshotModification::default()
+                .add_tags(self.add.clone())
+                .remove_tags(self.remove.clone())
+                .set_tags(self.set.clone()),
+            annotate: self.annotate.clone(),
+            remove_annotation: self.remove_annotation.clone(),
+        };
         let rewrite = RewriteCmd {
             ids: self.ids.clone(),
             modification,
             forget: true,
             ..Default::default()
         };
-        rewrite.run();
+        rewrite.run_as("tag");
     }
 }
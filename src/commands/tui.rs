@@ -1,4 +1,5 @@
 //! `tui` subcommand
+mod content_diff;
 mod diff;
 mod ls;
 mod progress;
@@ -6,6 +7,7 @@ mod restore;
 mod snapshots;
 pub mod summary;
 mod tree;
+mod viewer;
 mod widgets;
 
 pub use diff::Diff;
@@ -0,0 +1,714 @@
+//! Content diff of two versions of a file contained in snapshots.
+//!
+//! Text files are compared line by line and shown either side-by-side or as unified diff.
+//! For binary (or very large) files, statistics about changed chunks are shown instead;
+//! those are computed from the content blob lists of the two nodes without reading any data.
+
+use std::collections::BTreeSet;
+
+use anyhow::Result;
+use crossterm::event::{Event, KeyCode, KeyEventKind};
+use ratatui::{
+    prelude::*,
+    widgets::{Block, Borders, Paragraph},
+};
+use rustic_core::{DataId, repofile::Node};
+use style::palette::tailwind;
+
+use crate::{
+    commands::tui::{
+        viewer::{expand_tabs, is_text},
+        widgets::{Draw, PopUpText, popup_text},
+    },
+    helpers::bytes_size_to_string,
+    repository::IndexedRepo,
+};
+
+// the states this screen can be in
+enum CurrentScreen {
+    View,
+    ShowHelp(Box<PopUpText>),
+}
+
+const INFO_TEXT: &str = "(Esc) close | (n/N) next/previous hunk | (u) toggle unified/side-by-side | (?) show all commands";
+
+const HELP_TEXT: &str = r"
+Content Diff Commands:
+
+      Up,Down : scroll one line
+  PgUp,PgDown : scroll one page
+   Left,Right : scroll horizontally
+     Home,End : go to start/end of the diff
+          n,] : go to next hunk
+          N,[ : go to previous hunk
+            u : toggle between side-by-side and unified view
+
+General Commands:
+
+        q,Esc : close content diff
+            ? : show this help page
+
+ ";
+
+/// Maximum file size for which a line-based diff is computed
+const MAX_TEXT_SIZE: u64 = 8 * 1024 * 1024;
+/// Maximum edit distance the diff algorithm searches for; beyond this, the
+/// differing part is shown as completely replaced
+const MAX_EDIT_DISTANCE: usize = 2000;
+
+/// A single edit of a line-based diff
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Edit {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// Compute a shortest edit script transforming `a` into `b`
+fn diff<T: PartialEq>(a: &[T], b: &[T]) -> Vec<Edit> {
+    let prefix = a.iter().zip(b).take_while(|(a, b)| a == b).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (a_mid, b_mid) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+
+    let mut edits = vec![Edit::Equal; prefix];
+    if let Some(mid) = myers(a_mid, b_mid) {
+        edits.extend(mid);
+    } else {
+        edits.extend(std::iter::repeat_n(Edit::Delete, a_mid.len()));
+        edits.extend(std::iter::repeat_n(Edit::Insert, b_mid.len()));
+    }
+    edits.extend(std::iter::repeat_n(Edit::Equal, suffix));
+    edits
+}
+
+/// Myers' O(ND) diff algorithm; returns `None` if the edit distance exceeds `MAX_EDIT_DISTANCE`
+#[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+fn myers<T: PartialEq>(a: &[T], b: &[T]) -> Option<Vec<Edit>> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max_d = (a.len() + b.len()).min(MAX_EDIT_DISTANCE) as isize;
+    let offset = max_d + 1;
+    let mut v = vec![0_isize; 2 * max_d as usize + 3];
+    // trace[d] contains v[-d..=d] after step d
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    let same = |x: isize, y: isize| a[x as usize] == b[y as usize];
+    let mut found = None;
+    'outer: for d in 0..=max_d {
+        for k in (-d..=d).step_by(2) {
+            let idx = (offset + k) as usize;
+            let mut x = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+                v[idx + 1]
+            } else {
+                v[idx - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && same(x, y) {
+                x += 1;
+                y += 1;
+            }
+            v[idx] = x;
+            if x >= n && y >= m {
+                trace.push(v[(offset - d) as usize..=(offset + d) as usize].to_vec());
+                found = Some(d);
+                break 'outer;
+            }
+        }
+        trace.push(v[(offset - d) as usize..=(offset + d) as usize].to_vec());
+    }
+    let found = found?;
+
+    // backtrack the edit script
+    let mut edits = Vec::with_capacity((n + m) as usize);
+    let (mut x, mut y) = (n, m);
+    for d in (1..=found).rev() {
+        let prev = &trace[d as usize - 1];
+        let get = |k: isize| prev[(k + d - 1) as usize];
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && get(k - 1) < get(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = get(prev_k);
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            edits.push(Edit::Equal);
+            x -= 1;
+            y -= 1;
+        }
+        if x == prev_x {
+            edits.push(Edit::Insert);
+        } else {
+            edits.push(Edit::Delete);
+        }
+        (x, y) = (prev_x, prev_y);
+    }
+    edits.extend(std::iter::repeat_n(Edit::Equal, x as usize));
+    edits.reverse();
+    Some(edits)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum RowKind {
+    Equal,
+    Changed,
+}
+
+/// A displayed row of the diff referencing lines of the left and/or right file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Row {
+    left: Option<usize>,
+    right: Option<usize>,
+    kind: RowKind,
+}
+
+impl Row {
+    const fn equal(left: usize, right: usize) -> Self {
+        Self {
+            left: Some(left),
+            right: Some(right),
+            kind: RowKind::Equal,
+        }
+    }
+    const fn changed(left: Option<usize>, right: Option<usize>) -> Self {
+        Self {
+            left,
+            right,
+            kind: RowKind::Changed,
+        }
+    }
+}
+
+/// Build rows from the edit script; side-by-side rows pair deleted and inserted lines
+fn rows(edits: &[Edit], side_by_side: bool) -> Vec<Row> {
+    let mut rows = Vec::new();
+    let (mut l, mut r) = (0, 0);
+    let mut deleted = Vec::new();
+    let mut inserted = Vec::new();
+    let flush = |rows: &mut Vec<Row>, deleted: &mut Vec<usize>, inserted: &mut Vec<usize>| {
+        if side_by_side {
+            for i in 0..deleted.len().max(inserted.len()) {
+                rows.push(Row::changed(
+                    deleted.get(i).copied(),
+                    inserted.get(i).copied(),
+                ));
+            }
+        } else {
+            rows.extend(deleted.iter().map(|l| Row::changed(Some(*l), None)));
+            rows.extend(inserted.iter().map(|r| Row::changed(None, Some(*r))));
+        }
+        deleted.clear();
+        inserted.clear();
+    };
+    for edit in edits {
+        match edit {
+            Edit::Equal => {
+                flush(&mut rows, &mut deleted, &mut inserted);
+                rows.push(Row::equal(l, r));
+                l += 1;
+                r += 1;
+            }
+            Edit::Delete => {
+                deleted.push(l);
+                l += 1;
+            }
+            Edit::Insert => {
+                inserted.push(r);
+                r += 1;
+            }
+        }
+    }
+    flush(&mut rows, &mut deleted, &mut inserted);
+    rows
+}
+
+/// Indices of the rows starting a hunk, i.e. a sequence of changed rows
+fn hunks(rows: &[Row]) -> Vec<usize> {
+    rows.iter()
+        .enumerate()
+        .filter(|(i, row)| {
+            row.kind == RowKind::Changed && (*i == 0 || rows[i - 1].kind == RowKind::Equal)
+        })
+        .map(|(i, _)| i)
+        .collect()
+}
+
+struct TextDiff {
+    left: Vec<String>,
+    right: Vec<String>,
+    edits: Vec<Edit>,
+    rows: Vec<Row>,
+    hunks: Vec<usize>,
+}
+
+impl TextDiff {
+    fn new(left: &str, right: &str, side_by_side: bool) -> Self {
+        let left: Vec<_> = left.lines().map(expand_tabs).collect();
+        let right: Vec<_> = right.lines().map(expand_tabs).collect();
+        let edits = diff(&left, &right);
+        let rows = rows(&edits, side_by_side);
+        let hunks = hunks(&rows);
+        Self {
+            left,
+            right,
+            edits,
+            rows,
+            hunks,
+        }
+    }
+
+    fn set_side_by_side(&mut self, side_by_side: bool) {
+        self.rows = rows(&self.edits, side_by_side);
+        self.hunks = hunks(&self.rows);
+    }
+}
+
+/// Statistics about the changed chunks of two versions of a file
+#[derive(Debug, Default, PartialEq, Eq)]
+struct ChunkStats {
+    left_chunks: usize,
+    right_chunks: usize,
+    shared_chunks: usize,
+    removed_chunks: usize,
+    removed_size: u64,
+    added_chunks: usize,
+    added_size: u64,
+    /// Byte ranges of the right file which are not contained in the left file
+    changed_ranges: Vec<(u64, u64)>,
+}
+
+impl ChunkStats {
+    fn new(
+        left: &[DataId],
+        right: &[DataId],
+        size: impl Fn(&DataId) -> Result<u64>,
+    ) -> Result<Self> {
+        let left_ids: BTreeSet<_> = left.iter().collect();
+        let right_ids: BTreeSet<_> = right.iter().collect();
+        let mut stats = Self {
+            left_chunks: left.len(),
+            right_chunks: right.len(),
+            shared_chunks: left_ids.intersection(&right_ids).count(),
+            ..Default::default()
+        };
+        for id in left_ids.difference(&right_ids) {
+            stats.removed_chunks += 1;
+            stats.removed_size += size(id)?;
+        }
+        for id in right_ids.difference(&left_ids) {
+            stats.added_chunks += 1;
+            stats.added_size += size(id)?;
+        }
+
+        let mut offset = 0;
+        for id in right {
+            let len = size(id)?;
+            if !left_ids.contains(id) {
+                match stats.changed_ranges.last_mut() {
+                    Some((_, end)) if *end == offset => *end += len,
+                    _ => stats.changed_ranges.push((offset, offset + len)),
+                }
+            }
+            offset += len;
+        }
+        Ok(stats)
+    }
+
+    fn lines(&self, reason: &str) -> Vec<Line<'static>> {
+        let mut lines = vec![
+            Line::from(reason.to_string()),
+            Line::from(""),
+            Line::from(format!(
+                "chunks:  {} -> {} ({} unchanged)",
+                self.left_chunks, self.right_chunks, self.shared_chunks
+            )),
+            Line::styled(
+                format!(
+                    "removed: {} chunks, {}",
+                    self.removed_chunks,
+                    bytes_size_to_string(self.removed_size)
+                ),
+                Style::default().fg(Color::Red),
+            ),
+            Line::styled(
+                format!(
+                    "added:   {} chunks, {}",
+                    self.added_chunks,
+                    bytes_size_to_string(self.added_size)
+                ),
+                Style::default().fg(Color::Green),
+            ),
+            Line::from(""),
+        ];
+        if self.changed_ranges.is_empty() {
+            lines.push(Line::from("no changed regions in new file"));
+        } else {
+            lines.push(Line::from("changed regions in new file:"));
+            lines.extend(self.changed_ranges.iter().map(|(start, end)| {
+                Line::from(format!(
+                    "  {start:#012x} - {end:#012x} ({})",
+                    bytes_size_to_string(end - start)
+                ))
+            }));
+        }
+        lines
+    }
+}
+
+enum Content {
+    Text(TextDiff),
+    Binary(Vec<Line<'static>>),
+}
+
+pub struct ContentDiff {
+    current_screen: CurrentScreen,
+    title_left: String,
+    title_right: String,
+    content: Content,
+    side_by_side: bool,
+    top: usize,
+    left: usize,
+    height: usize,
+}
+
+impl ContentDiff {
+    pub fn new(
+        repo: &IndexedRepo,
+        left: &Node,
+        right: &Node,
+        title_left: String,
+        title_right: String,
+    ) -> Result<Self> {
+        let read = |node: &Node| -> Result<Option<String>> {
+            if node.meta.size > MAX_TEXT_SIZE {
+                return Ok(None);
+            }
+            let data = repo
+                .open_file(node)?
+                .read_at(repo, 0, node.meta.size.try_into()?)?;
+            Ok(is_text(&data, true).then(|| String::from_utf8_lossy(&data).to_string()))
+        };
+
+        let content = match (read(left)?, read(right)?) {
+            (Some(l), Some(r)) => Content::Text(TextDiff::new(&l, &r, true)),
+            _ => {
+                let reason = if left.meta.size > MAX_TEXT_SIZE || right.meta.size > MAX_TEXT_SIZE {
+                    format!(
+                        "file too large for a text diff (> {})",
+                        bytes_size_to_string(MAX_TEXT_SIZE)
+                    )
+                } else {
+                    "binary file".to_string()
+                };
+                let ids = |node: &Node| node.content.clone().unwrap_or_default();
+                let stats = ChunkStats::new(&ids(left), &ids(right), |id| {
+                    Ok(repo.get_index_entry(id)?.data_length().into())
+                })?;
+                Content::Binary(stats.lines(&reason))
+            }
+        };
+
+        Ok(Self {
+            current_screen: CurrentScreen::View,
+            title_left,
+            title_right,
+            content,
+            side_by_side: true,
+            top: 0,
+            left: 0,
+            height: 1,
+        })
+    }
+
+    fn rows(&self) -> usize {
+        match &self.content {
+            Content::Text(diff) => diff.rows.len(),
+            Content::Binary(lines) => lines.len(),
+        }
+    }
+
+    fn scroll_to(&mut self, row: usize) {
+        self.top = row.min(self.rows().saturating_sub(self.height));
+    }
+
+    fn next_hunk(&mut self, forward: bool) {
+        let Content::Text(diff) = &self.content else {
+            return;
+        };
+        let hunk = if forward {
+            diff.hunks.iter().find(|h| **h > self.top)
+        } else {
+            diff.hunks.iter().rev().find(|h| **h < self.top)
+        };
+        if let Some(&hunk) = hunk {
+            // show some context before the hunk
+            self.scroll_to(hunk.saturating_sub(3).max(usize::from(hunk > 0)));
+            self.top = self.top.min(hunk);
+        }
+    }
+
+    fn toggle_side_by_side(&mut self) {
+        self.side_by_side = !self.side_by_side;
+        if let Content::Text(diff) = &mut self.content {
+            // keep position within the file
+            let line = diff.rows.get(self.top).and_then(|row| row.left);
+            diff.set_side_by_side(self.side_by_side);
+            let row = line
+                .and_then(|line| diff.rows.iter().position(|row| row.left == Some(line)))
+                .unwrap_or_default();
+            self.scroll_to(row);
+        }
+    }
+
+    fn status(&self) -> String {
+        match &self.content {
+            Content::Text(diff) => {
+                let hunk = diff.hunks.partition_point(|h| *h <= self.top);
+                format!(
+                    "row {}/{} - hunk {hunk}/{}",
+                    self.top + 1,
+                    diff.rows.len(),
+                    diff.hunks.len()
+                )
+            }
+            Content::Binary(_) => "chunk statistics".to_string(),
+        }
+    }
+
+    /// Render a single line of the left or right file
+    fn render_line(
+        &self,
+        lines: &[String],
+        line: Option<usize>,
+        prefix: &'static str,
+        style: Style,
+        number_width: usize,
+    ) -> Line<'static> {
+        let number = line.map_or_else(String::new, |l| (l + 1).to_string());
+        let text: String = line
+            .map(|l| lines[l].chars().skip(self.left).collect())
+            .unwrap_or_default();
+        Line::from(vec![
+            Span::styled(
+                format!("{number:>number_width$} "),
+                Style::default().fg(Color::DarkGray),
+            ),
+            Span::styled(format!("{prefix}{text}"), style),
+        ])
+    }
+
+    pub fn input(&mut self, event: Event) -> bool {
+        use KeyCode::{Char, Down, End, Enter, Esc, Home, Left, PageDown, PageUp, Right, Up};
+        match &mut self.current_screen {
+            CurrentScreen::View => match event {
+                Event::Key(key) if key.kind == KeyEventKind::Press => match key.code {
+                    Esc | Char('q') => return true,
+                    Down | Char('j') => self.scroll_to(self.top + 1),
+                    Up | Char('k') => self.scroll_to(self.top.saturating_sub(1)),
+                    PageDown | Char(' ') => self.scroll_to(self.top + self.height),
+                    PageUp => self.scroll_to(self.top.saturating_sub(self.height)),
+                    Home | Char('g') => self.scroll_to(0),
+                    End | Char('G') => self.scroll_to(usize::MAX),
+                    Right => self.left += 8,
+                    Left => self.left = self.left.saturating_sub(8),
+                    Char('n' | ']') => self.next_hunk(true),
+                    Char('N' | '[') => self.next_hunk(false),
+                    Char('u') => self.toggle_side_by_side(),
+                    Char('?') => {
+                        self.current_screen =
+                            CurrentScreen::ShowHelp(Box::new(popup_text("help", HELP_TEXT.into())));
+                    }
+                    _ => {}
+                },
+                _ => {}
+            },
+            CurrentScreen::ShowHelp(_) => match event {
+                Event::Key(key) if key.kind == KeyEventKind::Press => {
+                    if matches!(key.code, Char('q' | ' ' | '?') | Esc | Enter) {
+                        self.current_screen = CurrentScreen::View;
+                    }
+                }
+                _ => {}
+            },
+        }
+        false
+    }
+
+    pub fn draw(&mut self, area: Rect, f: &mut Frame<'_>) {
+        let rects = Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).split(area);
+
+        let block = Block::new()
+            .borders(Borders::BOTTOM | Borders::TOP)
+            .title(format!("{} | {}", self.title_left, self.title_right))
+            .title_bottom(self.status())
+            .title_alignment(Alignment::Center);
+        let inner = block.inner(rects[0]);
+        f.render_widget(block, rects[0]);
+        self.height = usize::from(inner.height).max(1);
+
+        let removed = Style::default().fg(Color::Red);
+        let added = Style::default().fg(Color::Green);
+        match &self.content {
+            Content::Binary(lines) => {
+                let lines: Vec<_> = lines.iter().skip(self.top).cloned().collect();
+                f.render_widget(Paragraph::new(lines), inner);
+            }
+            Content::Text(diff) => {
+                let number_width = diff.left.len().max(diff.right.len()).to_string().len();
+                let visible = diff.rows.iter().skip(self.top).take(self.height);
+                if self.side_by_side {
+                    let mut left_lines = Vec::new();
+                    let mut right_lines = Vec::new();
+                    for row in visible {
+                        let (style_left, style_right) = match row.kind {
+                            RowKind::Equal => (Style::default(), Style::default()),
+                            RowKind::Changed => (removed, added),
+                        };
+                        left_lines.push(self.render_line(
+                            &diff.left,
+                            row.left,
+                            "",
+                            style_left,
+                            number_width,
+                        ));
+                        right_lines.push(self.render_line(
+                            &diff.right,
+                            row.right,
+                            "",
+                            style_right,
+                            number_width,
+                        ));
+                    }
+                    let halves =
+                        Layout::horizontal([Constraint::Ratio(1, 2), Constraint::Ratio(1, 2)])
+                            .split(inner);
+                    f.render_widget(
+                        Paragraph::new(left_lines).block(Block::new().borders(Borders::RIGHT)),
+                        halves[0],
+                    );
+                    f.render_widget(Paragraph::new(right_lines), halves[1]);
+                } else {
+                    let lines: Vec<_> = visible
+                        .map(|row| match (row.left, row.right) {
+                            (Some(l), Some(_)) => self.render_line(
+                                &diff.left,
+                                Some(l),
+                                " ",
+                                Style::default(),
+                                number_width,
+                            ),
+                            (Some(l), None) => {
+                                self.render_line(&diff.left, Some(l), "-", removed, number_width)
+                            }
+                            (None, r) => self.render_line(&diff.right, r, "+", added, number_width),
+                        })
+                        .collect();
+                    f.render_widget(Paragraph::new(lines), inner);
+                }
+            }
+        }
+
+        // draw the footer
+        let buffer_bg = tailwind::SLATE.c950;
+        let row_fg = tailwind::SLATE.c200;
+        let info_footer = Paragraph::new(Line::from(INFO_TEXT))
+            .style(Style::new().fg(row_fg).bg(buffer_bg))
+            .centered();
+        f.render_widget(info_footer, rects[1]);
+
+        // draw popups
+        match &mut self.current_screen {
+            CurrentScreen::View => {}
+            CurrentScreen::ShowHelp(popup) => popup.draw(area, f),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+    use rustic_core::Id;
+
+    use super::*;
+
+    /// Apply an edit script to `a`, taking inserted elements from `b`
+    fn apply(edits: &[Edit], a: &[char], b: &[char]) -> Vec<char> {
+        let (mut i, mut j) = (0, 0);
+        let mut result = Vec::new();
+        for edit in edits {
+            match edit {
+                Edit::Equal => {
+                    assert_eq!(a[i], b[j]);
+                    result.push(a[i]);
+                    i += 1;
+                    j += 1;
+                }
+                Edit::Delete => i += 1,
+                Edit::Insert => {
+                    result.push(b[j]);
+                    j += 1;
+                }
+            }
+        }
+        result
+    }
+
+    #[rstest]
+    #[case("", "", 0)]
+    #[case("abc", "abc", 0)]
+    #[case("abc", "", 3)]
+    #[case("", "abc", 3)]
+    #[case("abcabba", "cbabac", 5)]
+    #[case("abcdef", "abxdef", 2)]
+    fn diff_is_minimal_and_correct(#[case] a: &str, #[case] b: &str, #[case] distance: usize) {
+        let a: Vec<_> = a.chars().collect();
+        let b: Vec<_> = b.chars().collect();
+        let edits = diff(&a, &b);
+        assert_eq!(apply(&edits, &a, &b), b);
+        assert_eq!(
+            edits.iter().filter(|e| **e != Edit::Equal).count(),
+            distance
+        );
+    }
+
+    #[test]
+    fn rows_pair_changed_lines() {
+        let edits = [
+            Edit::Equal,
+            Edit::Delete,
+            Edit::Delete,
+            Edit::Insert,
+            Edit::Equal,
+        ];
+        assert_eq!(
+            rows(&edits, true),
+            vec![
+                Row::equal(0, 0),
+                Row::changed(Some(1), Some(1)),
+                Row::changed(Some(2), None),
+                Row::equal(3, 2),
+            ]
+        );
+        let unified = rows(&edits, false);
+        assert_eq!(unified.len(), 5);
+        assert_eq!(hunks(&unified), vec![1]);
+    }
+
+    #[test]
+    fn chunk_stats_are_computed() -> Result<()> {
+        let id = |i: u8| DataId::from(format!("{i:02x}").repeat(32).parse::<Id>().unwrap());
+        let left = [id(1), id(2), id(3)];
+        let right = [id(1), id(4), id(5), id(3)];
+        let stats = ChunkStats::new(&left, &right, |_| Ok(10))?;
+        assert_eq!(stats.shared_chunks, 2);
+        assert_eq!((stats.removed_chunks, stats.removed_size), (1, 10));
+        assert_eq!((stats.added_chunks, stats.added_size), (2, 20));
+        assert_eq!(stats.changed_ranges, vec![(10, 30)]);
+        Ok(())
+    }
+}
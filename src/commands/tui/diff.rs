@@ -25,6 +25,7 @@ use crate::{
 
 use super::{
     TuiResult,
+    content_diff::ContentDiff,
     summary::SummaryMap,
     widgets::{PopUpTable, popup_table},
 };
@@ -32,18 +33,19 @@ use super::{
 // the states this screen can be in
 enum CurrentScreen {
     Diff,
+    ContentDiff(Box<ContentDiff>),
     ShowHelp(PopUpText),
     Table(PopUpTable),
     PromptExit(PopUpPrompt),
     PromptLeave(PopUpPrompt),
 }
 
-const INFO_TEXT: &str =
-    "(Esc) quit | (Enter) enter dir | (Backspace) return to parent | (?) show all commands";
+const INFO_TEXT: &str = "(Esc) quit | (Enter) enter dir | (Backspace) return to parent | (v) view changes | (?) show all commands";
 
 const HELP_TEXT: &str = r"
 Diff Commands:
 
+          v : view content changes of selected file (side-by-side or unified)
           m : toggle ignoring metadata
           d : toggle show only different entries
           s : compute information for (sub-)dirs and show summary
@@ -424,6 +426,36 @@ impl<'a> Diff<'a> {
         Ok(popup_table("diff summary", rows))
     }
 
+    pub fn content_diff(&self) -> Result<Option<ContentDiff>> {
+        // viewing contents is not supported on cold repositories
+        if self.repo.config().is_hot == Some(true) {
+            return Ok(None);
+        }
+        let Some(DiffNode(EitherOrBoth::Both(left, right))) = self.selected_node() else {
+            return Ok(None);
+        };
+        if !left.is_file() || !right.is_file() {
+            return Ok(None);
+        }
+        let title_left = format!(
+            "{}:{}",
+            self.snapshot_left.id,
+            self.path_left.join(left.name()).display()
+        );
+        let title_right = format!(
+            "{}:{}",
+            self.snapshot_right.id,
+            self.path_right.join(right.name()).display()
+        );
+        Ok(Some(ContentDiff::new(
+            self.repo,
+            &left,
+            &right,
+            title_left,
+            title_right,
+        )?))
+    }
+
     pub fn snapshot_details(&self) -> PopUpTable {
         let mut rows = Vec::new();
         let mut rows_right = Vec::new();
@@ -482,10 +514,21 @@ impl<'a> ProcessEvent for Diff<'a> {
                     Char('I') => {
                         self.current_screen = CurrentScreen::Table(self.snapshot_details());
                     }
+                    Char('v') => {
+                        if let Some(content_diff) = self.content_diff()? {
+                            self.current_screen =
+                                CurrentScreen::ContentDiff(Box::new(content_diff));
+                        }
+                    }
                     _ => self.table.input(event),
                 },
                 _ => {}
             },
+            CurrentScreen::ContentDiff(content_diff) => {
+                if content_diff.input(event) {
+                    self.current_screen = CurrentScreen::Diff;
+                }
+            }
             CurrentScreen::Table(_) | CurrentScreen::ShowHelp(_) => match event {
                 Event::Key(key) if key.kind == KeyEventKind::Press => {
                     if matches!(key.code, Char('q' | ' ' | 'I' | '?') | Esc | Enter) {
@@ -515,20 +558,24 @@ impl<'a> Draw for Diff<'a> {
     fn draw(&mut self, area: Rect, f: &mut Frame<'_>) {
         let rects = Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).split(area);
 
-        // draw the table
-        self.table.draw(rects[0], f);
-
-        // draw the footer
-        let buffer_bg = tailwind::SLATE.c950;
-        let row_fg = tailwind::SLATE.c200;
-        let info_footer = Paragraph::new(Line::from(INFO_TEXT))
-            .style(Style::new().fg(row_fg).bg(buffer_bg))
-            .centered();
-        f.render_widget(info_footer, rects[1]);
+        if let CurrentScreen::ContentDiff(content_diff) = &mut self.current_screen {
+            content_diff.draw(area, f);
+        } else {
+            // draw the table
+            self.table.draw(rects[0], f);
+
+            // draw the footer
+            let buffer_bg = tailwind::SLATE.c950;
+            let row_fg = tailwind::SLATE.c200;
+            let info_footer = Paragraph::new(Line::from(INFO_TEXT))
+                .style(Style::new().fg(row_fg).bg(buffer_bg))
+                .centered();
+            f.render_widget(info_footer, rects[1]);
+        }
 
         // draw popups
         match &mut self.current_screen {
-            CurrentScreen::Diff => {}
+            CurrentScreen::Diff | CurrentScreen::ContentDiff(_) => {}
             CurrentScreen::Table(popup) => popup.draw(area, f),
             CurrentScreen::ShowHelp(popup) => popup.draw(area, f),
             CurrentScreen::PromptExit(popup) | CurrentScreen::PromptLeave(popup) => {
@@ -18,10 +18,10 @@ use crate::{
         tui::{
             TuiResult,
             restore::Restore,
+            viewer::Viewer,
             widgets::{
                 Draw, PopUpPrompt, PopUpTable, PopUpText, ProcessEvent, PromptResult, SelectTable,
-                TextInputResult, WithBlock, popup_prompt, popup_scrollable_text, popup_table,
-                popup_text,
+                WithBlock, popup_prompt, popup_table, popup_text,
             },
         },
     },
@@ -29,7 +29,7 @@ use crate::{
     repository::IndexedRepo,
 };
 
-use super::{summary::SummaryMap, widgets::PopUpInput};
+use super::summary::SummaryMap;
 
 // the states this screen can be in
 enum CurrentScreen<'a> {
@@ -39,7 +39,7 @@ enum CurrentScreen<'a> {
     Restore(Box<Restore<'a>>),
     PromptExit(PopUpPrompt),
     PromptLeave(PopUpPrompt),
-    ShowFile(Box<PopUpInput>),
+    ShowFile(Box<Viewer<'a>>),
 }
 
 const INFO_TEXT: &str = "(Esc) quit | (Enter) enter dir | (Backspace) return to parent | (v) view | (r) restore | (?) show all commands";
@@ -47,8 +47,8 @@ const INFO_TEXT: &str = "(Esc) quit | (Enter) enter dir | (Backspace) return to
 const HELP_TEXT: &str = r"
 Ls Commands:
 
-          v : view file contents (text files only, up to 1MiB; use arrow keys to scroll)
-          r : restore selected item
+          v : view file contents (full-screen viewer with search and hex view)
+          r : restore selected item (shows a preview of the changes at the destination)
           n : toggle numeric IDs
           s : compute information for (sub-)dirs and show summary
           S : compute information for selected node and show summary
@@ -327,24 +327,15 @@ impl<'a> ProcessEvent for Ls<'a> {
                         if self.repo.config().is_hot != Some(true)
                             && let Some(node) = self.selected_node()
                             && node.is_file()
-                            && let Ok(data) = self.repo.open_file(node)?.read_at(
-                                self.repo,
-                                0,
-                                node.meta.size.min(1_000_000).try_into().unwrap(),
-                            )
                         {
-                            // viewing is only supported for text files
-                            if let Ok(content) = String::from_utf8(data.to_vec()) {
-                                let lines = content.lines().count();
-                                let path = self.path.join(node.name());
-                                let path = path.display();
-                                self.current_screen =
-                                    CurrentScreen::ShowFile(Box::new(popup_scrollable_text(
-                                        format!("{}:/{path}", self.snapshot.id),
-                                        &content,
-                                        (lines + 1).min(40).try_into().unwrap(),
-                                    )));
-                            }
+                            let path = self.path.join(node.name());
+                            let path = path.display();
+                            let viewer = Viewer::new(
+                                self.repo,
+                                node,
+                                format!("{}:/{path}", self.snapshot.id),
+                            )?;
+                            self.current_screen = CurrentScreen::ShowFile(Box::new(viewer));
                         }
                     }
                     Char('r') => {
@@ -374,12 +365,11 @@ impl<'a> ProcessEvent for Ls<'a> {
                 },
                 _ => {}
             },
-            CurrentScreen::ShowFile(prompt) => match prompt.input(event) {
-                TextInputResult::Cancel | TextInputResult::Input(_) => {
+            CurrentScreen::ShowFile(viewer) => {
+                if viewer.input(event)? {
                     self.current_screen = CurrentScreen::Ls;
                 }
-                TextInputResult::None => {}
-            },
+            }
             CurrentScreen::Table(_) | CurrentScreen::ShowHelp(_) => match event {
                 Event::Key(key) if key.kind == KeyEventKind::Press => {
                     if matches!(key.code, Char('q' | ' ' | '?') | Esc | Enter) {
@@ -416,6 +406,8 @@ impl<'a> Draw for Ls<'a> {
 
         if let CurrentScreen::Restore(restore) = &mut self.current_screen {
             restore.draw(area, f);
+        } else if let CurrentScreen::ShowFile(viewer) = &mut self.current_screen {
+            viewer.draw(area, f);
         } else {
             // draw the table
             self.table.draw(rects[0], f);
@@ -431,13 +423,12 @@ impl<'a> Draw for Ls<'a> {
 
         // draw popups
         match &mut self.current_screen {
-            CurrentScreen::Ls | CurrentScreen::Restore(_) => {}
+            CurrentScreen::Ls | CurrentScreen::Restore(_) | CurrentScreen::ShowFile(_) => {}
             CurrentScreen::Table(popup) => popup.draw(area, f),
             CurrentScreen::ShowHelp(popup) => popup.draw(area, f),
             CurrentScreen::PromptExit(popup) | CurrentScreen::PromptLeave(popup) => {
                 popup.draw(area, f);
             }
-            CurrentScreen::ShowFile(popup) => popup.draw(area, f),
         }
     }
 }
@@ -1,12 +1,26 @@
+use std::{
+    collections::BTreeSet,
+    fmt::Display,
+    fs, iter,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::Duration,
+};
+
 use anyhow::Result;
-use crossterm::event::{Event, KeyCode, KeyEventKind};
-use ratatui::prelude::*;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::{
+    prelude::*,
+    widgets::{Block, Borders, Paragraph},
+};
 use rustic_core::{LocalDestination, LsOptions, RestoreOptions, RestorePlan, repofile::Node};
+use style::palette::tailwind;
 
 use crate::{
     commands::tui::widgets::{
-        Draw, PopUpInput, PopUpPrompt, PopUpText, ProcessEvent, PromptResult, TextInputResult,
-        popup_input, popup_prompt,
+        Draw, PopUpInput, PopUpPrompt, PopUpText, ProcessEvent, PromptResult, SelectTable,
+        TextInputResult, WithBlock, popup_input, popup_prompt,
     },
     helpers::bytes_size_to_string,
     repository::IndexedRepo,
@@ -16,71 +30,578 @@ use super::widgets::popup_text;
 
 // the states this screen can be in
 enum CurrentScreen {
-    GetDestination(PopUpInput),
-    PromptRestore(PopUpPrompt, Option<RestorePlan>),
+    GetDestination(Box<PopUpInput>),
+    Preview,
+    ShowHelp(PopUpText),
+    PromptRestore(PopUpPrompt),
     RestoreDone(PopUpText),
 }
 
-pub(crate) struct Restore<'a> {
-    current_screen: CurrentScreen,
+const INFO_TEXT: &str = "(Esc) cancel | (Space) toggle entry | (p) change policy for subtree | (Enter) restore | (?) show all commands";
+
+const HELP_TEXT: &str = r"
+Restore Preview Commands:
+
+      Space : toggle selected entry
+          a : select all entries to create or modify
+          A : deselect all entries to create or modify
+          p : change conflict policy for selected entry and all entries below
+              (for entries not contained in the snapshot: of the directory containing it)
+          i : show restore information
+
+  Conflict policies:
+
+  overwrite : create new and overwrite modified entries (default)
+       skip : only create new entries, don't touch existing ones
+     mirror : restore the whole directory and delete entries not contained in the snapshot
+
+  Entries not contained in the snapshot (-) are only deleted by the mirror policy
+  of the directory containing them and cannot be selected individually.
+
+General Commands:
+
+      Enter : restore all selected entries
+      q,Esc : cancel restore
+          ? : show this help page
+
+ ";
+
+/// The action a restore would perform for an entry of the destination
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Action {
+    /// Entry doesn't exist in destination and will be created
+    Create,
+    /// Entry exists in destination but differs
+    Modify,
+    /// Entry exists in destination and needs no restore
+    Unchanged,
+    /// Entry exists only in destination and may be deleted
+    Delete,
+}
+
+impl Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::Create => "+",
+            Self::Modify => "M",
+            Self::Unchanged => "=",
+            Self::Delete => "-",
+        };
+        f.write_str(s)
+    }
+}
+
+/// How to handle entries which already exist in the destination
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum ConflictPolicy {
+    #[default]
+    Overwrite,
+    Skip,
+    Mirror,
+}
+
+impl ConflictPolicy {
+    const fn next(self) -> Self {
+        match self {
+            Self::Overwrite => Self::Skip,
+            Self::Skip => Self::Mirror,
+            Self::Mirror => Self::Overwrite,
+        }
+    }
+
+    /// Whether an entry with the given action is restored (or deleted) by default
+    const fn selects(self, action: Action) -> bool {
+        match action {
+            Action::Create => true,
+            Action::Modify => !matches!(self, Self::Skip),
+            Action::Unchanged => false,
+            Action::Delete => matches!(self, Self::Mirror),
+        }
+    }
+}
+
+impl Display for ConflictPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::Overwrite => "overwrite",
+            Self::Skip => "skip",
+            Self::Mirror => "mirror",
+        };
+        f.write_str(s)
+    }
+}
+
+/// An entry of the restore preview
+struct PreviewEntry {
+    /// path relative to the restore destination
+    path: PathBuf,
+    /// the node to restore; `None` for entries which only exist in the destination
+    node: Option<Node>,
+    action: Action,
+    policy: ConflictPolicy,
+    selected: bool,
+}
+
+/// The entries of the restore preview together with their policies and selection
+#[derive(Default)]
+struct Preview {
+    /// the entries, sorted by path
+    entries: Vec<PreviewEntry>,
+    /// the policy for the entries of the destination directory which are not contained in the snapshot
+    root_policy: ConflictPolicy,
+}
+
+impl Preview {
+    /// Create the preview from the entries to restore and the entries only present in the destination
+    fn new(restore: Vec<(PathBuf, Node, Action)>, extra: Vec<PathBuf>) -> Self {
+        let policy = ConflictPolicy::default();
+        let mut entries: Vec<_> = restore
+            .into_iter()
+            .map(|(path, node, action)| PreviewEntry {
+                path,
+                node: Some(node),
+                action,
+                policy,
+                selected: policy.selects(action),
+            })
+            .chain(extra.into_iter().map(|path| PreviewEntry {
+                path,
+                node: None,
+                action: Action::Delete,
+                policy,
+                selected: false,
+            }))
+            .collect();
+        entries.sort_by(|e1, e2| e1.path.cmp(&e2.path));
+        Self {
+            entries,
+            root_policy: policy,
+        }
+    }
+
+    /// Whether the selection of the entry can be changed
+    ///
+    /// Entries with mirror policy are always restored as a whole, including the deletions.
+    fn selectable(entry: &PreviewEntry) -> bool {
+        matches!(entry.action, Action::Create | Action::Modify)
+            && entry.policy != ConflictPolicy::Mirror
+    }
+
+    fn toggle(&mut self, idx: usize) {
+        let entry = &mut self.entries[idx];
+        if Self::selectable(entry) {
+            entry.selected = !entry.selected;
+        }
+    }
+
+    fn select_all(&mut self, selected: bool) {
+        for entry in &mut self.entries {
+            if Self::selectable(entry) {
+                entry.selected = selected;
+            }
+        }
+    }
+
+    /// The policy of the entry with the given path; the empty path is the destination itself
+    fn policy(&self, path: &Path) -> ConflictPolicy {
+        // entries are sorted by path
+        self.entries
+            .binary_search_by(|entry| entry.path.as_path().cmp(path))
+            .map_or(self.root_policy, |idx| self.entries[idx].policy)
+    }
+
+    /// Change the conflict policy of the entry with index `idx` and all entries below it
+    ///
+    /// For entries which are not contained in the snapshot, the policy of the containing directory is changed.
+    fn change_policy(&mut self, idx: usize) {
+        let entry = &self.entries[idx];
+        let path = if entry.action == Action::Delete {
+            entry
+                .path
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_default()
+        } else {
+            entry.path.clone()
+        };
+        let policy = self.policy(&path).next();
+        if path.as_os_str().is_empty() {
+            self.root_policy = policy;
+        }
+        for entry in &mut self.entries {
+            if entry.path.starts_with(&path) {
+                entry.policy = policy;
+                entry.selected = policy.selects(entry.action);
+            }
+        }
+    }
+
+    /// The directories which are restored with mirror policy, i.e. whose parent doesn't use mirror policy
+    fn mirror_dirs(&self) -> Vec<PathBuf> {
+        if self.root_policy == ConflictPolicy::Mirror {
+            return vec![PathBuf::new()];
+        }
+        self.entries
+            .iter()
+            .filter(|entry| {
+                entry.policy == ConflictPolicy::Mirror
+                    && entry.node.as_ref().is_some_and(Node::is_dir)
+                    && entry
+                        .path
+                        .parent()
+                        .is_none_or(|parent| self.policy(parent) != ConflictPolicy::Mirror)
+            })
+            .map(|entry| entry.path.clone())
+            .collect()
+    }
+
+    /// The nodes to restore within the given mirror dir, relative to it
+    fn mirror_nodes(&self, dir: &Path) -> Vec<(PathBuf, Node)> {
+        self.entries
+            .iter()
+            .filter_map(|entry| {
+                let path = entry.path.strip_prefix(dir).ok()?;
+                if path.as_os_str().is_empty() {
+                    return None;
+                }
+                Some((path.to_path_buf(), entry.node.clone()?))
+            })
+            .collect()
+    }
+
+    /// The selected nodes which are not within one of the `mirror_dirs`, including all their parent dirs
+    fn selected_nodes(&self, mirror_dirs: &[PathBuf]) -> Vec<(PathBuf, Node)> {
+        let mut needed_dirs = BTreeSet::new();
+        for entry in &self.entries {
+            if entry.selected {
+                needed_dirs.extend(entry.path.ancestors().skip(1).map(Path::to_path_buf));
+            }
+        }
+
+        self.entries
+            .iter()
+            .filter(|entry| {
+                (entry.selected || needed_dirs.contains(&entry.path))
+                    && !mirror_dirs
+                        .iter()
+                        .any(|dir| entry.path.starts_with(dir) && &entry.path != dir)
+            })
+            .filter_map(|entry| Some((entry.path.clone(), entry.node.clone()?)))
+            .collect()
+    }
+
+    /// The number of selected entries with the given action
+    fn count(&self, action: Action) -> usize {
+        self.entries
+            .iter()
+            .filter(|e| e.selected && e.action == action)
+            .count()
+    }
+}
+
+/// What to restore and where to, shared with the thread computing the preview
+struct Target<'a> {
     repo: &'a IndexedRepo,
     opts: RestoreOptions,
     node: Node,
-    source: String,
     dest: String,
 }
 
+impl Target<'_> {
+    fn destination(&self) -> Result<LocalDestination> {
+        Ok(LocalDestination::new(
+            &self.dest,
+            true,
+            !self.node.is_dir(),
+        )?)
+    }
+
+    /// The path of an item within the destination, see `LocalDestination`
+    fn dest_path(&self, item: &Path) -> PathBuf {
+        let dest = Path::new(&self.dest);
+        if dest.is_file() || (!dest.is_dir() && !self.node.is_dir()) {
+            dest.to_path_buf()
+        } else {
+            dest.join(item)
+        }
+    }
+
+    /// The action a restore performs for the given node
+    ///
+    /// Existing files are classified by the dry-run restore plan of the single file, i.e. using the
+    /// criteria of `restore`. Existing dirs are kept and only get their metadata restored, other
+    /// existing nodes are always replaced.
+    fn action(&self, path: &Path, node: &Node) -> Result<Action> {
+        let dest = self.dest_path(path);
+        let Ok(meta) = fs::symlink_metadata(&dest) else {
+            return Ok(Action::Create);
+        };
+        if node.is_dir() && meta.is_dir() {
+            return Ok(Action::Unchanged);
+        }
+        if !node.is_file() || !meta.is_file() {
+            return Ok(Action::Modify);
+        }
+        let dest = LocalDestination::new(&dest.to_string_lossy(), false, true)?;
+        let ls = iter::once(Ok((PathBuf::new(), node.clone())));
+        let files = self
+            .repo
+            .prepare_restore(&self.opts, ls, &dest, true)?
+            .stats
+            .files;
+        Ok(match (files.restore, files.modify) {
+            (0, 0) => Action::Unchanged,
+            (0, _) => Action::Modify,
+            _ => Action::Create,
+        })
+    }
+
+    /// Compute the dry-run restore plan and the preview entries by comparing the nodes to restore
+    /// with the destination
+    ///
+    /// Returns `None` if canceled.
+    fn compute_preview(&self, cancel: &AtomicBool) -> Result<Option<(RestorePlan, Preview)>> {
+        // for restore, always recurse into tree
+        let mut ls_opts = LsOptions::default();
+        ls_opts.recursive = true;
+
+        let ls = self.repo.ls(&self.node, &ls_opts)?;
+        let plan = self
+            .repo
+            .prepare_restore(&self.opts, ls, &self.destination()?, true)?;
+
+        let mut restore = Vec::new();
+        let mut paths = BTreeSet::new();
+        for item in self.repo.ls(&self.node, &ls_opts)? {
+            if cancel.load(Ordering::Relaxed) {
+                return Ok(None);
+            }
+            let (path, node) = item?;
+            let action = self.action(&path, &node)?;
+            _ = paths.insert(path.clone());
+            restore.push((path, node, action));
+        }
+
+        // entries only present in the destination
+        let mut extra = Vec::new();
+        if self.node.is_dir() {
+            collect_extra_entries(Path::new(&self.dest), Path::new(""), &paths, &mut extra)?;
+        }
+
+        Ok(Some((plan, Preview::new(restore, extra))))
+    }
+}
+
+pub(crate) struct Restore<'a> {
+    current_screen: CurrentScreen,
+    target: Target<'a>,
+    source: String,
+    table: WithBlock<SelectTable>,
+    preview: Preview,
+    plan: Option<RestorePlan>,
+}
+
 impl<'a> Restore<'a> {
     pub fn new(repo: &'a IndexedRepo, node: Node, source: String, path: &str) -> Self {
         let opts = RestoreOptions::default();
         let title = format!("restore {source} to:");
         let popup = popup_input(title, "enter restore destination", path, 1);
+        let header = ["", "Action", "Policy", "Path", "Size"]
+            .into_iter()
+            .map(Text::from)
+            .collect();
         Self {
-            current_screen: CurrentScreen::GetDestination(popup),
-            node,
-            repo,
-            opts,
+            current_screen: CurrentScreen::GetDestination(Box::new(popup)),
+            target: Target {
+                repo,
+                opts,
+                node,
+                dest: String::new(),
+            },
             source,
-            dest: String::new(),
+            table: WithBlock::new(SelectTable::new(header), Block::new()),
+            preview: Preview::default(),
+            plan: None,
         }
     }
 
-    pub fn compute_plan(&mut self, mut dest: String, dry_run: bool) -> Result<RestorePlan> {
+    /// Compute the dry-run restore plan and the preview in a separate thread
+    ///
+    /// Computing both reads the contents of existing files; the computation can be canceled by
+    /// pressing Esc or q. Returns whether the preview has been computed.
+    fn compute(&mut self, mut dest: String) -> Result<bool> {
         if dest.is_empty() {
             dest = ".".to_string();
         }
-        self.dest = dest;
-        let dest = LocalDestination::new(&self.dest, true, !self.node.is_dir())?;
+        self.target.dest = dest;
 
-        // for restore, always recurse into tree
-        let mut ls_opts = LsOptions::default();
-        ls_opts.recursive = true;
+        let cancel = AtomicBool::new(false);
+        let computed = thread::scope(|s| {
+            let worker = s.spawn(|| self.target.compute_preview(&cancel));
+            while !worker.is_finished() {
+                if event::poll(Duration::from_millis(100))?
+                    && let Event::Key(key) = event::read()?
+                    && key.kind == KeyEventKind::Press
+                    && matches!(key.code, KeyCode::Esc | KeyCode::Char('q'))
+                {
+                    cancel.store(true, Ordering::Relaxed);
+                }
+            }
+            worker
+                .join()
+                .unwrap_or_else(|err| std::panic::resume_unwind(err))
+        })?;
+        let Some((plan, preview)) = computed else {
+            return Ok(false);
+        };
+        self.plan = Some(plan);
+        self.preview = preview;
+        self.table.widget.set_to(0);
+        self.update_table();
+        Ok(true)
+    }
 
-        let ls = self.repo.ls(&self.node, &ls_opts)?;
+    fn update_table(&mut self) {
+        let old_selection = if self.preview.entries.is_empty() {
+            None
+        } else {
+            Some(self.table.widget.selected().unwrap_or_default())
+        };
+        let rows = self
+            .preview
+            .entries
+            .iter()
+            .map(|entry| {
+                let selected = if entry.selected { "[x]" } else { "[ ]" };
+                let size = entry.node.as_ref().map_or_else(String::new, |node| {
+                    if node.is_dir() {
+                        String::new()
+                    } else {
+                        bytes_size_to_string(node.meta.size)
+                    }
+                });
+                let mut path = entry.path.display().to_string();
+                if entry.node.as_ref().is_some_and(Node::is_dir) {
+                    path.push('/');
+                }
+                let style = match entry.action {
+                    Action::Create => Style::new().fg(tailwind::GREEN.c400),
+                    Action::Modify => Style::new().fg(tailwind::YELLOW.c400),
+                    Action::Unchanged => Style::new(),
+                    Action::Delete => Style::new().fg(tailwind::RED.c400),
+                };
+                vec![
+                    Text::from(selected),
+                    Text::styled(entry.action.to_string(), style),
+                    Text::from(entry.policy.to_string()),
+                    Text::styled(path, style),
+                    Text::from(size),
+                ]
+            })
+            .collect();
+        self.table.widget.set_content(rows, 1);
+
+        self.table.block = Block::new()
+            .borders(Borders::BOTTOM | Borders::TOP)
+            .title(format!("restore {} to {}", self.source, self.target.dest))
+            .title_bottom(format!(
+                "selected: {} to create, {} to modify, {} to delete - total: {} entries",
+                self.preview.count(Action::Create),
+                self.preview.count(Action::Modify),
+                self.preview.count(Action::Delete),
+                self.preview.entries.len(),
+            ))
+            .title_alignment(Alignment::Center);
+        self.table.widget.select(old_selection);
+    }
 
-        let plan = self.repo.prepare_restore(&self.opts, ls, &dest, dry_run)?;
+    fn toggle_selected(&mut self) {
+        if let Some(idx) = self.table.widget.selected() {
+            self.preview.toggle(idx);
+            self.update_table();
+        }
+    }
 
-        Ok(plan)
+    fn select_all(&mut self, selected: bool) {
+        self.preview.select_all(selected);
+        self.update_table();
     }
 
-    // restore using the plan
-    //
-    // Note: This currently runs `prepare_restore` again and doesn't use `plan`
-    // TODO: Fix when restore is changed such that `prepare_restore` is always dry_run and all modification is done in `restore`
-    fn restore(&self, _plan: RestorePlan) -> Result<()> {
-        let dest = LocalDestination::new(&self.dest, true, !self.node.is_dir())?;
+    /// Change the conflict policy of the selected entry and all entries below it
+    fn change_policy(&mut self) {
+        if let Some(idx) = self.table.widget.selected() {
+            self.preview.change_policy(idx);
+            self.update_table();
+        }
+    }
 
-        // for restore, always recurse into tree
-        let mut ls_opts = LsOptions::default();
-        ls_opts.recursive = true;
+    fn restore_info(&self) -> Text<'static> {
+        let Some(plan) = &self.plan else {
+            return Text::default();
+        };
+        let fs = plan.stats.files;
+        let ds = plan.stats.dirs;
+        Text::from(format!(
+            r#"
+restoring from: {}
+restoring to: {}
 
-        let ls = self.repo.ls(&self.node, &ls_opts)?;
-        let plan = self
-            .repo
-            .prepare_restore(&self.opts, ls.clone(), &dest, false)?;
+Files:  {} to restore, {} unchanged, {} verified, {} to modify, {} additional
+Dirs:   {} to restore, {} to modify, {} additional
+Total restore size: {}
+ "#,
+            self.source,
+            self.target.dest,
+            fs.restore,
+            fs.unchanged,
+            fs.verified,
+            fs.modify,
+            fs.additional,
+            ds.restore,
+            ds.modify,
+            ds.additional,
+            bytes_size_to_string(plan.restore_size)
+        ))
+    }
+
+    // restore the selected entries
+    fn restore(&self) -> Result<()> {
+        // directories with mirror policy are restored as a whole; the restore also deletes the entries
+        // not contained in the snapshot
+        let mirror_dirs = if self.target.node.is_dir() {
+            self.preview.mirror_dirs()
+        } else {
+            Vec::new()
+        };
+        for dir in &mirror_dirs {
+            let dest =
+                LocalDestination::new(&self.target.dest_path(dir).to_string_lossy(), true, false)?;
+            self.restore_nodes(self.preview.mirror_nodes(dir), &dest, true)?;
+        }
+
+        let ls = self.preview.selected_nodes(&mirror_dirs);
+        self.restore_nodes(ls, &self.target.destination()?, false)
+    }
 
-        // the actual restore
-        self.repo.restore(plan, &self.opts, ls, &dest)?;
+    /// Restore the given nodes to the destination; if `delete` is set, remove all other entries of the destination
+    fn restore_nodes(
+        &self,
+        ls: Vec<(PathBuf, Node)>,
+        dest: &LocalDestination,
+        delete: bool,
+    ) -> Result<()> {
+        if ls.is_empty() && !delete {
+            return Ok(());
+        }
+        let mut opts = self.target.opts;
+        opts.delete = delete;
+        let plan =
+            self.target
+                .repo
+                .prepare_restore(&opts, ls.clone().into_iter().map(Ok), dest, false)?;
+        self.target
+            .repo
+            .restore(plan, &opts, ls.into_iter().map(Ok), dest)?;
         Ok(())
     }
 
@@ -90,49 +611,66 @@ impl<'a> Restore<'a> {
             CurrentScreen::GetDestination(prompt) => match prompt.input(event) {
                 TextInputResult::Cancel => return Ok(true),
                 TextInputResult::Input(input) => {
-                    let plan = self.compute_plan(input, true)?;
-                    let fs = plan.stats.files;
-                    let ds = plan.stats.dirs;
-                    let popup = popup_prompt(
-                        "restore information",
-                        Text::from(format!(
-                            r#"
-restoring from: {}
-restoring to: {}
-                            
-Files:  {} to restore, {} unchanged, {} verified, {} to modify, {} additional
-Dirs:   {} to restore, {} to modify, {} additional
-Total restore size: {}
-
-Do you want to proceed (y/n)?
- "#,
-                            self.source,
-                            self.dest,
-                            fs.restore,
-                            fs.unchanged,
-                            fs.verified,
-                            fs.modify,
-                            fs.additional,
-                            ds.restore,
-                            ds.modify,
-                            ds.additional,
-                            bytes_size_to_string(plan.restore_size)
-                        )),
-                    );
-                    self.current_screen = CurrentScreen::PromptRestore(popup, Some(plan));
+                    if !self.compute(input)? {
+                        return Ok(true);
+                    }
+                    self.current_screen = CurrentScreen::Preview;
                 }
                 TextInputResult::None => {}
             },
-            CurrentScreen::PromptRestore(prompt, plan) => match prompt.input(event) {
+            CurrentScreen::Preview => match event {
+                Event::Key(key) if key.kind == KeyEventKind::Press => match key.code {
+                    Esc | Char('q') => return Ok(true),
+                    Char(' ') => self.toggle_selected(),
+                    Char('a') => self.select_all(true),
+                    Char('A') => self.select_all(false),
+                    Char('p') => self.change_policy(),
+                    Char('i') => {
+                        self.current_screen = CurrentScreen::ShowHelp(popup_text(
+                            "restore information",
+                            self.restore_info(),
+                        ));
+                    }
+                    Char('?') => {
+                        self.current_screen =
+                            CurrentScreen::ShowHelp(popup_text("help", HELP_TEXT.into()));
+                    }
+                    Enter => {
+                        let count = self.preview.entries.iter().filter(|e| e.selected).count();
+                        self.current_screen = CurrentScreen::PromptRestore(popup_prompt(
+                            "restore",
+                            format!(
+                                "restore {count} selected entries to {}?\n\nDo you want to proceed (y/n)?",
+                                self.target.dest
+                            )
+                            .into(),
+                        ));
+                    }
+                    _ => self.table.input(event),
+                },
+                _ => {}
+            },
+            CurrentScreen::ShowHelp(_) => match event {
+                Event::Key(key) if key.kind == KeyEventKind::Press => {
+                    if matches!(key.code, Char('q' | ' ' | 'i' | '?') | Esc | Enter) {
+                        self.current_screen = CurrentScreen::Preview;
+                    }
+                }
+                _ => {}
+            },
+            CurrentScreen::PromptRestore(prompt) => match prompt.input(event) {
                 PromptResult::Ok => {
-                    let plan = plan.take().unwrap();
-                    self.restore(plan)?;
+                    self.restore()?;
                     self.current_screen = CurrentScreen::RestoreDone(popup_text(
                         "restore done",
-                        format!("restored {} successfully to {}", self.source, self.dest).into(),
+                        format!(
+                            "restored {} successfully to {}",
+                            self.source, self.target.dest
+                        )
+                        .into(),
                     ));
                 }
-                PromptResult::Cancel => return Ok(true),
+                PromptResult::Cancel => self.current_screen = CurrentScreen::Preview,
                 PromptResult::None => {}
             },
             CurrentScreen::RestoreDone(_) => match event {
@@ -148,11 +686,261 @@ Do you want to proceed (y/n)?
     }
 
     pub fn draw(&mut self, area: Rect, f: &mut Frame<'_>) {
+        if !matches!(self.current_screen, CurrentScreen::GetDestination(_)) {
+            let rects = Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).split(area);
+
+            // draw the preview table
+            self.table.draw(rects[0], f);
+
+            // draw the footer
+            let buffer_bg = tailwind::SLATE.c950;
+            let row_fg = tailwind::SLATE.c200;
+            let info_footer = Paragraph::new(Line::from(INFO_TEXT))
+                .style(Style::new().fg(row_fg).bg(buffer_bg))
+                .centered();
+            f.render_widget(info_footer, rects[1]);
+        }
+
         // draw popups
         match &mut self.current_screen {
+            CurrentScreen::Preview => {}
             CurrentScreen::GetDestination(popup) => popup.draw(area, f),
-            CurrentScreen::PromptRestore(popup, _) => popup.draw(area, f),
-            CurrentScreen::RestoreDone(popup) => popup.draw(area, f),
+            CurrentScreen::ShowHelp(popup) | CurrentScreen::RestoreDone(popup) => {
+                popup.draw(area, f);
+            }
+            CurrentScreen::PromptRestore(popup) => popup.draw(area, f),
+        }
+    }
+}
+
+/// Collect all entries below `dir` in the destination which are not contained in `paths`
+///
+/// Directories which are not contained in `paths` are collected as a whole.
+fn collect_extra_entries(
+    dest: &Path,
+    dir: &Path,
+    paths: &BTreeSet<PathBuf>,
+    extra: &mut Vec<PathBuf>,
+) -> Result<()> {
+    let Ok(read_dir) = fs::read_dir(dest.join(dir)) else {
+        return Ok(());
+    };
+    for entry in read_dir {
+        let entry = entry?;
+        let path = dir.join(entry.file_name());
+        if !paths.contains(&path) {
+            extra.push(path);
+        } else if entry.file_type()?.is_dir() {
+            collect_extra_entries(dest, &path, paths, extra)?;
         }
     }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rustic_core::repofile::{Metadata, NodeType};
+
+    use super::*;
+
+    fn node(name: &str, node_type: NodeType) -> Node {
+        Node::new_node(name.as_ref(), node_type, Metadata::default())
+    }
+
+    // dir/
+    //   new.txt   (create)
+    //   mod.txt   (modify)
+    //   same.txt  (unchanged)
+    //   extra.txt (only in destination)
+    // top.txt     (modify)
+    // other.txt   (only in destination)
+    fn preview() -> Preview {
+        let restore = vec![
+            (
+                PathBuf::from("dir"),
+                node("dir", NodeType::Dir),
+                Action::Unchanged,
+            ),
+            (
+                PathBuf::from("dir/new.txt"),
+                node("new.txt", NodeType::File),
+                Action::Create,
+            ),
+            (
+                PathBuf::from("dir/mod.txt"),
+                node("mod.txt", NodeType::File),
+                Action::Modify,
+            ),
+            (
+                PathBuf::from("dir/same.txt"),
+                node("same.txt", NodeType::File),
+                Action::Unchanged,
+            ),
+            (
+                PathBuf::from("top.txt"),
+                node("top.txt", NodeType::File),
+                Action::Modify,
+            ),
+        ];
+        let extra = vec![PathBuf::from("dir/extra.txt"), PathBuf::from("other.txt")];
+        Preview::new(restore, extra)
+    }
+
+    fn selected(preview: &Preview) -> Vec<&str> {
+        preview
+            .entries
+            .iter()
+            .filter(|e| e.selected)
+            .map(|e| e.path.to_str().unwrap())
+            .collect()
+    }
+
+    fn index(preview: &Preview, path: &str) -> usize {
+        preview
+            .entries
+            .iter()
+            .position(|e| e.path == Path::new(path))
+            .unwrap()
+    }
+
+    #[test]
+    fn default_selection_creates_and_modifies() {
+        let preview = preview();
+        assert_eq!(
+            selected(&preview),
+            ["dir/mod.txt", "dir/new.txt", "top.txt"]
+        );
+    }
+
+    #[test]
+    fn selection_can_be_changed() {
+        let mut preview = preview();
+        preview.toggle(index(&preview, "top.txt"));
+        // unchanged and deleted entries can't be selected
+        preview.toggle(index(&preview, "dir/same.txt"));
+        preview.toggle(index(&preview, "other.txt"));
+        assert_eq!(selected(&preview), ["dir/mod.txt", "dir/new.txt"]);
+
+        preview.select_all(false);
+        assert!(selected(&preview).is_empty());
+        preview.select_all(true);
+        assert_eq!(
+            selected(&preview),
+            ["dir/mod.txt", "dir/new.txt", "top.txt"]
+        );
+    }
+
+    #[test]
+    fn change_policy_applies_to_subtree() {
+        let mut preview = preview();
+        let dir = index(&preview, "dir");
+
+        preview.change_policy(dir);
+        assert_eq!(
+            preview.policy(Path::new("dir/mod.txt")),
+            ConflictPolicy::Skip
+        );
+        assert_eq!(
+            preview.policy(Path::new("top.txt")),
+            ConflictPolicy::Overwrite
+        );
+        assert_eq!(selected(&preview), ["dir/new.txt", "top.txt"]);
+
+        preview.change_policy(dir);
+        assert_eq!(preview.policy(Path::new("dir")), ConflictPolicy::Mirror);
+        assert_eq!(
+            selected(&preview),
+            ["dir/extra.txt", "dir/mod.txt", "dir/new.txt", "top.txt"]
+        );
+        // entries with mirror policy can't be deselected
+        preview.toggle(index(&preview, "dir/new.txt"));
+        assert!(selected(&preview).contains(&"dir/new.txt"));
+
+        preview.change_policy(dir);
+        assert_eq!(preview.policy(Path::new("dir")), ConflictPolicy::Overwrite);
+    }
+
+    #[test]
+    fn change_policy_of_extra_entry_changes_parent() {
+        let mut preview = preview();
+        preview.change_policy(index(&preview, "other.txt"));
+        assert_eq!(preview.root_policy, ConflictPolicy::Skip);
+        assert!(
+            preview
+                .entries
+                .iter()
+                .all(|e| e.policy == ConflictPolicy::Skip)
+        );
+
+        let mut preview = self::preview();
+        preview.change_policy(index(&preview, "dir/extra.txt"));
+        assert_eq!(preview.policy(Path::new("dir")), ConflictPolicy::Skip);
+        assert_eq!(preview.root_policy, ConflictPolicy::Overwrite);
+    }
+
+    #[test]
+    fn mirror_dirs_are_topmost_mirrored_dirs() {
+        let mut preview = preview();
+        assert!(preview.mirror_dirs().is_empty());
+
+        let dir = index(&preview, "dir");
+        preview.change_policy(dir);
+        preview.change_policy(dir);
+        assert_eq!(preview.mirror_dirs(), [PathBuf::from("dir")]);
+        let nodes: Vec<_> = preview
+            .mirror_nodes(Path::new("dir"))
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        assert_eq!(nodes, ["mod.txt", "new.txt", "same.txt"].map(PathBuf::from));
+        // entries within mirrored dirs are not restored separately
+        let nodes: Vec<_> = preview
+            .selected_nodes(&preview.mirror_dirs())
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        assert_eq!(nodes, ["dir", "top.txt"].map(PathBuf::from));
+
+        preview.change_policy(index(&preview, "other.txt"));
+        preview.change_policy(index(&preview, "other.txt"));
+        assert_eq!(preview.root_policy, ConflictPolicy::Mirror);
+        assert_eq!(preview.mirror_dirs(), [PathBuf::new()]);
+    }
+
+    #[test]
+    fn selected_nodes_contain_parent_dirs() {
+        let mut preview = preview();
+        preview.toggle(index(&preview, "top.txt"));
+        preview.toggle(index(&preview, "dir/mod.txt"));
+        let nodes: Vec<_> = preview
+            .selected_nodes(&[])
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        assert_eq!(nodes, ["dir", "dir/new.txt"].map(PathBuf::from));
+    }
+
+    #[test]
+    fn extra_entries_are_collected() -> Result<()> {
+        let dest = tempfile::tempdir()?;
+        fs::create_dir_all(dest.path().join("dir/sub"))?;
+        fs::create_dir_all(dest.path().join("extra_dir/sub"))?;
+        fs::write(dest.path().join("dir/file.txt"), "")?;
+        fs::write(dest.path().join("dir/extra.txt"), "")?;
+        fs::write(dest.path().join("dir/sub/extra.txt"), "")?;
+        fs::write(dest.path().join("extra_dir/sub/file.txt"), "")?;
+
+        let paths = ["dir", "dir/file.txt", "dir/sub"]
+            .into_iter()
+            .map(PathBuf::from)
+            .collect();
+        let mut extra = Vec::new();
+        collect_extra_entries(dest.path(), Path::new(""), &paths, &mut extra)?;
+        extra.sort();
+        assert_eq!(
+            extra,
+            ["dir/extra.txt", "dir/sub/extra.txt", "extra_dir"].map(PathBuf::from)
+        );
+        Ok(())
+    }
 }
@@ -15,7 +15,9 @@ use rustic_core::{
 use style::palette::tailwind;
 
 use crate::{
+    annotations::{Annotation, annotate, annotations, formatln, plain_tags, with_annotations},
     commands::{
+        backup::{plain_description, read_log, with_log},
         snapshots::{fill_table, snap_to_table},
         tui::{
             diff::{Diff, DiffResult},
@@ -55,6 +57,7 @@ pub enum SnapshotProperty {
     AddTags,
     SetTags,
     RemoveTags,
+    Annotations,
     Hostname,
 }
 
@@ -69,21 +72,31 @@ impl SnapshotProperty {
                 true
             }
             Self::Description => {
-                if snap.description.is_none() && value.is_empty()
-                    || snap.description.as_ref() == Some(value)
-                {
+                if plain_description(snap).unwrap_or_default() == value {
                     return false;
                 }
-                if value.is_empty() {
-                    snap.description = None;
-                } else {
-                    snap.description = Some(value.clone());
-                }
+                // keep a saved backup log
+                snap.description = with_log(Some(value), read_log(snap));
                 true
             }
             Self::AddTags => snap.add_tags(vec![StringList::from_str(value).unwrap()]),
-            Self::SetTags => snap.set_tags(vec![StringList::from_str(value).unwrap()]),
+            Self::SetTags => {
+                let tags = StringList::from_str(value).unwrap();
+                snap.set_tags(vec![with_annotations(tags, &annotations(&snap.tags))])
+            }
             Self::RemoveTags => snap.remove_tags(&[StringList::from_str(value).unwrap()]),
+            Self::Annotations => {
+                let Ok(new_annotations) = value
+                    .lines()
+                    .filter(|line| !line.trim().is_empty())
+                    .map(Annotation::from_str)
+                    .collect::<Result<Vec<_>>>()
+                else {
+                    return false;
+                };
+                let tags = plain_tags(&snap.tags);
+                snap.set_tags(vec![annotate(&tags, &new_annotations, &[])])
+            }
             Self::Hostname => {
                 if &snap.hostname == value {
                     return false;
@@ -156,6 +169,8 @@ Commands applied to marked snapshot(s) (selected if none marked):
   Ctrl-t : remove all tags for snapshot(s)
        s : set tag(s) for snapshot(s)
        r : remove tag(s) for snapshot(s)
+       a : set annotations for snapshot(s)
+  Ctrl-a : remove all annotations for snapshot(s)
        H : set hostname for snapshot(s) 
        p : set delete protection for snapshot(s)
   Ctrl-p : remove delete protection for snapshot(s)
@@ -384,7 +399,7 @@ impl<'a> Snapshots<'a> {
                 let snap = &self.snapshots[*index];
                 let symbols = match (
                     snap.delete == DeleteOption::NotSet,
-                    snap.description.is_none(),
+                    plain_description(snap).is_none(),
                 ) {
                     (true, true) => "",
                     (true, false) => "🗎",
@@ -447,7 +462,7 @@ impl<'a> Snapshots<'a> {
         let max_tags = self
             .filtered_snapshots
             .iter()
-            .map(|&i| self.snapshots[i].tags.iter().count())
+            .map(|&i| plain_tags(&self.snapshots[i].tags).iter().count())
             .max()
             .unwrap_or(1);
         let max_paths = self
@@ -529,6 +544,11 @@ impl<'a> Snapshots<'a> {
             fill_table(snap, |title, value| {
                 rows.push(vec![Text::from(title.to_string()), Text::from(value)]);
             });
+            // TODO: error handling
+            if let Some(log) = read_log(snap) {
+                rows.push(vec![Text::from(""), Text::from("")]);
+                rows.push(vec![Text::from("Backup log"), Text::from(log.to_string())]);
+            }
         }
         popup_table("snapshot details", rows)
     }
@@ -683,7 +703,11 @@ impl<'a> Snapshots<'a> {
     }
 
     pub fn get_tags(&mut self) -> String {
-        self.get_snap_entity(|snap| snap.tags.formatln())
+        self.get_snap_entity(|snap| plain_tags(&snap.tags).formatln())
+    }
+
+    pub fn get_annotations(&mut self) -> String {
+        self.get_snap_entity(|snap| formatln(&annotations(&snap.tags)))
     }
 
     pub fn get_hostname(&mut self) -> String {
@@ -691,7 +715,7 @@ impl<'a> Snapshots<'a> {
     }
 
     pub fn get_description(&mut self) -> String {
-        self.get_snap_entity(|snap| snap.description.clone().unwrap_or_default())
+        self.get_snap_entity(|snap| plain_description(snap).unwrap_or_default().to_string())
     }
 
     pub fn get_filter(&self) -> Result<String> {
@@ -721,6 +745,10 @@ impl<'a> Snapshots<'a> {
         self.set_property(SnapshotProperty::SetTags, String::new());
     }
 
+    pub fn clear_annotations(&mut self) {
+        self.set_property(SnapshotProperty::Annotations, String::new());
+    }
+
     pub fn set_delete_protection_to(&mut self, delete: DeleteOption) {
         self.process_marked_snaps(|snap| {
             if snap.delete == delete {
@@ -838,6 +866,7 @@ impl<'a> ProcessEvent for Snapshots<'a> {
                                 Char('l') => self.clear_label(),
                                 Char('d') => self.clear_description(),
                                 Char('t') => self.clear_tags(),
+                                Char('a') => self.clear_annotations(),
                                 Char('p') => self.clear_delete_protection(),
                                 Char('v') => self.reset_filter(),
                                 _ => {}
@@ -943,6 +972,17 @@ impl<'a> ProcessEvent for Snapshots<'a> {
                                         SnapshotProperty::RemoveTags,
                                     ));
                                 }
+                                Char('a') => {
+                                    self.current_screen = CurrentScreen::EnterProperty((
+                                        popup_input(
+                                            "set annotations (Ctrl-s to confirm)",
+                                            "enter KEY=VALUE, one per line",
+                                            &self.get_annotations(),
+                                            5,
+                                        ),
+                                        SnapshotProperty::Annotations,
+                                    ));
+                                }
                                 Char('H') => {
                                     self.current_screen = CurrentScreen::EnterProperty((
                                         popup_input(
@@ -0,0 +1,975 @@
+//! Full-screen pager to view the contents of a file contained in a snapshot.
+//!
+//! File contents are loaded lazily in chunks from the repository; only a bounded
+//! number of chunks is kept in memory so arbitrarily large files can be viewed.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    rc::Rc,
+};
+
+use anyhow::Result;
+use crossterm::event::{Event, KeyCode, KeyEventKind};
+use ratatui::{
+    prelude::*,
+    widgets::{Block, Borders, Paragraph},
+};
+use rustic_core::{repofile::Node, vfs::OpenFile};
+use style::palette::tailwind;
+
+use crate::{
+    commands::tui::widgets::{
+        Draw, PopUpInput, PopUpText, ProcessEvent, TextInputResult, popup_input, popup_text,
+    },
+    repository::IndexedRepo,
+};
+
+// the states this screen can be in
+enum CurrentScreen {
+    View,
+    Search(Box<PopUpInput>),
+    ShowHelp(Box<PopUpText>),
+}
+
+const INFO_TEXT: &str = "(Esc) close | (/) search | (n) next match | (#) line numbers | (x) hex view | (?) show all commands";
+
+const HELP_TEXT: &str = r"
+File Viewer Commands:
+
+      Up,Down : scroll one line
+  PgUp,PgDown : scroll one page
+   Left,Right : scroll horizontally
+     Home,End : go to start/end of the file
+            / : search for text
+            n : go to next match
+            N : go to previous match
+            # : toggle line numbers
+            x : toggle hex view
+
+General Commands:
+
+        q,Esc : close viewer
+            ? : show this help page
+
+ ";
+
+/// Size of the chunks which are read from the repository
+const CHUNK_SIZE: usize = 64 * 1024;
+/// Maximum number of chunks kept in memory
+const MAX_CHUNKS: usize = 64;
+/// Maximum number of bytes shown for a single line in text view
+const MAX_LINE_LEN: usize = 4096;
+/// Number of bytes shown per row in hex view
+const HEX_WIDTH: usize = 16;
+/// Number of spaces a tab is expanded to
+const TAB_WIDTH: usize = 4;
+/// Number of lines between two line starts kept in the line index
+const CHECKPOINT_LINES: usize = 1024;
+
+/// Random access to the content which is viewed
+trait Source {
+    fn size(&self) -> u64;
+    fn read_at(&self, offset: u64, length: usize) -> Result<Rc<[u8]>>;
+}
+
+struct RepoFile<'a> {
+    repo: &'a IndexedRepo,
+    file: OpenFile,
+    size: u64,
+}
+
+impl Source for RepoFile<'_> {
+    fn size(&self) -> u64 {
+        self.size
+    }
+    fn read_at(&self, offset: u64, length: usize) -> Result<Rc<[u8]>> {
+        Ok(self.file.read_at(self.repo, offset.try_into()?, length)?[..].into())
+    }
+}
+
+/// Chunked, size-bounded cache of the file contents
+struct ChunkCache<S> {
+    source: S,
+    chunks: BTreeMap<u64, Rc<[u8]>>,
+    order: VecDeque<u64>,
+}
+
+impl<S: Source> ChunkCache<S> {
+    fn new(source: S) -> Self {
+        Self {
+            source,
+            chunks: BTreeMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    fn size(&self) -> u64 {
+        self.source.size()
+    }
+
+    fn chunk(&mut self, idx: u64) -> Result<Rc<[u8]>> {
+        if let Some(chunk) = self.chunks.get(&idx) {
+            return Ok(Rc::clone(chunk));
+        }
+        let chunk = self.source.read_at(idx * CHUNK_SIZE as u64, CHUNK_SIZE)?;
+        if self.order.len() >= MAX_CHUNKS
+            && let Some(old) = self.order.pop_front()
+        {
+            _ = self.chunks.remove(&old);
+        }
+        self.order.push_back(idx);
+        _ = self.chunks.insert(idx, Rc::clone(&chunk));
+        Ok(chunk)
+    }
+
+    /// Read `length` bytes starting at `offset`; the result is shorter at the end of the file
+    fn read(&mut self, offset: u64, length: usize) -> Result<Vec<u8>> {
+        let end = (offset + length as u64).min(self.size());
+        let mut result = Vec::with_capacity(end.saturating_sub(offset).try_into()?);
+        let mut pos = offset;
+        while pos < end {
+            let idx = pos / CHUNK_SIZE as u64;
+            let chunk = self.chunk(idx)?;
+            if chunk.is_empty() {
+                break;
+            }
+            let start: usize = (pos - idx * CHUNK_SIZE as u64).try_into()?;
+            let stop = chunk.len().min(start + usize::try_from(end - pos)?);
+            result.extend_from_slice(&chunk[start..stop]);
+            pos += (stop - start) as u64;
+        }
+        Ok(result)
+    }
+}
+
+/// Index of line starts, built incrementally while scrolling through the file
+///
+/// Only the start of every [`CHECKPOINT_LINES`]-th line is kept; the starts of the other lines are
+/// found by scanning from the previous checkpoint. The starts of the most recently used block of
+/// lines are kept to make this cheap when scrolling.
+struct LineIndex {
+    /// start of the lines `0`, `CHECKPOINT_LINES`, `2 * CHECKPOINT_LINES`, ...
+    checkpoints: Vec<u64>,
+    /// number of line starts found so far
+    count: usize,
+    /// start of the last line found so far
+    last: u64,
+    scanned: u64,
+    /// block number and line starts of the most recently used block
+    block: Option<(usize, Vec<u64>)>,
+}
+
+impl LineIndex {
+    fn new() -> Self {
+        Self {
+            checkpoints: vec![0],
+            count: 1,
+            last: 0,
+            scanned: 0,
+            block: None,
+        }
+    }
+
+    fn complete(&self, size: u64) -> bool {
+        self.scanned >= size
+    }
+
+    /// Scan the file until line `line` is known or the end of the file is reached
+    fn scan_to<S: Source>(&mut self, cache: &mut ChunkCache<S>, line: usize) -> Result<()> {
+        let size = cache.size();
+        while self.count <= line.saturating_add(1) && !self.complete(size) {
+            let idx = self.scanned / CHUNK_SIZE as u64;
+            let chunk = cache.chunk(idx)?;
+            if chunk.is_empty() {
+                self.scanned = size;
+                break;
+            }
+            let start: usize = (self.scanned - idx * CHUNK_SIZE as u64).try_into()?;
+            for (i, b) in chunk[start..].iter().enumerate() {
+                if *b == b'\n' {
+                    self.last = self.scanned + i as u64 + 1;
+                    if self.count.is_multiple_of(CHECKPOINT_LINES) {
+                        self.checkpoints.push(self.last);
+                    }
+                    self.count += 1;
+                }
+            }
+            self.scanned += (chunk.len() - start) as u64;
+        }
+        Ok(())
+    }
+
+    /// Number of lines known so far; exact once the index is complete
+    fn lines(&self, size: u64) -> usize {
+        // a trailing newline doesn't start a new line
+        if self.last == size && size > 0 && self.complete(size) {
+            self.count - 1
+        } else {
+            self.count
+        }
+    }
+
+    /// The line starts of the given block of lines which are known so far
+    fn block<S: Source>(&mut self, cache: &mut ChunkCache<S>, block: usize) -> Result<&[u64]> {
+        let expected = (self.count - block * CHECKPOINT_LINES).min(CHECKPOINT_LINES);
+        if self
+            .block
+            .as_ref()
+            .is_none_or(|(b, starts)| *b != block || starts.len() != expected)
+        {
+            let mut pos = self.checkpoints[block];
+            let mut starts = vec![pos];
+            while starts.len() < expected {
+                let idx = pos / CHUNK_SIZE as u64;
+                let chunk = cache.chunk(idx)?;
+                let start: usize = (pos - idx * CHUNK_SIZE as u64).try_into()?;
+                if start >= chunk.len() {
+                    break;
+                }
+                for (i, b) in chunk[start..].iter().enumerate() {
+                    if *b == b'\n' && starts.len() < expected {
+                        starts.push(pos + i as u64 + 1);
+                    }
+                }
+                pos += (chunk.len() - start) as u64;
+            }
+            self.block = Some((block, starts));
+        }
+        Ok(self.block.as_ref().map_or(&[], |(_, starts)| starts))
+    }
+
+    /// Start of line `line`, if it is known
+    fn start<S: Source>(&mut self, cache: &mut ChunkCache<S>, line: usize) -> Result<Option<u64>> {
+        if line >= self.count {
+            return Ok(None);
+        }
+        let starts = self.block(cache, line / CHECKPOINT_LINES)?;
+        Ok(starts.get(line % CHECKPOINT_LINES).copied())
+    }
+
+    /// Byte range of line `line`, excluding the line break
+    fn range<S: Source>(
+        &mut self,
+        cache: &mut ChunkCache<S>,
+        line: usize,
+    ) -> Result<Option<(u64, u64)>> {
+        let Some(start) = self.start(cache, line)? else {
+            return Ok(None);
+        };
+        let size = cache.size();
+        let end = self.start(cache, line + 1)?.map_or(size, |next| next - 1);
+        Ok(Some((start, end)))
+    }
+
+    /// Find the line containing the byte at `offset`; the file must be scanned up to `offset`
+    fn line_of<S: Source>(&mut self, cache: &mut ChunkCache<S>, offset: u64) -> Result<usize> {
+        let block = self
+            .checkpoints
+            .partition_point(|&s| s <= offset)
+            .saturating_sub(1);
+        let starts = self.block(cache, block)?;
+        let line = starts.partition_point(|&s| s <= offset).saturating_sub(1);
+        Ok(block * CHECKPOINT_LINES + line)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Text,
+    Hex,
+}
+
+pub struct Viewer<'a> {
+    current_screen: CurrentScreen,
+    title: String,
+    cache: ChunkCache<RepoFile<'a>>,
+    index: LineIndex,
+    mode: Mode,
+    syntax: Option<&'static Syntax>,
+    line_numbers: bool,
+    top: usize,
+    left: usize,
+    height: usize,
+    search: Option<String>,
+    message: Option<String>,
+}
+
+impl<'a> Viewer<'a> {
+    pub fn new(repo: &'a IndexedRepo, node: &Node, title: String) -> Result<Self> {
+        let file = repo.open_file(node)?;
+        let source = RepoFile {
+            repo,
+            file,
+            size: node.meta.size,
+        };
+        let mut cache = ChunkCache::new(source);
+        let first = cache.read(0, CHUNK_SIZE)?;
+        let mode = if is_text(&first, cache.size() <= CHUNK_SIZE as u64) {
+            Mode::Text
+        } else {
+            Mode::Hex
+        };
+        let syntax = Syntax::from_name(&node.name().to_string_lossy());
+        Ok(Self {
+            current_screen: CurrentScreen::View,
+            title,
+            cache,
+            index: LineIndex::new(),
+            mode,
+            syntax,
+            line_numbers: true,
+            top: 0,
+            left: 0,
+            height: 1,
+            search: None,
+            message: None,
+        })
+    }
+
+    /// Number of rows of the current view; in text view only the lines scanned so far are known
+    fn rows(&self) -> usize {
+        let size = self.cache.size();
+        match self.mode {
+            Mode::Text => self.index.lines(size),
+            Mode::Hex => usize::try_from(size.div_ceil(HEX_WIDTH as u64))
+                .unwrap_or(usize::MAX)
+                .max(1),
+        }
+    }
+
+    fn scroll_to(&mut self, row: usize) -> Result<()> {
+        if self.mode == Mode::Text {
+            self.index
+                .scan_to(&mut self.cache, row.saturating_add(self.height))?;
+        }
+        let max = self.rows().saturating_sub(self.height);
+        self.top = row.min(max);
+        Ok(())
+    }
+
+    fn scroll_down(&mut self, n: usize) -> Result<()> {
+        self.scroll_to(self.top.saturating_add(n))
+    }
+
+    fn scroll_up(&mut self, n: usize) -> Result<()> {
+        self.scroll_to(self.top.saturating_sub(n))
+    }
+
+    fn goto_end(&mut self) -> Result<()> {
+        // this scans the whole file in text view, but only keeps the line index in memory
+        self.scroll_to(usize::MAX - self.height)
+    }
+
+    fn toggle_mode(&mut self) -> Result<()> {
+        // keep the current position when switching the view
+        let offset = match self.mode {
+            Mode::Text => self
+                .index
+                .start(&mut self.cache, self.top)?
+                .unwrap_or_default(),
+            Mode::Hex => (self.top * HEX_WIDTH) as u64,
+        };
+        self.mode = match self.mode {
+            Mode::Text => Mode::Hex,
+            Mode::Hex => Mode::Text,
+        };
+        self.left = 0;
+        let row = match self.mode {
+            Mode::Hex => usize::try_from(offset)? / HEX_WIDTH,
+            Mode::Text => self.line_of(offset)?,
+        };
+        self.scroll_to(row)
+    }
+
+    /// Find the line containing the byte at `offset`
+    fn line_of(&mut self, offset: u64) -> Result<usize> {
+        let size = self.cache.size();
+        while self.index.last <= offset && !self.index.complete(size) {
+            let lines = self.index.count;
+            self.index.scan_to(&mut self.cache, lines)?;
+        }
+        self.index.line_of(&mut self.cache, offset)
+    }
+
+    fn text_line(&mut self, line: usize) -> Result<Option<String>> {
+        let Some((start, end)) = self.index.range(&mut self.cache, line)? else {
+            return Ok(None);
+        };
+        let len = usize::try_from(end - start)?.min(MAX_LINE_LEN);
+        let data = self.cache.read(start, len)?;
+        let line = String::from_utf8_lossy(&data);
+        let line = line.strip_suffix('\r').unwrap_or(&line);
+        Ok(Some(expand_tabs(line)))
+    }
+
+    /// Search for the next (or previous) occurrence of the search text
+    ///
+    /// If `current` is set, a forward search includes the current row; this is used for a new search.
+    fn find(&mut self, forward: bool, current: bool) -> Result<()> {
+        let Some(pattern) = self.search.clone() else {
+            return Ok(());
+        };
+        let found = match self.mode {
+            Mode::Text => self.find_line(&pattern, forward, current)?,
+            Mode::Hex => self.find_bytes(pattern.as_bytes(), forward, current)?,
+        };
+        match found {
+            Some(row) => {
+                self.message = None;
+                // show matching row at the top, if possible
+                self.scroll_to(row)?;
+            }
+            None => self.message = Some(format!("pattern \"{pattern}\" not found")),
+        }
+        Ok(())
+    }
+
+    fn find_line(&mut self, pattern: &str, forward: bool, current: bool) -> Result<Option<usize>> {
+        if forward {
+            let mut line = self.top + usize::from(!current);
+            loop {
+                self.index.scan_to(&mut self.cache, line)?;
+                match self.text_line(line)? {
+                    Some(text) if text.contains(pattern) => return Ok(Some(line)),
+                    Some(_) => line += 1,
+                    None => return Ok(None),
+                }
+            }
+        } else {
+            for line in (0..self.top).rev() {
+                if self
+                    .text_line(line)?
+                    .is_some_and(|text| text.contains(pattern))
+                {
+                    return Ok(Some(line));
+                }
+            }
+            Ok(None)
+        }
+    }
+
+    fn find_bytes(
+        &mut self,
+        pattern: &[u8],
+        forward: bool,
+        current: bool,
+    ) -> Result<Option<usize>> {
+        if pattern.is_empty() {
+            return Ok(None);
+        }
+        let size = self.cache.size();
+        let overlap = pattern.len() - 1;
+        let top = (self.top * HEX_WIDTH) as u64;
+        if forward {
+            let mut pos = if current { top } else { top + HEX_WIDTH as u64 };
+            while pos < size {
+                let data = self.cache.read(pos, CHUNK_SIZE + overlap)?;
+                if let Some(i) = find_in(&data, pattern) {
+                    return Ok(Some(usize::try_from(pos + i as u64)? / HEX_WIDTH));
+                }
+                pos += CHUNK_SIZE as u64;
+            }
+        } else {
+            let mut end = top + overlap as u64;
+            while end > overlap as u64 {
+                let start = end.saturating_sub((CHUNK_SIZE + overlap) as u64);
+                let data = self.cache.read(start, usize::try_from(end - start)?)?;
+                if let Some(i) = rfind_in(&data, pattern) {
+                    return Ok(Some(usize::try_from(start + i as u64)? / HEX_WIDTH));
+                }
+                end = start + overlap as u64;
+                if start == 0 {
+                    break;
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn visible_lines(&mut self) -> Result<Vec<Line<'static>>> {
+        let mut lines = Vec::with_capacity(self.height);
+        match self.mode {
+            Mode::Text => {
+                self.index
+                    .scan_to(&mut self.cache, self.top + self.height)?;
+                let size = self.cache.size();
+                let number_width = self.index.lines(size).to_string().len().max(4);
+                for line in self.top..self.top + self.height {
+                    if line >= self.index.lines(size) {
+                        break;
+                    }
+                    let Some(text) = self.text_line(line)? else {
+                        break;
+                    };
+                    let text: String = text.chars().skip(self.left).collect();
+                    let mut spans = Vec::new();
+                    if self.line_numbers {
+                        spans.push(Span::styled(
+                            format!("{:>number_width$} ", line + 1),
+                            Style::default().fg(Color::DarkGray),
+                        ));
+                    }
+                    spans.extend(highlight(&text, self.syntax, self.search.as_deref()));
+                    lines.push(Line::from(spans));
+                }
+            }
+            Mode::Hex => {
+                let size = self.cache.size();
+                for row in self.top..self.top + self.height {
+                    let offset = (row * HEX_WIDTH) as u64;
+                    if offset >= size {
+                        break;
+                    }
+                    let data = self.cache.read(offset, HEX_WIDTH)?;
+                    lines.push(hex_line(offset, &data));
+                }
+            }
+        }
+        Ok(lines)
+    }
+
+    fn status(&self) -> String {
+        let size = self.cache.size();
+        let position = match self.mode {
+            Mode::Text => {
+                let total = if self.index.complete(size) {
+                    self.index.lines(size).to_string()
+                } else {
+                    "?".to_string()
+                };
+                format!("line {}/{total}", self.top + 1)
+            }
+            Mode::Hex => format!("offset {:#010x}/{size:#010x}", self.top * HEX_WIDTH),
+        };
+        match &self.message {
+            Some(message) => format!("{position} - {message}"),
+            None => position,
+        }
+    }
+
+    pub fn input(&mut self, event: Event) -> Result<bool> {
+        use KeyCode::{Char, Down, End, Enter, Esc, Home, Left, PageDown, PageUp, Right, Up};
+        match &mut self.current_screen {
+            CurrentScreen::View => match event {
+                Event::Key(key) if key.kind == KeyEventKind::Press => {
+                    self.message = None;
+                    match key.code {
+                        Esc | Char('q') => return Ok(true),
+                        Down | Char('j') => self.scroll_down(1)?,
+                        Up | Char('k') => self.scroll_up(1)?,
+                        PageDown | Char(' ') => self.scroll_down(self.height)?,
+                        PageUp => self.scroll_up(self.height)?,
+                        Home | Char('g') => self.scroll_to(0)?,
+                        End | Char('G') => self.goto_end()?,
+                        Right if self.mode == Mode::Text => self.left += 8,
+                        Left if self.mode == Mode::Text => self.left = self.left.saturating_sub(8),
+                        Char('#') => self.line_numbers = !self.line_numbers,
+                        Char('x') => self.toggle_mode()?,
+                        Char('n') => self.find(true, false)?,
+                        Char('N') => self.find(false, false)?,
+                        Char('/') => {
+                            self.current_screen = CurrentScreen::Search(Box::new(popup_input(
+                                "search",
+                                "enter search text",
+                                self.search.as_deref().unwrap_or_default(),
+                                1,
+                            )));
+                        }
+                        Char('?') => {
+                            self.current_screen = CurrentScreen::ShowHelp(Box::new(popup_text(
+                                "help",
+                                HELP_TEXT.into(),
+                            )));
+                        }
+                        _ => {}
+                    }
+                }
+                _ => {}
+            },
+            CurrentScreen::Search(prompt) => match prompt.input(event) {
+                TextInputResult::Cancel => self.current_screen = CurrentScreen::View,
+                TextInputResult::Input(input) => {
+                    self.current_screen = CurrentScreen::View;
+                    self.search = (!input.is_empty()).then_some(input);
+                    self.find(true, true)?;
+                }
+                TextInputResult::None => {}
+            },
+            CurrentScreen::ShowHelp(_) => match event {
+                Event::Key(key) if key.kind == KeyEventKind::Press => {
+                    if matches!(key.code, Char('q' | ' ' | '?') | Esc | Enter) {
+                        self.current_screen = CurrentScreen::View;
+                    }
+                }
+                _ => {}
+            },
+        }
+        Ok(false)
+    }
+
+    pub fn draw(&mut self, area: Rect, f: &mut Frame<'_>) {
+        let rects = Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).split(area);
+
+        let block = Block::new()
+            .borders(Borders::BOTTOM | Borders::TOP)
+            .title(self.title.clone())
+            .title_bottom(self.status())
+            .title_alignment(Alignment::Center);
+        let inner = block.inner(rects[0]);
+        self.height = usize::from(inner.height).max(1);
+        let lines = match self.visible_lines() {
+            Ok(lines) => lines,
+            Err(err) => vec![Line::styled(
+                format!("error reading file: {err}"),
+                Style::default().fg(Color::Red),
+            )],
+        };
+        f.render_widget(Paragraph::new(lines).block(block), rects[0]);
+
+        // draw the footer
+        let buffer_bg = tailwind::SLATE.c950;
+        let row_fg = tailwind::SLATE.c200;
+        let info_footer = Paragraph::new(Line::from(INFO_TEXT))
+            .style(Style::new().fg(row_fg).bg(buffer_bg))
+            .centered();
+        f.render_widget(info_footer, rects[1]);
+
+        // draw popups
+        match &mut self.current_screen {
+            CurrentScreen::View => {}
+            CurrentScreen::Search(popup) => popup.draw(area, f),
+            CurrentScreen::ShowHelp(popup) => popup.draw(area, f),
+        }
+    }
+}
+
+/// Check if the data looks like text, i.e. is valid UTF-8 without NUL bytes
+///
+/// If `complete` is false, an incomplete UTF-8 sequence at the end of the data is allowed.
+pub(super) fn is_text(data: &[u8], complete: bool) -> bool {
+    if data.contains(&0) {
+        return false;
+    }
+    match std::str::from_utf8(data) {
+        Ok(_) => true,
+        Err(err) => !complete && err.error_len().is_none(),
+    }
+}
+
+pub(super) fn expand_tabs(line: &str) -> String {
+    let mut result = String::with_capacity(line.len());
+    for c in line.chars() {
+        match c {
+            '\t' => {
+                let n = TAB_WIDTH - result.chars().count() % TAB_WIDTH;
+                result.extend(std::iter::repeat_n(' ', n));
+            }
+            c if c.is_control() => result.push('.'),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+fn hex_line(offset: u64, data: &[u8]) -> Line<'static> {
+    let mut hex = String::with_capacity(3 * HEX_WIDTH + 1);
+    for i in 0..HEX_WIDTH {
+        if i == HEX_WIDTH / 2 {
+            hex.push(' ');
+        }
+        match data.get(i) {
+            Some(b) => hex.push_str(&format!("{b:02x} ")),
+            None => hex.push_str("   "),
+        }
+    }
+    let ascii: String = data
+        .iter()
+        .map(|&b| {
+            if b.is_ascii_graphic() || b == b' ' {
+                char::from(b)
+            } else {
+                '.'
+            }
+        })
+        .collect();
+    Line::from(vec![
+        Span::styled(
+            format!("{offset:08x}  "),
+            Style::default().fg(Color::DarkGray),
+        ),
+        Span::raw(hex),
+        Span::styled(format!(" |{ascii}|"), Style::default().fg(Color::Cyan)),
+    ])
+}
+
+fn find_in(data: &[u8], pattern: &[u8]) -> Option<usize> {
+    data.windows(pattern.len()).position(|w| w == pattern)
+}
+
+fn rfind_in(data: &[u8], pattern: &[u8]) -> Option<usize> {
+    data.windows(pattern.len()).rposition(|w| w == pattern)
+}
+
+/// Simple syntax description used for highlighting
+struct Syntax {
+    extensions: &'static [&'static str],
+    line_comment: &'static [&'static str],
+    keywords: &'static [&'static str],
+}
+
+const SYNTAXES: &[Syntax] = &[
+    Syntax {
+        extensions: &["rs"],
+        line_comment: &["//"],
+        keywords: &[
+            "as", "async", "await", "break", "const", "continue", "crate", "else", "enum", "false",
+            "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+            "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true", "type",
+            "unsafe", "use", "where", "while",
+        ],
+    },
+    Syntax {
+        extensions: &[
+            "c", "h", "cc", "cpp", "hpp", "java", "js", "ts", "go", "cs", "kt", "swift",
+        ],
+        line_comment: &["//"],
+        keywords: &[
+            "break", "case", "class", "const", "continue", "default", "do", "else", "enum",
+            "extern", "false", "for", "func", "function", "if", "import", "let", "new", "null",
+            "package", "private", "public", "return", "static", "struct", "switch", "this", "true",
+            "typedef", "var", "void", "while",
+        ],
+    },
+    Syntax {
+        extensions: &["py"],
+        line_comment: &["#"],
+        keywords: &[
+            "and", "as", "class", "def", "elif", "else", "except", "False", "finally", "for",
+            "from", "if", "import", "in", "is", "lambda", "None", "not", "or", "pass", "raise",
+            "return", "True", "try", "while", "with", "yield",
+        ],
+    },
+    Syntax {
+        extensions: &["sh", "bash", "zsh"],
+        line_comment: &["#"],
+        keywords: &[
+            "case", "do", "done", "elif", "else", "esac", "export", "fi", "for", "function", "if",
+            "in", "local", "return", "then", "while",
+        ],
+    },
+    Syntax {
+        extensions: &["toml", "ini", "conf", "cfg", "yaml", "yml"],
+        line_comment: &["#", ";"],
+        keywords: &["true", "false"],
+    },
+    Syntax {
+        extensions: &["json"],
+        line_comment: &[],
+        keywords: &["true", "false", "null"],
+    },
+];
+
+impl Syntax {
+    fn from_name(name: &str) -> Option<&'static Self> {
+        let (_, ext) = name.rsplit_once('.')?;
+        let ext = ext.to_lowercase();
+        SYNTAXES
+            .iter()
+            .find(|s| s.extensions.contains(&ext.as_str()))
+    }
+}
+
+/// Highlight a line using `syntax` and mark all occurrences of `search`
+fn highlight(line: &str, syntax: Option<&Syntax>, search: Option<&str>) -> Vec<Span<'static>> {
+    let mut styles = syntax.map_or_else(
+        || vec![Style::default(); line.len()],
+        |syntax| syntax_styles(line, syntax),
+    );
+    if let Some(search) = search.filter(|s| !s.is_empty()) {
+        for (pos, _) in line.match_indices(search) {
+            for style in &mut styles[pos..pos + search.len()] {
+                *style = style.add_modifier(Modifier::REVERSED);
+            }
+        }
+    }
+
+    // merge characters with equal styles into spans
+    let mut spans = Vec::new();
+    let mut current = String::new();
+    let mut current_style = Style::default();
+    for (pos, c) in line.char_indices() {
+        if styles[pos] != current_style && !current.is_empty() {
+            spans.push(Span::styled(std::mem::take(&mut current), current_style));
+        }
+        current_style = styles[pos];
+        current.push(c);
+    }
+    if !current.is_empty() {
+        spans.push(Span::styled(current, current_style));
+    }
+    spans
+}
+
+/// Compute a style for each byte of `line`
+fn syntax_styles(line: &str, syntax: &Syntax) -> Vec<Style> {
+    let comment = Style::default().fg(Color::DarkGray);
+    let string = Style::default().fg(Color::Green);
+    let number = Style::default().fg(Color::Magenta);
+    let keyword = Style::default().fg(Color::Yellow);
+
+    let mut styles = vec![Style::default(); line.len()];
+    let bytes = line.as_bytes();
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let rest = &line[pos..];
+        if syntax.line_comment.iter().any(|c| rest.starts_with(c)) {
+            styles[pos..].fill(comment);
+            break;
+        }
+        let b = bytes[pos];
+        if b == b'"' || b == b'\'' {
+            let mut end = pos + 1;
+            while end < bytes.len() && bytes[end] != b {
+                if bytes[end] == b'\\' {
+                    end += 1;
+                }
+                end += 1;
+            }
+            let end = (end + 1).min(bytes.len());
+            styles[pos..end].fill(string);
+            pos = end;
+        } else if b.is_ascii_alphanumeric() || b == b'_' {
+            let len = rest
+                .bytes()
+                .take_while(|b| b.is_ascii_alphanumeric() || *b == b'_')
+                .count();
+            let word = &rest[..len];
+            if b.is_ascii_digit() {
+                styles[pos..pos + len].fill(number);
+            } else if syntax.keywords.contains(&word) {
+                styles[pos..pos + len].fill(keyword);
+            }
+            pos += len;
+        } else {
+            pos += rest.chars().next().map_or(1, char::len_utf8);
+        }
+    }
+    styles
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    impl Source for Vec<u8> {
+        fn size(&self) -> u64 {
+            self.len() as u64
+        }
+        fn read_at(&self, offset: u64, length: usize) -> Result<Rc<[u8]>> {
+            let start = usize::try_from(offset)?.min(self.len());
+            let end = (start + length).min(self.len());
+            Ok(self[start..end].into())
+        }
+    }
+
+    #[test]
+    fn cache_reads_across_chunks() -> Result<()> {
+        let data: Vec<u8> = (0..3 * CHUNK_SIZE).map(|i| (i % 251) as u8).collect();
+        let mut cache = ChunkCache::new(data.clone());
+        let offset = CHUNK_SIZE - 10;
+        assert_eq!(cache.read(offset as u64, 20)?, data[offset..offset + 20]);
+        assert_eq!(
+            cache.read((3 * CHUNK_SIZE - 5) as u64, 20)?,
+            data[3 * CHUNK_SIZE - 5..]
+        );
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("", 1)]
+    #[case("a", 1)]
+    #[case("a\n", 1)]
+    #[case("a\nb", 2)]
+    #[case("a\n\nb\n", 3)]
+    fn line_index_counts_lines(#[case] content: &str, #[case] lines: usize) -> Result<()> {
+        let mut cache = ChunkCache::new(content.as_bytes().to_vec());
+        let mut index = LineIndex::new();
+        index.scan_to(&mut cache, usize::MAX - 1)?;
+        assert_eq!(index.lines(cache.size()), lines);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(b"hello world", true, true)]
+    #[case(b"hello\0world", true, false)]
+    #[case(b"\xc3\xa4", true, true)]
+    #[case(b"abc\xc3", true, false)]
+    #[case(b"abc\xc3", false, true)]
+    #[case(b"\xff\xfe", false, false)]
+    fn text_is_detected(#[case] data: &[u8], #[case] complete: bool, #[case] expected: bool) {
+        assert_eq!(is_text(data, complete), expected);
+    }
+
+    #[test]
+    fn tabs_are_expanded() {
+        assert_eq!(expand_tabs("a\tb\t\tc"), "a   b       c");
+    }
+
+    #[test]
+    fn syntax_is_found_by_extension() {
+        assert!(Syntax::from_name("main.rs").is_some());
+        assert!(Syntax::from_name("CONFIG.TOML").is_some());
+        assert!(Syntax::from_name("README").is_none());
+    }
+
+    #[test]
+    fn line_index_finds_lines_across_checkpoints() -> Result<()> {
+        let content: String = (0..3 * CHECKPOINT_LINES)
+            .map(|i| format!("line {i}\n"))
+            .collect();
+        let mut cache = ChunkCache::new(content.as_bytes().to_vec());
+        let mut index = LineIndex::new();
+        index.scan_to(&mut cache, usize::MAX - 1)?;
+        assert_eq!(index.lines(cache.size()), 3 * CHECKPOINT_LINES);
+        for line in [
+            0,
+            1,
+            CHECKPOINT_LINES - 1,
+            CHECKPOINT_LINES,
+            3 * CHECKPOINT_LINES - 1,
+        ] {
+            let (start, end) = index.range(&mut cache, line)?.unwrap();
+            let start = usize::try_from(start)?;
+            let end = usize::try_from(end)?;
+            assert_eq!(content[start..end], format!("line {line}"));
+            assert_eq!(index.line_of(&mut cache, (start + 2) as u64)?, line);
+        }
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("x.yaml", "name: Müller")]
+    #[case("x.rs", r#"let s = "\ä"; // ö"#)]
+    #[case("x.py", "größe = 1 # ß")]
+    fn highlight_non_ascii(#[case] name: &str, #[case] line: &str) {
+        let syntax = Syntax::from_name(name);
+        let text: String = highlight(line, syntax, Some("ü"))
+            .iter()
+            .map(|s| s.content.to_string())
+            .collect();
+        assert_eq!(text, line);
+    }
+
+    #[test]
+    fn highlight_keeps_text() {
+        let line = r#"let s = "a # b"; // comment"#;
+        let syntax = Syntax::from_name("x.rs");
+        let text: String = highlight(line, syntax, Some("s"))
+            .iter()
+            .map(|s| s.content.to_string())
+            .collect();
+        assert_eq!(text, line);
+    }
+}
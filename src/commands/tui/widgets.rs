@@ -56,17 +56,6 @@ pub fn popup_input(
     ))
 }
 
-pub fn popup_scrollable_text(
-    title: impl Into<Line<'static>>,
-    text: &str,
-    lines: u16,
-) -> PopUpInput {
-    PopUp(WithBlock::new(
-        TextInput::new(None, text, lines, false),
-        Block::bordered().title(title),
-    ))
-}
-
 pub type PopUpText = PopUp<WithBlock<SizedParagraph>>;
 pub fn popup_text(title: impl Into<Line<'static>>, text: Text<'static>) -> PopUpText {
     PopUp(WithBlock::new(
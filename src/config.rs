@@ -11,6 +11,7 @@ pub(crate) mod progress_options;
 use std::{
     collections::BTreeMap,
     fmt::{self, Display, Formatter},
+    net::SocketAddr,
     path::PathBuf,
 };
 
@@ -35,10 +36,10 @@ use crate::commands::mount::MountCmd;
 use crate::commands::webdav::WebDavCmd;
 
 use crate::{
-    commands::{backup::BackupCmd, copy::CopyCmd, forget::ForgetOptions},
+    commands::{backup::BackupCmd, copy::CopyCmd, forget::ForgetOptions, status::StatusCmd},
     config::{hooks::Hooks, logging::LoggingOptions, progress_options::ProgressOptions},
     filtering::SnapshotFilter,
-    repository::AllRepositoryOptions,
+    repository::{AllRepositoryOptions, throttle::ThrottleOptions},
 };
 
 /// Rustic Configuration
@@ -74,6 +75,10 @@ pub struct RusticConfig {
     #[clap(skip)]
     pub forget: ForgetOptions,
 
+    /// Status options
+    #[clap(skip)]
+    pub status: StatusCmd,
+
     /// mount options
     #[cfg(feature = "mount")]
     #[clap(skip)]
@@ -117,12 +122,7 @@ impl RusticConfig {
         merge_logs: &mut Vec<(Level, String)>,
         level_missing: Level,
     ) -> Result<(), FrameworkError> {
-        let profile_filename = if profile.ends_with(".toml") {
-            profile.to_string()
-        } else {
-            profile.to_string() + ".toml"
-        };
-        let paths = get_config_paths(&profile_filename);
+        let paths = get_config_paths(&profile_filename(profile));
 
         if let Some(path) = paths.iter().find(|path| path.exists()) {
             merge_logs.push((Level::Info, format!("using config {}", path.display())));
@@ -219,6 +219,21 @@ pub struct GlobalOptions {
     #[serde(flatten)]
     pub progress_options: ProgressOptions,
 
+    /// Settings to limit transfer rates
+    #[clap(flatten)]
+    #[serde(flatten)]
+    pub throttle_options: ThrottleOptions,
+
+    /// Serve the state of all active progress bars as JSON on the given Unix socket
+    #[clap(long, global = true, env = "RUSTIC_PROGRESS_SOCKET", value_name = "PATH", value_hint = ValueHint::FilePath)]
+    #[merge(strategy=conflate::option::overwrite_none)]
+    pub progress_socket: Option<PathBuf>,
+
+    /// Serve the state of all active progress bars as JSON via HTTP on the given address, e.g. "127.0.0.1:8090"
+    #[clap(long, global = true, env = "RUSTIC_PROGRESS_HTTP", value_name = "ADDR")]
+    #[merge(strategy=conflate::option::overwrite_none)]
+    pub progress_http: Option<SocketAddr>,
+
     /// Hooks
     #[clap(skip)]
     pub hooks: Hooks,
@@ -244,8 +259,13 @@ pub struct GlobalOptions {
     #[merge(strategy=conflate::option::overwrite_none)]
     pub prometheus_pass: Option<String>,
 
-    /// Additional labels to set to generated metrics
-    #[clap(skip)]
+    /// Write metrics to .prom files in the given directory, e.g. for the textfile collector of the node exporter
+    #[clap(long, global = true, env = "RUSTIC_PROMETHEUS_TEXTFILE_DIR", value_name = "DIR", value_hint = ValueHint::DirPath)]
+    #[merge(strategy=conflate::option::overwrite_none)]
+    pub prometheus_textfile_dir: Option<PathBuf>,
+
+    /// Additional labels to set to metrics generated by any command
+    #[clap(long, global = true, value_name = "NAME=VALUE", value_parser = parse_labels, default_value = "")]
     #[merge(strategy=conflate::btreemap::append_or_ignore)]
     pub metrics_labels: BTreeMap<String, String>,
 
@@ -255,6 +275,25 @@ pub struct GlobalOptions {
     #[merge(strategy=conflate::option::overwrite_none)]
     pub opentelemetry: Option<Url>,
 
+    /// OpenTelemetry traces endpoint (HTTP Protobuf)
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[clap(long, global = true, env = "RUSTIC_OTEL_TRACES", value_name = "ENDPOINT_URL", value_hint = ValueHint::Url)]
+    #[merge(strategy=conflate::option::overwrite_none)]
+    pub opentelemetry_traces: Option<Url>,
+
+    /// OpenTelemetry logs endpoint (HTTP Protobuf)
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[clap(long, global = true, env = "RUSTIC_OTEL_LOGS", value_name = "ENDPOINT_URL", value_hint = ValueHint::Url)]
+    #[merge(strategy=conflate::option::overwrite_none)]
+    pub opentelemetry_logs: Option<Url>,
+
+    /// OpenTelemetry collector base URL (HTTP Protobuf). Metrics, traces and logs are sent to <URL>/v1/metrics,
+    /// <URL>/v1/traces and <URL>/v1/logs unless their endpoints are given explicitly
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[clap(long, global = true, env = "RUSTIC_OTEL_ENDPOINT", value_name = "URL", value_hint = ValueHint::Url)]
+    #[merge(strategy=conflate::option::overwrite_none)]
+    pub otel: Option<Url>,
+
     /// Show time offsets instead of converting to system time zone
     #[clap(long, global = true, env = "RUSTIC_SHOW_TIME_OFFSET")]
     #[merge(strategy=conflate::bool::overwrite_false)]
@@ -278,7 +317,37 @@ pub fn parse_labels(s: &str) -> Result<BTreeMap<String, String>> {
 
 impl GlobalOptions {
     pub fn is_metrics_configured(&self) -> bool {
-        self.prometheus.is_some() || self.opentelemetry.is_some()
+        self.prometheus.is_some()
+            || self.prometheus_textfile_dir.is_some()
+            || self.opentelemetry_metrics_endpoint().is_some()
+    }
+
+    /// The endpoint to push OpenTelemetry metrics to
+    pub fn opentelemetry_metrics_endpoint(&self) -> Option<Url> {
+        self.otel_endpoint(self.opentelemetry.as_ref(), "metrics")
+    }
+
+    /// The endpoint to export OpenTelemetry traces to
+    pub fn opentelemetry_traces_endpoint(&self) -> Option<Url> {
+        self.otel_endpoint(self.opentelemetry_traces.as_ref(), "traces")
+    }
+
+    /// The endpoint to export OpenTelemetry logs to
+    pub fn opentelemetry_logs_endpoint(&self) -> Option<Url> {
+        self.otel_endpoint(self.opentelemetry_logs.as_ref(), "logs")
+    }
+
+    fn otel_endpoint(&self, endpoint: Option<&Url>, signal: &str) -> Option<Url> {
+        if let Some(endpoint) = endpoint {
+            return Some(endpoint.clone());
+        }
+        let mut url = self.otel.clone()?;
+        _ = url
+            .path_segments_mut()
+            .ok()?
+            .pop_if_empty()
+            .extend(["v1", signal]);
+        Some(url)
     }
 
     pub fn format_timestamp(&self, timestamp: Timestamp) -> String {
@@ -300,6 +369,28 @@ impl GlobalOptions {
     }
 }
 
+/// Get the file name of a profile
+fn profile_filename(profile: &str) -> String {
+    if profile.ends_with(".toml") {
+        profile.to_string()
+    } else {
+        profile.to_string() + ".toml"
+    }
+}
+
+/// Get the path of the config file of an existing profile
+pub fn profile_path(profile: &str) -> Option<PathBuf> {
+    get_config_paths(&profile_filename(profile))
+        .into_iter()
+        .find(|path| path.exists())
+}
+
+/// Get the path where a new profile is written to, i.e. the file within the user config dir
+pub fn user_profile_path(profile: &str) -> Option<PathBuf> {
+    ProjectDirs::from("", "", "rustic")
+        .map(|project_dirs| project_dirs.config_dir().join(profile_filename(profile)))
+}
+
 /// Get the paths to the config file
 ///
 /// # Arguments
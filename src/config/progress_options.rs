@@ -19,6 +19,10 @@ use serde_with::{DisplayFromStr, serde_as};
 
 use rustic_core::{Progress, ProgressBars, ProgressType, RusticProgress};
 
+use crate::telemetry;
+
+pub(crate) mod status;
+
 /// Returns the global `MultiProgress` instance used by all interactive progress bars.
 ///
 /// Must be shared with `indicatif_log_bridge::LogWrapper` so that log output
@@ -126,7 +130,9 @@ impl ProgressOptions {
 
 impl ProgressBars for ProgressOptions {
     fn progress(&self, progress_kind: ProgressType, prefix: &str) -> Progress {
-        self.create_progress(prefix, progress_kind)
+        let progress = self.create_progress(prefix, progress_kind);
+        let progress = status::track_progress(prefix, progress_kind, progress);
+        telemetry::trace_progress(prefix, progress)
     }
 }
 
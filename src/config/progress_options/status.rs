@@ -0,0 +1,364 @@
+//! Serve the state of all active progress bars as JSON
+//!
+//! This allows to query the progress of a long-running command from another process, e.g. using
+//! `nc -U <PATH>` for the Unix socket or `curl http://<ADDR>/` for the HTTP endpoint.
+
+use std::{
+    collections::BTreeMap,
+    io::{Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex, OnceLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use log::{info, warn};
+use rustic_core::{Progress, ProgressType, RusticProgress};
+use serde::Serialize;
+
+/// Maximum time to wait for a HTTP request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Maximum size of a HTTP request head
+const MAX_REQUEST_SIZE: usize = 8192;
+
+/// The running status server; progress is only tracked if it is set
+static SERVER: OnceLock<Server> = OnceLock::new();
+
+#[derive(Debug)]
+struct Server {
+    command: String,
+    socket: Option<PathBuf>,
+    active: Mutex<BTreeMap<u64, Arc<Mutex<ProgressState>>>>,
+    next_id: AtomicU64,
+}
+
+/// State of all active progress bars
+#[derive(Serialize)]
+struct Status<'a> {
+    command: &'a str,
+    pid: u32,
+    progress: Vec<ProgressStatus>,
+}
+
+/// State of a single progress bar
+#[derive(Serialize)]
+struct ProgressStatus {
+    phase: String,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    position: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    length: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    percent_done: Option<f64>,
+    seconds_elapsed: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seconds_remaining: Option<u64>,
+}
+
+#[derive(Debug)]
+struct ProgressState {
+    title: String,
+    kind: ProgressType,
+    position: u64,
+    length: Option<u64>,
+    start: Instant,
+}
+
+impl ProgressState {
+    fn status(&self) -> ProgressStatus {
+        let elapsed = self.start.elapsed().as_secs();
+        let length = self.length.filter(|len| *len > 0);
+        #[allow(clippy::cast_precision_loss)]
+        let percent_done = length.map(|len| (self.position as f64 / len as f64 * 100.0).min(100.0));
+        let seconds_remaining = match (self.position, length) {
+            (position, Some(len)) if position > 0 && len > position => {
+                Some(elapsed.saturating_mul(len - position) / position)
+            }
+            _ => None,
+        };
+        ProgressStatus {
+            phase: self.title.trim_end_matches('.').to_string(),
+            kind: match self.kind {
+                ProgressType::Spinner => "spinner",
+                ProgressType::Counter => "counter",
+                ProgressType::Bytes => "bytes",
+            },
+            position: self.position,
+            length,
+            percent_done,
+            seconds_elapsed: elapsed,
+            seconds_remaining,
+        }
+    }
+}
+
+impl Server {
+    fn status_json(&self) -> Vec<u8> {
+        let progress = self
+            .active
+            .lock()
+            .map(|active| {
+                active
+                    .values()
+                    .filter_map(|state| state.lock().ok().map(|state| state.status()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let status = Status {
+            command: &self.command,
+            pid: std::process::id(),
+            progress,
+        };
+        let mut json = serde_json::to_vec(&status).unwrap_or_default();
+        json.push(b'\n');
+        json
+    }
+}
+
+/// Start serving the progress state on the given Unix socket and/or HTTP address
+///
+/// # Errors
+///
+/// * If the socket or address cannot be bound
+pub(crate) fn serve(command: &str, socket: Option<&Path>, http: Option<SocketAddr>) -> Result<()> {
+    if socket.is_none() && http.is_none() {
+        return Ok(());
+    }
+
+    let socket_listener = socket.map(bind_socket).transpose()?;
+    let http_listener = http
+        .map(|addr| {
+            TcpListener::bind(addr)
+                .with_context(|| format!("cannot listen on {addr} for serving the progress"))
+        })
+        .transpose()?;
+
+    let server = Server {
+        command: command.to_string(),
+        socket: socket.map(Path::to_path_buf),
+        active: Mutex::default(),
+        next_id: AtomicU64::new(0),
+    };
+    if SERVER.set(server).is_err() {
+        // already serving
+        return Ok(());
+    }
+
+    if let Some(listener) = socket_listener {
+        _ = thread::spawn(move || serve_socket(&listener));
+    }
+    if let Some(listener) = http_listener {
+        if let Ok(addr) = listener.local_addr() {
+            info!("serving progress on http://{addr}/");
+        }
+        _ = thread::spawn(move || serve_http(&listener));
+    }
+    Ok(())
+}
+
+/// Remove the Unix socket, if one is served
+pub(crate) fn shutdown() {
+    if let Some(socket) = SERVER.get().and_then(|server| server.socket.as_ref()) {
+        _ = std::fs::remove_file(socket);
+    }
+}
+
+/// Wrap the progress such that its state is served, if a status server is running
+pub(crate) fn track_progress(prefix: &str, kind: ProgressType, progress: Progress) -> Progress {
+    let Some(server) = SERVER.get() else {
+        return progress;
+    };
+    let id = server.next_id.fetch_add(1, Ordering::Relaxed);
+    let state = Arc::new(Mutex::new(ProgressState {
+        title: prefix.to_string(),
+        kind,
+        position: 0,
+        length: None,
+        start: Instant::now(),
+    }));
+    if let Ok(mut active) = server.active.lock() {
+        _ = active.insert(id, state.clone());
+    }
+    Progress::new(TrackedProgress {
+        progress,
+        id,
+        state,
+    })
+}
+
+/// A progress whose state is served while it is active
+#[derive(Debug)]
+struct TrackedProgress {
+    progress: Progress,
+    id: u64,
+    state: Arc<Mutex<ProgressState>>,
+}
+
+impl TrackedProgress {
+    fn update(&self, f: impl FnOnce(&mut ProgressState)) {
+        if let Ok(mut state) = self.state.lock() {
+            f(&mut state);
+        }
+    }
+
+    fn remove(&self) {
+        if let Some(server) = SERVER.get()
+            && let Ok(mut active) = server.active.lock()
+        {
+            _ = active.remove(&self.id);
+        }
+    }
+}
+
+impl RusticProgress for TrackedProgress {
+    fn is_hidden(&self) -> bool {
+        // the state is served, so the progress needs to be computed
+        false
+    }
+
+    fn set_length(&self, len: u64) {
+        self.update(|state| state.length = Some(len));
+        self.progress.set_length(len);
+    }
+
+    fn set_title(&self, title: &str) {
+        self.update(|state| state.title = title.to_string());
+        self.progress.set_title(title);
+    }
+
+    fn inc(&self, inc: u64) {
+        self.update(|state| state.position += inc);
+        self.progress.inc(inc);
+    }
+
+    fn finish(&self) {
+        self.remove();
+        self.progress.finish();
+    }
+}
+
+impl Drop for TrackedProgress {
+    fn drop(&mut self) {
+        self.remove();
+    }
+}
+
+#[cfg(unix)]
+type SocketListener = std::os::unix::net::UnixListener;
+
+#[cfg(unix)]
+fn bind_socket(path: &Path) -> Result<SocketListener> {
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            anyhow::bail!("progress socket {} is already in use.", path.display());
+        }
+        // remove the stale socket of a previous run
+        std::fs::remove_file(path)
+            .with_context(|| format!("cannot remove stale socket {}", path.display()))?;
+    }
+    UnixListener::bind(path)
+        .with_context(|| format!("cannot bind progress socket {}", path.display()))
+}
+
+#[cfg(unix)]
+fn serve_socket(listener: &SocketListener) {
+    for stream in listener.incoming() {
+        let Some(server) = SERVER.get() else {
+            return;
+        };
+        match stream {
+            Ok(mut stream) => _ = stream.write_all(&server.status_json()),
+            Err(err) => warn!("error accepting connection on progress socket: {err}"),
+        }
+    }
+}
+
+#[cfg(not(unix))]
+type SocketListener = ();
+
+#[cfg(not(unix))]
+fn bind_socket(_path: &Path) -> Result<SocketListener> {
+    anyhow::bail!("progress sockets are only supported on Unix, please use --progress-http.");
+}
+
+#[cfg(not(unix))]
+fn serve_socket(_listener: &SocketListener) {}
+
+fn serve_http(listener: &TcpListener) {
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                if let Err(err) = respond_http(stream) {
+                    warn!("error serving progress: {err}");
+                }
+            }
+            Err(err) => warn!("error accepting connection for serving progress: {err}"),
+        }
+    }
+}
+
+fn respond_http(mut stream: TcpStream) -> Result<()> {
+    let Some(server) = SERVER.get() else {
+        return Ok(());
+    };
+    // read the request head; its content doesn't matter as every request gets the status
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+    while !request.ends_with(b"\r\n\r\n") && request.len() < MAX_REQUEST_SIZE {
+        let n = stream.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+
+    let body = server.status_json();
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    )?;
+    stream.write_all(&body)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(0, None, None, None)]
+    #[case(25, Some(100), Some(25.0), Some(30))]
+    #[case(100, Some(100), Some(100.0), None)]
+    #[case(10, Some(0), None, None)]
+    fn progress_status(
+        #[case] position: u64,
+        #[case] length: Option<u64>,
+        #[case] percent_done: Option<f64>,
+        #[case] seconds_remaining: Option<u64>,
+    ) {
+        let state = ProgressState {
+            title: "backing up...".to_string(),
+            kind: ProgressType::Bytes,
+            position,
+            length,
+            start: Instant::now() - Duration::from_secs(10),
+        };
+        let status = state.status();
+        assert_eq!(status.phase, "backing up");
+        assert_eq!(status.kind, "bytes");
+        assert_eq!(status.percent_done, percent_done);
+        assert_eq!(status.seconds_remaining, seconds_remaining);
+    }
+}
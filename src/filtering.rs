@@ -4,19 +4,27 @@ use crate::error::RhaiErrorKinds;
 #[cfg(feature = "rhai")]
 use std::error::Error;
 use std::{
+    collections::BTreeMap,
+    ffi::OsStr,
     fmt::{Debug, Display},
+    path::{Component, Path},
     str::FromStr,
+    sync::{Mutex, PoisonError},
 };
 
+use anyhow::Result;
 #[cfg(feature = "jq")]
 use anyhow::{anyhow, bail};
 use bytesize::ByteSize;
 use derive_more::derive::Display;
-use jiff::{Zoned, civil::Time, tz::TimeZone};
+use jiff::{
+    Span, Zoned,
+    civil::{Time, Weekday},
+};
 use log::warn;
 use rustic_core::{
-    StringList,
-    repofile::{RusticTime, SnapshotFile},
+    IndexedTree, Repository, StringList, TreeId,
+    repofile::{Metadata, Node, NodeType, RusticTime, SnapshotFile},
 };
 
 use cached::macros::cached;
@@ -36,6 +44,8 @@ use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use serde_with::{DisplayFromStr, serde_as};
 
+use crate::annotations::{Annotation, annotations, matches_filter, plain_tags};
+
 /// A function to filter snapshots
 ///
 /// The function is called with a [`SnapshotFile`] and must return a boolean.
@@ -180,13 +190,22 @@ pub struct SnapshotFilter {
     #[merge(strategy=conflate::vec::overwrite_empty)]
     filter_tags_exact: Vec<StringList>,
 
+    /// Annotation to filter as KEY=VALUE (can be specified multiple times; all keys must match one of
+    /// their given values)
+    #[clap(long = "filter-annotation", global = true, value_name = "KEY=VALUE")]
+    #[serde_as(as = "Vec<DisplayFromStr>")]
+    #[merge(strategy=conflate::vec::overwrite_empty)]
+    filter_annotations: Vec<Annotation>,
+
     /// Only use snapshots which are taken after the given given date/time
+    /// (absolute or relative like "7d", "2 weeks ago", "last monday" or "yesterday 18:00")
     #[serde_as(as = "Option<DisplayFromStr>")]
     #[clap(long, global = true, value_name = "DATE(TIME)")]
     #[merge(strategy=conflate::option::overwrite_none)]
     filter_after: Option<AfterDate>,
 
     /// Only use snapshots which are taken before the given given date/time
+    /// (absolute or relative like "7d", "2 weeks ago", "last monday" or "yesterday 18:00")
     #[serde_as(as = "Option<DisplayFromStr>")]
     #[clap(long, global = true, value_name = "DATE(TIME)")]
     #[merge(strategy=conflate::option::overwrite_none)]
@@ -210,6 +229,23 @@ pub struct SnapshotFilter {
     #[merge(strategy=conflate::option::overwrite_none)]
     filter_last: Option<usize>,
 
+    /// Only use snapshots which contain the given path (can be specified multiple times)
+    #[clap(long, global = true, value_name = "PATH")]
+    #[merge(strategy=conflate::vec::overwrite_empty)]
+    filter_contains: Vec<String>,
+
+    /// Only use snapshots which contain the given file with a modification time after the given date/time (can be specified multiple times)
+    #[clap(long, global = true, value_name = "PATH:DATE(TIME)")]
+    #[serde_as(as = "Vec<DisplayFromStr>")]
+    #[merge(strategy=conflate::vec::overwrite_empty)]
+    filter_file_newer: Vec<FileNewer>,
+
+    /// Only use snapshots which differ from the given snapshot (at the given path, if given)
+    #[clap(long, global = true, value_name = "SNAPSHOT[:PATH]")]
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[merge(strategy=conflate::option::overwrite_none)]
+    filter_changed_since: Option<ChangedSince>,
+
     /// Function to filter snapshots
     #[cfg(feature = "rhai")]
     #[clap(long, global = true, value_name = "FUNC")]
@@ -225,6 +261,11 @@ pub struct SnapshotFilter {
     filter_jq: Option<String>,
 }
 
+/// Results of the content-aware filters by snapshot tree, see [`SnapshotFilter::evaluate_content`]
+///
+/// The content-aware filters only depend on the tree, so snapshots with identical trees share the result.
+static CONTENT_MATCHES: Mutex<BTreeMap<TreeId, bool>> = Mutex::new(BTreeMap::new());
+
 impl SnapshotFilter {
     /// Check if a [`SnapshotFile`] matches the filter
     ///
@@ -237,6 +278,18 @@ impl SnapshotFilter {
     /// `true` if the snapshot matches the filter, `false` otherwise
     #[must_use]
     pub fn matches(&self, snapshot: &SnapshotFile) -> bool {
+        self.matches_without_content(snapshot)
+            && (!self.has_content_filter()
+                || CONTENT_MATCHES
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .get(&snapshot.tree)
+                    .copied()
+                    .unwrap_or_default())
+    }
+
+    // check all filters which don't need to read the snapshot contents
+    fn matches_without_content(&self, snapshot: &SnapshotFile) -> bool {
         #[cfg(feature = "rhai")]
         if let Some(filter_fn) = &self.filter_fn
             && let Some(func) = string_to_fn(filter_fn)
@@ -296,11 +349,72 @@ impl SnapshotFilter {
             && (self.filter_paths_exact.is_empty()
                 || self.filter_paths_exact.contains(&snapshot.paths))
             && (self.filter_tags_exact.is_empty()
-                || self.filter_tags_exact.contains(&snapshot.tags))
+                || self.filter_tags_exact.contains(&plain_tags(&snapshot.tags)))
+            && (self.filter_annotations.is_empty()
+                || matches_filter(&annotations(&snapshot.tags), &self.filter_annotations))
             && (self.filter_hosts.is_empty() || self.filter_hosts.contains(&snapshot.hostname))
             && (self.filter_labels.is_empty() || self.filter_labels.contains(&snapshot.label))
     }
 
+    /// Check if filters are given which need to read the snapshot contents from the repository
+    #[must_use]
+    pub fn has_content_filter(&self) -> bool {
+        !self.filter_contains.is_empty()
+            || !self.filter_file_newer.is_empty()
+            || self.filter_changed_since.is_some()
+    }
+
+    /// Evaluate the content-aware filters for the snapshots of the repository
+    ///
+    /// The trees of all snapshots matching the other filters are read; [`Self::matches`] uses the
+    /// results. Without content-aware filters, nothing is done.
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The repository to read the snapshots and trees from
+    ///
+    /// # Errors
+    ///
+    /// * If the snapshot given in `filter-changed-since` cannot be found
+    /// * If a tree of a snapshot cannot be read
+    pub fn evaluate_content<S: IndexedTree>(&self, repo: &Repository<S>) -> Result<()> {
+        if !self.has_content_filter() {
+            return Ok(());
+        }
+
+        let changed_since = self
+            .filter_changed_since
+            .as_ref()
+            .map(|changed| -> Result<_> {
+                let snap = repo.get_snapshot_from_str(&changed.snap, |_| true)?;
+                Ok((changed, find_node(repo, &snap, &changed.path)?))
+            })
+            .transpose()?;
+
+        let snapshots = repo.get_matching_snapshots(|sn| self.matches_without_content(sn))?;
+        let mut results = Vec::new();
+        for sn in snapshots {
+            let node = |path: &str| find_node(repo, &sn, path);
+            let mut matches = true;
+            for path in &self.filter_contains {
+                matches = matches && node(path)?.is_some();
+            }
+            for newer in &self.filter_file_newer {
+                matches = matches && node(&newer.path)?.is_some_and(|node| newer.matches(&node));
+            }
+            if let Some((changed, reference)) = &changed_since {
+                matches = matches
+                    && ChangedSince::changed(reference.as_ref(), node(&changed.path)?.as_ref());
+            }
+            results.push((sn.tree, matches));
+        }
+        CONTENT_MATCHES
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .extend(results);
+        Ok(())
+    }
+
     pub fn post_process(&self, snapshots: &mut Vec<SnapshotFile>) {
         snapshots.sort_unstable();
         if let Some(last) = self.filter_last {
@@ -313,6 +427,40 @@ impl SnapshotFilter {
     }
 }
 
+/// Get the node of the given path within the snapshot
+///
+/// Returns `None` if the path doesn't exist in the snapshot.
+///
+/// # Errors
+///
+/// * If a tree cannot be read from the repository
+fn find_node<S: IndexedTree>(
+    repo: &Repository<S>,
+    snap: &SnapshotFile,
+    path: &str,
+) -> Result<Option<Node>> {
+    let mut node = Node::new_node(OsStr::new(""), NodeType::Dir, Metadata::default());
+    node.subtree = Some(snap.tree);
+    for comp in Path::new(path).components() {
+        let Component::Normal(name) = comp else {
+            continue;
+        };
+        let Some(id) = node.subtree else {
+            return Ok(None);
+        };
+        let Some(found) = repo
+            .get_tree(&id)?
+            .nodes
+            .into_iter()
+            .find(|node| node.name() == name)
+        else {
+            return Ok(None);
+        };
+        node = found;
+    }
+    Ok(Some(node))
+}
+
 #[derive(Debug, Clone, Display)]
 struct AfterDate(Zoned);
 
@@ -325,7 +473,7 @@ impl AfterDate {
 impl FromStr for AfterDate {
     type Err = anyhow::Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(Self(RusticTime::parse(s, Time::MAX, TimeZone::system())?))
+        Ok(Self(parse_time(s, Time::MAX)?))
     }
 }
 
@@ -341,7 +489,181 @@ impl BeforeDate {
 impl FromStr for BeforeDate {
     type Err = anyhow::Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(Self(RusticTime::parse_system(s)?))
+        Ok(Self(parse_time(s, Time::MIN)?))
+    }
+}
+
+/// Best-effort parsing of a string into a `Zoned` which may also be given relative to now.
+///
+/// Uses `default_time` if no time is given and the system timezone if no zone is given.
+/// See [`parse_time_relative_to`] for the supported formats.
+///
+/// # Errors
+///
+/// * If the string can neither be parsed as relative nor as absolute date/time
+pub(crate) fn parse_time(s: &str, default_time: Time) -> Result<Zoned> {
+    parse_time_relative_to(s, default_time, &Zoned::now())
+}
+
+/// Best-effort parsing of a string into a `Zoned` which may also be given relative to now.
+///
+/// Uses 00:00 if no time is given and the system timezone if no zone is given.
+///
+/// # Errors
+///
+/// * If the string can neither be parsed as relative nor as absolute date/time
+pub(crate) fn parse_time_system(s: &str) -> Result<Zoned> {
+    parse_time(s, Time::MIN)
+}
+
+/// Parse a string into a `Zoned` relative to `now`.
+///
+/// Supported are
+/// - durations in "friendly" or ISO 8601 format, e.g. "7d", "2 weeks ago", "1h30m" or "P1W".
+///   Durations always refer to the past, i.e. "7d" and "7d ago" are the same.
+/// - "now"
+/// - days relative to today: "today", "yesterday", "monday" or "last monday" (the last monday before today),
+///   optionally followed by a time, e.g. "yesterday 18:00"
+/// - absolute date/times as understood by [`RusticTime::parse`]
+fn parse_time_relative_to(s: &str, default_time: Time, now: &Zoned) -> Result<Zoned> {
+    let s = s.trim();
+    if s.eq_ignore_ascii_case("now") {
+        return Ok(now.clone());
+    }
+
+    if let Ok(span) = Span::from_str(s) {
+        let span = if span.is_negative() {
+            span
+        } else {
+            span.negate()
+        };
+        return Ok(now.checked_add(span)?);
+    }
+
+    let lower = s.to_lowercase();
+    let mut words = lower.split_whitespace();
+    let date = match words.next() {
+        Some("today") => Some(now.date()),
+        Some("yesterday") => Some(now.date().yesterday()?),
+        Some("last") => match words.next().and_then(parse_weekday) {
+            Some(weekday) => Some(now.date().nth_weekday(-1, weekday)?),
+            None => None,
+        },
+        Some(word) => match parse_weekday(word) {
+            Some(weekday) => Some(now.date().nth_weekday(-1, weekday)?),
+            None => None,
+        },
+        None => None,
+    };
+
+    if let Some(date) = date {
+        let time = match words.next() {
+            Some(time) => Time::from_str(time)?,
+            None => default_time,
+        };
+        if let Some(word) = words.next() {
+            anyhow::bail!("unexpected \"{word}\" in date/time \"{s}\"");
+        }
+        return Ok(date.to_datetime(time).to_zoned(now.time_zone().clone())?);
+    }
+
+    Ok(RusticTime::parse(s, default_time, now.time_zone().clone())?)
+}
+
+pub(crate) fn parse_weekday(s: &str) -> Option<Weekday> {
+    let weekday = match s {
+        "monday" | "mon" => Weekday::Monday,
+        "tuesday" | "tue" => Weekday::Tuesday,
+        "wednesday" | "wed" => Weekday::Wednesday,
+        "thursday" | "thu" => Weekday::Thursday,
+        "friday" | "fri" => Weekday::Friday,
+        "saturday" | "sat" => Weekday::Saturday,
+        "sunday" | "sun" => Weekday::Sunday,
+        _ => return None,
+    };
+    Some(weekday)
+}
+
+#[derive(Debug, Clone)]
+struct FileNewer {
+    path: String,
+    time: Zoned,
+}
+
+impl FileNewer {
+    fn matches(&self, node: &Node) -> bool {
+        node.meta
+            .mtime
+            .is_some_and(|mtime| self.time.timestamp() < mtime)
+    }
+}
+
+impl FromStr for FileNewer {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Both the path and the time may contain `:`, so start with the last `:` and use the first
+        // split which gives a valid time.
+        let mut err = anyhow::anyhow!("expected PATH:DATE(TIME), got \"{s}\"");
+        for (pos, _) in s.rmatch_indices(':') {
+            match parse_time(&s[pos + 1..], Time::MIN) {
+                Ok(time) => {
+                    return Ok(Self {
+                        path: s[..pos].to_string(),
+                        time,
+                    });
+                }
+                Err(e) => err = e,
+            }
+        }
+        Err(err)
+    }
+}
+
+impl Display for FileNewer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.path, self.time)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ChangedSince {
+    snap: String,
+    path: String,
+}
+
+impl ChangedSince {
+    /// Check if the contents of two (optional) nodes differ
+    fn changed(reference: Option<&Node>, node: Option<&Node>) -> bool {
+        match (reference, node) {
+            (None, None) => false,
+            (Some(reference), Some(node)) => {
+                reference.node_type != node.node_type
+                    || reference.subtree != node.subtree
+                    || reference.content != node.content
+            }
+            _ => true,
+        }
+    }
+}
+
+impl FromStr for ChangedSince {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (snap, path) = s.split_once(':').unwrap_or((s, ""));
+        Ok(Self {
+            snap: snap.to_string(),
+            path: path.to_string(),
+        })
+    }
+}
+
+impl Display for ChangedSince {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.snap)?;
+        if !self.path.is_empty() {
+            write!(f, ":{}", self.path)?;
+        }
+        Ok(())
     }
 }
 
@@ -415,4 +737,56 @@ mod tests {
         assert_eq!(input.from.map(|v| v.0), from);
         assert_eq!(input.to.map(|v| v.0), to);
     }
+
+    #[rstest]
+    #[case("now", "2024-03-14T12:30:00")]
+    #[case("7d", "2024-03-07T12:30:00")]
+    #[case("2 weeks ago", "2024-02-29T12:30:00")]
+    #[case("1h30m", "2024-03-14T11:00:00")]
+    #[case("P1W", "2024-03-07T12:30:00")]
+    #[case("today", "2024-03-14T00:00:00")]
+    #[case("yesterday 18:00", "2024-03-13T18:00:00")]
+    #[case("last monday", "2024-03-11T00:00:00")]
+    #[case("Thursday", "2024-03-07T00:00:00")]
+    #[case("2024-01-01", "2024-01-01T00:00:00")]
+    #[case("2024-01-01 11:15:23", "2024-01-01T11:15:23")]
+    fn parse_time_relative(#[case] input: &str, #[case] expected: &str) {
+        let now = Zoned::from_str("2024-03-14T12:30:00[UTC]").unwrap();
+        let time = parse_time_relative_to(input, Time::MIN, &now).unwrap();
+        assert_eq!(time.datetime().to_string(), expected);
+    }
+
+    #[rstest]
+    #[case(
+        "/etc/nginx/nginx.conf:2024-01-01",
+        "/etc/nginx/nginx.conf",
+        "2024-01-01T00:00:00"
+    )]
+    #[case("file:2024-01-01 18:00", "file", "2024-01-01T18:00:00")]
+    #[case("/data/a:b:2024-01-01 18:00:30", "/data/a:b", "2024-01-01T18:00:30")]
+    #[case("/data/a:b:2024-01-01", "/data/a:b", "2024-01-01T00:00:00")]
+    fn file_newer_from_str(#[case] input: FileNewer, #[case] path: &str, #[case] time: &str) {
+        assert_eq!(input.path, path);
+        assert_eq!(input.time.datetime().to_string(), time);
+    }
+
+    #[rstest]
+    #[case("latest", "latest", "")]
+    #[case("01a2b3c4:/etc/nginx", "01a2b3c4", "/etc/nginx")]
+    fn changed_since_from_str(#[case] input: &str, #[case] snap: &str, #[case] path: &str) {
+        let changed = ChangedSince::from_str(input).unwrap();
+        assert_eq!(changed.snap, snap);
+        assert_eq!(changed.path, path);
+        assert_eq!(changed.to_string(), input);
+    }
+
+    #[rstest]
+    #[case("last")]
+    #[case("yesterday 25:00")]
+    #[case("monday 10:00 foo")]
+    #[case("no date")]
+    fn parse_time_relative_fails(#[case] input: &str) {
+        let now = Zoned::from_str("2024-03-14T12:30:00[UTC]").unwrap();
+        assert!(parse_time_relative_to(input, Time::MIN, &now).is_err());
+    }
 }
@@ -59,15 +59,16 @@ Application based on the [Abscissa] framework.
     clippy::missing_const_for_fn
 )]
 
+pub(crate) mod annotations;
 pub mod application;
 pub(crate) mod commands;
 pub(crate) mod config;
 pub(crate) mod error;
 pub(crate) mod filtering;
 pub(crate) mod helpers;
-#[cfg(any(feature = "prometheus", feature = "opentelemetry"))]
 pub(crate) mod metrics;
 pub(crate) mod repository;
+pub(crate) mod telemetry;
 
 // rustic_cli Public API
 
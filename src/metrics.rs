@@ -1,16 +1,25 @@
+use std::{borrow::Cow, collections::BTreeMap, time::Instant};
+
 use anyhow::Result;
+use jiff::Timestamp;
+use log::warn;
+
+use crate::{Application, RUSTIC_APP};
 
+#[derive(Debug, Clone, Copy)]
 pub enum MetricValue {
     Int(u64),
     Float(f64),
 }
 
+#[derive(Debug)]
 pub struct Metric {
-    pub name: &'static str,
+    pub name: Cow<'static, str>,
     pub description: &'static str,
     pub value: MetricValue,
 }
 
+#[cfg(any(feature = "prometheus", feature = "opentelemetry"))]
 pub trait MetricsExporter {
     fn push_metrics(&self, metrics: &[Metric]) -> Result<()>;
 }
@@ -20,3 +29,172 @@ pub mod prometheus;
 
 #[cfg(feature = "opentelemetry")]
 pub mod opentelemetry;
+
+/// Convert a timestamp into seconds since the epoch
+#[allow(clippy::cast_precision_loss)]
+pub fn timestamp_secs(timestamp: Timestamp) -> f64 {
+    timestamp.as_millisecond() as f64 / 1000.
+}
+
+/// Metrics of a command run which are published once the command has finished
+///
+/// All metrics are named `rustic_<command>_<name>`. Additionally to the metrics added by the command,
+/// the duration, a success flag and (for successful runs) the timestamp of the last success are published.
+#[derive(Debug)]
+pub struct CommandMetrics {
+    command: &'static str,
+    job_name: String,
+    labels: BTreeMap<String, String>,
+    start: Instant,
+    metrics: Vec<Metric>,
+}
+
+impl CommandMetrics {
+    pub fn new(command: &'static str) -> Self {
+        Self {
+            command,
+            job_name: format!("rustic_{command}"),
+            labels: BTreeMap::new(),
+            start: Instant::now(),
+            metrics: Vec::new(),
+        }
+    }
+
+    /// Use the given job name instead of `rustic_<command>`
+    pub fn with_job_name(mut self, job_name: Option<String>) -> Self {
+        if let Some(job_name) = job_name {
+            self.job_name = job_name;
+        }
+        self
+    }
+
+    /// Set a label for the published metrics; labels which are already set are kept
+    ///
+    /// The global metrics labels are added when publishing.
+    pub fn add_label(&mut self, name: &str, value: impl Into<String>) {
+        _ = self
+            .labels
+            .entry(name.to_string())
+            .or_insert_with(|| value.into());
+    }
+
+    pub fn add(&mut self, name: &str, description: &'static str, value: MetricValue) {
+        self.metrics.push(Metric {
+            name: format!("rustic_{}_{name}", self.command).into(),
+            description,
+            value,
+        });
+    }
+
+    /// Publish the metrics if metrics are configured; errors are only reported as warnings
+    pub fn publish(mut self, success: bool) {
+        let config = RUSTIC_APP.config();
+        if !config.global.is_metrics_configured() {
+            return;
+        }
+        self.add(
+            "duration",
+            "Duration of the command run in seconds",
+            MetricValue::Float(self.start.elapsed().as_secs_f64()),
+        );
+        self.add(
+            "success",
+            "Whether the command run was successful (1) or failed (0)",
+            MetricValue::Int(success.into()),
+        );
+        if success {
+            self.add(
+                "last_success",
+                "Timestamp of the last successful command run",
+                MetricValue::Float(timestamp_secs(Timestamp::now())),
+            );
+        }
+        let mut labels = self.labels;
+        conflate::btreemap::append_or_ignore(&mut labels, config.global.metrics_labels.clone());
+        if let Err(err) = publish_metrics(&self.metrics, &self.job_name, labels) {
+            warn!("error pushing metrics: {err}");
+        }
+    }
+}
+
+/// Push the metrics to all configured metrics backends
+#[cfg(not(any(feature = "prometheus", feature = "opentelemetry")))]
+pub fn publish_metrics(
+    _metrics: &[Metric],
+    _job_name: &str,
+    _labels: BTreeMap<String, String>,
+) -> Result<()> {
+    Err(anyhow::anyhow!("metrics support is not compiled-in!"))
+}
+
+/// Push the metrics to all configured metrics backends
+#[cfg(any(feature = "prometheus", feature = "opentelemetry"))]
+pub fn publish_metrics(
+    metrics: &[Metric],
+    job_name: &str,
+    labels: BTreeMap<String, String>,
+) -> Result<()> {
+    #[allow(unused_imports)]
+    use anyhow::{Context, bail};
+
+    let global_config = &RUSTIC_APP.config().global;
+
+    #[cfg(feature = "prometheus")]
+    if let Some(prometheus_endpoint) = &global_config.prometheus {
+        use prometheus::PrometheusExporter;
+
+        let metrics_exporter = PrometheusExporter {
+            endpoint: prometheus_endpoint.clone(),
+            job_name: job_name.to_string(),
+            grouping: labels.clone(),
+            prometheus_user: global_config.prometheus_user.clone(),
+            prometheus_pass: global_config.prometheus_pass.clone(),
+        };
+
+        metrics_exporter
+            .push_metrics(metrics)
+            .context("pushing prometheus metrics")?;
+    }
+
+    #[cfg(feature = "prometheus")]
+    if let Some(dir) = &global_config.prometheus_textfile_dir {
+        use prometheus::PrometheusTextfileExporter;
+
+        let metrics_exporter = PrometheusTextfileExporter {
+            dir: dir.clone(),
+            job_name: job_name.to_string(),
+            labels: labels.clone(),
+        };
+
+        metrics_exporter
+            .push_metrics(metrics)
+            .context("writing prometheus textfile")?;
+    }
+
+    #[cfg(not(feature = "prometheus"))]
+    if global_config.prometheus.is_some() || global_config.prometheus_textfile_dir.is_some() {
+        bail!("prometheus metrics support is not compiled-in!");
+    }
+
+    #[cfg(feature = "opentelemetry")]
+    if let Some(otlp_endpoint) = global_config.opentelemetry_metrics_endpoint() {
+        use opentelemetry::OpentelemetryExporter;
+
+        let metrics_exporter = OpentelemetryExporter {
+            endpoint: otlp_endpoint,
+            service_name: job_name.to_string(),
+            labels,
+        };
+
+        metrics_exporter
+            .push_metrics(metrics)
+            .context("pushing opentelemetry metrics")?;
+    }
+
+    #[cfg(not(feature = "opentelemetry"))]
+    if global_config.opentelemetry_metrics_endpoint().is_some() {
+        bail!("opentelemetry metrics support is not compiled-in!");
+    }
+
+    Ok(())
+}
@@ -52,7 +52,7 @@ impl MetricsExporter for OpentelemetryExporter {
             match metric.value {
                 MetricValue::Int(value) => {
                     let gauge = &meter
-                        .u64_gauge(metric.name)
+                        .u64_gauge(metric.name.clone())
                         .with_description(metric.description)
                         .build();
 
@@ -60,7 +60,7 @@ impl MetricsExporter for OpentelemetryExporter {
                 }
                 MetricValue::Float(value) => {
                     let gauge = &meter
-                        .f64_gauge(metric.name)
+                        .f64_gauge(metric.name.clone())
                         .with_description(metric.description)
                         .build();
 
@@ -1,8 +1,8 @@
 use anyhow::{Context, Result, bail};
 use log::debug;
-use prometheus::{Registry, register_gauge_with_registry};
+use prometheus::{Opts, Registry, register_gauge_with_registry};
 use reqwest::Url;
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, fs, path::PathBuf};
 
 use crate::metrics::MetricValue::*;
 
@@ -24,7 +24,7 @@ impl MetricsExporter for PrometheusExporter {
         let registry = Registry::new();
 
         for metric in metrics {
-            let gauge = register_gauge_with_registry!(metric.name, metric.description, registry)
+            let gauge = register_gauge_with_registry!(&*metric.name, metric.description, registry)
                 .context("registering prometheus gauge")?;
 
             gauge.set(match metric.value {
@@ -64,6 +64,68 @@ impl MetricsExporter for PrometheusExporter {
     }
 }
 
+/// Writes the metrics into a `.prom` file, e.g. to be read by the `node_exporter` textfile collector
+pub struct PrometheusTextfileExporter {
+    pub dir: PathBuf,
+    pub job_name: String,
+    pub labels: BTreeMap<String, String>,
+}
+
+impl MetricsExporter for PrometheusTextfileExporter {
+    fn push_metrics(&self, metrics: &[Metric]) -> Result<()> {
+        use prometheus::{Encoder, TextEncoder};
+
+        let registry = Registry::new();
+        let labels: std::collections::HashMap<_, _> = self
+            .labels
+            .iter()
+            .filter(|(_, value)| !value.is_empty())
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+
+        for metric in metrics {
+            let opts = Opts::new(&*metric.name, metric.description).const_labels(labels.clone());
+            let gauge = register_gauge_with_registry!(opts, registry)
+                .context("registering prometheus gauge")?;
+
+            gauge.set(match metric.value {
+                Int(i) => i as f64,
+                Float(f) => f,
+            });
+        }
+
+        let mut buf = Vec::new();
+        TextEncoder::new().encode(&registry.gather(), &mut buf)?;
+
+        // write to a temporary file and rename it, so that the collector never reads a partial file
+        let path = self.dir.join(self.filename());
+        let tmp_path = path.with_extension("prom.tmp");
+        debug!("writing metrics to {}", path.display());
+        fs::write(&tmp_path, buf)
+            .with_context(|| format!("writing metrics to {}", tmp_path.display()))?;
+        fs::rename(&tmp_path, &path)
+            .with_context(|| format!("renaming metrics file to {}", path.display()))?;
+        Ok(())
+    }
+}
+
+impl PrometheusTextfileExporter {
+    /// The filename is determined by the job name and the labels, like the grouping of a Pushgateway
+    fn filename(&self) -> String {
+        if self.labels.values().all(String::is_empty) {
+            return format!("{}.prom", self.job_name);
+        }
+        // use FNV-1a to get a stable hash of the labels
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for (name, value) in &self.labels {
+            for byte in name.bytes().chain([0]).chain(value.bytes()).chain([0]) {
+                hash = (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3);
+            }
+        }
+        format!("{}-{hash:016x}.prom", self.job_name)
+    }
+}
+
 impl PrometheusExporter {
     // TODO: This should be actually part of the prometheus crate, see https://github.com/tikv/rust-prometheus/issues/536
     fn make_url_and_encoded_metrics(&self, registry: &Registry) -> Result<(Url, Vec<u8>)> {
@@ -130,3 +192,34 @@ fn test_make_url_and_encoded_metrics() -> Result<()> {
     );
     Ok(())
 }
+
+#[cfg(feature = "prometheus")]
+#[test]
+fn test_textfile_exporter() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let mut exporter = PrometheusTextfileExporter {
+        dir: dir.path().to_path_buf(),
+        job_name: "test_job".to_string(),
+        labels: BTreeMap::new(),
+    };
+    let metrics = [Metric {
+        name: "rustic_test_value".into(),
+        description: "A test value",
+        value: Int(42),
+    }];
+
+    exporter.push_metrics(&metrics)?;
+    let content = fs::read_to_string(dir.path().join("test_job.prom"))?;
+    assert!(content.contains("# HELP rustic_test_value A test value"));
+    assert!(content.contains("rustic_test_value 42"));
+
+    _ = exporter
+        .labels
+        .insert("path".to_string(), "/my/path".to_string());
+    let filename = exporter.filename();
+    assert!(filename.starts_with("test_job-") && filename.ends_with(".prom"));
+    exporter.push_metrics(&metrics)?;
+    let content = fs::read_to_string(dir.path().join(filename))?;
+    assert!(content.contains(r#"rustic_test_value{path="/my/path"} 42"#));
+    Ok(())
+}
@@ -7,6 +7,7 @@
 use std::collections::HashMap;
 use std::fmt::Debug;
 use std::ops::Deref;
+use std::sync::Arc;
 
 use abscissa_core::Application;
 use anyhow::{Result, anyhow, bail};
@@ -16,12 +17,16 @@ use dialoguer::Password;
 use rustic_backend::BackendOptions;
 use rustic_core::{
     CredentialOptions, Credentials, Grouped, IndexedFullStatus, IndexedIdsStatus, Open, OpenStatus,
-    ProgressBars, Repository, RepositoryOptions, RusticResult, SnapshotGroupCriterion,
-    repofile::SnapshotFile,
+    ProgressBars, Repository, RepositoryBackends, RepositoryOptions, RusticResult,
+    SnapshotGroupCriterion, WriteBackend, repofile::SnapshotFile,
 };
 use serde::{Deserialize, Serialize};
 
-use crate::{RUSTIC_APP, config::hooks::Hooks};
+use crate::{RUSTIC_APP, config::hooks::Hooks, repository::parity::ParityOptions, telemetry};
+
+pub mod parity;
+pub mod snapshot_source;
+pub mod throttle;
 
 pub(super) mod constants {
     pub(super) const MAX_PASSWORD_RETRIES: usize = 5;
@@ -45,14 +50,30 @@ pub struct AllRepositoryOptions {
     #[serde(flatten)]
     pub credential_opts: CredentialOptions,
 
+    /// Parity options
+    #[clap(flatten, next_help_heading = "parity options")]
+    #[serde(flatten)]
+    pub parity: ParityOptions,
+
     /// Hooks
     #[clap(skip)]
     pub hooks: Hooks,
 }
 
 impl AllRepositoryOptions {
+    /// The backends of the repository, including the generation of parity files and rate limits if configured
+    pub fn backends(&self) -> Result<RepositoryBackends> {
+        let backends = self.parity.wrap(&self.be, self.be.to_backends()?)?;
+        Ok(RUSTIC_APP.config().global.throttle_options.wrap(backends))
+    }
+
+    /// The backend parity files are saved in, if configured
+    pub fn parity_backend(&self) -> Result<Option<Arc<dyn WriteBackend>>> {
+        self.parity.backend(&self.be)
+    }
+
     pub fn repository(&self, po: impl ProgressBars) -> Result<Repo> {
-        let backends = self.be.to_backends()?;
+        let backends = self.backends()?;
         let repo = Repository::new_with_progress(&self.repo, &backends, po)?;
         Ok(Repo(repo))
     }
@@ -78,7 +99,20 @@ impl AllRepositoryOptions {
     }
 
     pub fn run_open<T>(&self, f: impl FnOnce(OpenRepo) -> Result<T>) -> Result<T> {
-        self.run(|repo| f(repo.open(&self.credential_opts)?))
+        self.run(|repo| {
+            let repo = repo.open(&self.credential_opts)?;
+            if RUSTIC_APP.config().snapshot_filter.has_content_filter() {
+                // content-aware filters need to read trees
+                let repo = repo.to_indexed_ids()?;
+                RUSTIC_APP
+                    .config()
+                    .snapshot_filter
+                    .evaluate_content(&repo)?;
+                f(repo.drop_index())
+            } else {
+                f(repo)
+            }
+        })
     }
 
     pub fn run_open_or_init_with<T: Clone>(
@@ -145,7 +179,9 @@ impl Repo {
         }
     }
     pub fn open(self, credential_opts: &CredentialOptions) -> Result<OpenRepo> {
-        self.open_with(credential_opts, |repo, credentials| repo.open(credentials))
+        telemetry::in_span("open repository", || {
+            self.open_with(credential_opts, |repo, credentials| repo.open(credentials))
+        })
     }
 
     fn open_or_init_repository_with(
@@ -178,6 +214,10 @@ impl Repo {
         } else {
             open.to_indexed()
         }?;
+        RUSTIC_APP
+            .config()
+            .snapshot_filter
+            .evaluate_content(&repo)?;
         Ok(repo)
     }
 }
@@ -0,0 +1,672 @@
+//! Forward error correction for pack files
+//!
+//! When a parity repository is configured, a Reed-Solomon parity file is saved for each pack
+//! written to the repository. Each pack is split into a number of data shards; the parity file
+//! contains CRC32 checksums of all shards and the parity shards. Up to `parity-shards` damaged
+//! data shards can be reconstructed from it.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Debug,
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use anyhow::{Result, bail};
+use bytes::Bytes;
+use clap::Parser;
+use conflate::Merge;
+use log::{error, warn};
+use reed_solomon_erasure::galois_8::ReedSolomon;
+use rustic_backend::BackendOptions;
+use rustic_core::{
+    BytesList, FileType, Id, ReadBackend, RepositoryBackends, RusticResult, WriteBackend,
+};
+use serde::{Deserialize, Serialize};
+
+/// Default number of data shards a pack is split into
+const DEFAULT_DATA_SHARDS: usize = 16;
+/// Default number of parity shards
+const DEFAULT_PARITY_SHARDS: usize = 2;
+/// Version of the parity file format
+const PARITY_VERSION: u32 = 1;
+
+#[derive(Clone, Default, Debug, Parser, Serialize, Deserialize, Merge)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct ParityOptions {
+    /// Repository to save Reed-Solomon parity files of packs to. If set, parity files are written
+    /// for all newly saved packs and can be used by `repair packs` to reconstruct damaged packs
+    #[clap(
+        long,
+        global = true,
+        env = "RUSTIC_PARITY_REPOSITORY",
+        value_name = "PARITY_REPOSITORY"
+    )]
+    #[merge(strategy=conflate::option::overwrite_none)]
+    pub parity_repository: Option<String>,
+
+    /// Number of data shards each pack is split into [default: 16]
+    #[clap(long, global = true, value_name = "N")]
+    #[merge(strategy=conflate::option::overwrite_none)]
+    pub parity_data_shards: Option<usize>,
+
+    /// Number of parity shards, i.e. the number of damaged data shards which can be reconstructed [default: 2]
+    #[clap(long, global = true, value_name = "N")]
+    #[merge(strategy=conflate::option::overwrite_none)]
+    pub parity_shards: Option<usize>,
+
+    /// Verify packs against their parity files while they are read
+    #[clap(skip)]
+    #[serde(skip)]
+    #[merge(skip)]
+    pub check: Option<Arc<ParityCheck>>,
+}
+
+impl ParityOptions {
+    /// The backend parity files are saved in, if configured
+    ///
+    /// The parity repository uses the options given for the (cold) repository backend.
+    pub fn backend(&self, be: &BackendOptions) -> Result<Option<Arc<dyn WriteBackend>>> {
+        let Some(repository) = &self.parity_repository else {
+            return Ok(None);
+        };
+        let mut opts = be.clone();
+        opts.repository = Some(repository.clone());
+        opts.repo_hot = None;
+        Ok(Some(opts.to_backends()?.repository()))
+    }
+
+    /// Wrap the repository backend such that parity files are written for all saved packs
+    ///
+    /// If `check` is set, packs which are read completely are verified against their parity files.
+    pub fn wrap(
+        &self,
+        be: &BackendOptions,
+        backends: RepositoryBackends,
+    ) -> Result<RepositoryBackends> {
+        let Some(parity_be) = self.backend(be)? else {
+            return Ok(backends);
+        };
+        let data_shards = self.parity_data_shards.unwrap_or(DEFAULT_DATA_SHARDS);
+        let parity_shards = self.parity_shards.unwrap_or(DEFAULT_PARITY_SHARDS);
+        if data_shards == 0 || parity_shards == 0 || data_shards + parity_shards > 256 {
+            bail!(
+                "invalid number of parity shards: need at least one data and parity shard and at most 256 shards in total."
+            );
+        }
+        let be = ParityBackend {
+            be: backends.repository(),
+            parity_be,
+            rs: ReedSolomon::new(data_shards, parity_shards)?,
+            check: self.check.clone(),
+        };
+        Ok(RepositoryBackends::new(Arc::new(be), backends.repo_hot()))
+    }
+}
+
+/// Backend which additionally saves a parity file for each pack written
+#[derive(Debug)]
+struct ParityBackend {
+    be: Arc<dyn WriteBackend>,
+    parity_be: Arc<dyn WriteBackend>,
+    rs: ReedSolomon,
+    check: Option<Arc<ParityCheck>>,
+}
+
+impl ReadBackend for ParityBackend {
+    fn location(&self) -> String {
+        self.be.location()
+    }
+
+    fn list_with_size(&self, tpe: FileType) -> RusticResult<Vec<(Id, u32)>> {
+        self.be.list_with_size(tpe)
+    }
+
+    fn list(&self, tpe: FileType) -> RusticResult<Vec<Id>> {
+        self.be.list(tpe)
+    }
+
+    fn read_full(&self, tpe: FileType, id: &Id) -> RusticResult<Bytes> {
+        let data = self.be.read_full(tpe, id)?;
+        if let Some(check) = &self.check
+            && tpe == FileType::Pack
+        {
+            check.read_full(self.parity_be.as_ref(), id, &data);
+        }
+        Ok(data)
+    }
+
+    fn read_partial(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        offset: u32,
+        length: u32,
+    ) -> RusticResult<Bytes> {
+        let data = self.be.read_partial(tpe, id, cacheable, offset, length)?;
+        if let Some(check) = &self.check
+            && tpe == FileType::Pack
+        {
+            check.read(self.parity_be.as_ref(), id, offset, &data);
+        }
+        Ok(data)
+    }
+
+    fn warmup_path(&self, tpe: FileType, id: &Id) -> String {
+        self.be.warmup_path(tpe, id)
+    }
+
+    fn needs_warm_up(&self) -> bool {
+        self.be.needs_warm_up()
+    }
+
+    fn warm_up(&self, tpe: FileType, id: &Id) -> RusticResult<()> {
+        self.be.warm_up(tpe, id)
+    }
+}
+
+impl WriteBackend for ParityBackend {
+    fn create(&self) -> RusticResult<()> {
+        self.be.create()?;
+        self.parity_be.create()
+    }
+
+    fn write_bytes(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        content: BytesList,
+    ) -> RusticResult<()> {
+        let parity = (tpe == FileType::Pack).then(|| {
+            let data: Vec<u8> = content.slice().concat();
+            ParityFile::new(&data, &self.rs).to_bytes()
+        });
+        self.be.write_bytes(tpe, id, cacheable, content)?;
+        if let Some(parity) = parity {
+            self.parity_be
+                .write_bytes(FileType::Pack, id, false, parity.into())?;
+        }
+        Ok(())
+    }
+
+    fn remove(&self, tpe: FileType, id: &Id, cacheable: bool) -> RusticResult<()> {
+        self.be.remove(tpe, id, cacheable)?;
+        if tpe == FileType::Pack
+            && let Err(err) = self.parity_be.remove(FileType::Pack, id, false)
+        {
+            log::warn!("error removing parity file for pack {id}: {err}");
+        }
+        Ok(())
+    }
+}
+
+/// Verification of packs against their parity files while the packs are read, e.g. by `check --read-data`
+///
+/// Packs read in parts must be read sequentially; packs which are not read completely are not verified.
+/// Packs without parity file are skipped.
+#[derive(Debug, Default)]
+pub struct ParityCheck {
+    /// Packs which have a parity file
+    with_parity: OnceLock<BTreeSet<Id>>,
+    /// Packs currently being read
+    reading: Mutex<BTreeMap<Id, ShardChecker>>,
+    /// Packs which have been verified and found damaged
+    damaged: Mutex<Vec<(Id, ParityStatus)>>,
+}
+
+impl ParityCheck {
+    /// The parity file of the pack, if there is one
+    fn parity_file(&self, parity_be: &dyn WriteBackend, id: &Id) -> Option<ParityFile> {
+        let with_parity = self.with_parity.get_or_init(|| {
+            parity_be
+                .list(FileType::Pack)
+                .unwrap_or_else(|err| {
+                    warn!("cannot list parity files: {err}");
+                    Vec::new()
+                })
+                .into_iter()
+                .collect()
+        });
+        if !with_parity.contains(id) {
+            return None;
+        }
+        parity_be
+            .read_full(FileType::Pack, id)
+            .map_err(anyhow::Error::from)
+            .and_then(|bytes| ParityFile::from_bytes(&bytes))
+            .inspect_err(|err| warn!("pack {id}: cannot read parity file: {err}"))
+            .ok()
+    }
+
+    fn add_status(&self, id: &Id, status: ParityStatus) {
+        if status != ParityStatus::Ok {
+            self.damaged.lock().unwrap().push((*id, status));
+        }
+    }
+
+    /// Process the complete data of a pack
+    fn read_full(&self, parity_be: &dyn WriteBackend, id: &Id, data: &[u8]) {
+        if let Some(parity) = self.parity_file(parity_be, id) {
+            self.add_status(id, parity.verify(data));
+        }
+    }
+
+    /// Process the data of a pack read at `offset`
+    fn read(&self, parity_be: &dyn WriteBackend, id: &Id, offset: u32, data: &[u8]) {
+        let checker = if offset == 0 {
+            // start of the pack; a pack which is read again is verified again
+            self.parity_file(parity_be, id)
+                .map(|parity| ShardChecker::new(&parity))
+        } else {
+            self.reading.lock().unwrap().remove(id)
+        };
+
+        let Some(mut checker) = checker else {
+            return;
+        };
+        if checker.offset != u64::from(offset) {
+            // not read sequentially, so the pack cannot be verified
+            return;
+        }
+        checker.update(data);
+        if let Some(status) = checker.status() {
+            self.add_status(id, status);
+        } else {
+            _ = self.reading.lock().unwrap().insert(*id, checker);
+        }
+    }
+
+    /// Report all packs found damaged
+    ///
+    /// Returns the number of damaged packs.
+    pub fn report(&self) -> u64 {
+        let damaged = self.damaged.lock().unwrap();
+        for (id, status) in damaged.iter() {
+            match status {
+                ParityStatus::Ok => {}
+                ParityStatus::Repairable(shards) => error!(
+                    "pack {id}: shards {shards:?} are damaged, but can be reconstructed using `repair packs`."
+                ),
+                ParityStatus::Unrepairable(shards) => {
+                    error!("pack {id}: shards {shards:?} are damaged and cannot be reconstructed.");
+                }
+            }
+        }
+        damaged.len() as u64
+    }
+}
+
+/// Checksums of the data shards of a pack which is read sequentially
+#[derive(Debug)]
+struct ShardChecker {
+    header: ParityHeader,
+    valid_parity: usize,
+    /// Number of bytes of the pack processed
+    offset: u64,
+    crc: flate2::Crc,
+    damaged: Vec<usize>,
+}
+
+impl ShardChecker {
+    fn new(parity: &ParityFile) -> Self {
+        Self {
+            header: parity.header.clone(),
+            valid_parity: parity.valid_parity().len(),
+            offset: 0,
+            crc: flate2::Crc::new(),
+            damaged: Vec::new(),
+        }
+    }
+
+    /// Index of the shard containing the current offset and the position within this shard
+    fn position(&self) -> (usize, usize) {
+        let shard_size = self.header.shard_size as u64;
+        #[allow(clippy::cast_possible_truncation)]
+        let pos = (
+            (self.offset / shard_size) as usize,
+            (self.offset % shard_size) as usize,
+        );
+        pos
+    }
+
+    /// Add data to the current shard; completed shards are compared with their checksum
+    fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            let (shard, pos) = self.position();
+            let len = data.len().min(self.header.shard_size - pos);
+            self.crc.update(&data[..len]);
+            self.offset += len as u64;
+            data = &data[len..];
+            if pos + len == self.header.shard_size {
+                self.finish_shard(shard);
+            }
+        }
+    }
+
+    fn finish_shard(&mut self, shard: usize) {
+        if shard >= self.header.data_shards || self.crc.sum() != self.header.crcs[shard] {
+            self.damaged.push(shard.min(self.header.data_shards - 1));
+        }
+        self.crc.reset();
+    }
+
+    /// The result of the verification, once the complete pack has been read
+    fn status(&mut self) -> Option<ParityStatus> {
+        if self.offset < self.header.pack_size {
+            return None;
+        }
+        // the remaining shards are padded with zeros
+        let (mut shard, pos) = self.position();
+        if pos > 0 {
+            self.crc.update(&vec![0; self.header.shard_size - pos]);
+            self.finish_shard(shard);
+            shard += 1;
+        }
+        for shard in shard..self.header.data_shards {
+            self.crc.update(&vec![0; self.header.shard_size]);
+            self.finish_shard(shard);
+        }
+        self.damaged.dedup();
+        let damaged = std::mem::take(&mut self.damaged);
+        Some(if damaged.is_empty() {
+            ParityStatus::Ok
+        } else if damaged.len() <= self.valid_parity {
+            ParityStatus::Repairable(damaged)
+        } else {
+            ParityStatus::Unrepairable(damaged)
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct ParityHeader {
+    version: u32,
+    pack_size: u64,
+    shard_size: usize,
+    data_shards: usize,
+    parity_shards: usize,
+    /// CRC32 checksums of all data shards followed by all parity shards
+    crcs: Vec<u32>,
+}
+
+/// A parity file: a JSON header line followed by the parity shards
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParityFile {
+    header: ParityHeader,
+    parity: Vec<Vec<u8>>,
+}
+
+/// The result of verifying a pack against its parity file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParityStatus {
+    /// The pack matches the parity file
+    Ok,
+    /// The given data shards are damaged, but can be reconstructed
+    Repairable(Vec<usize>),
+    /// Too many data shards are damaged
+    Unrepairable(Vec<usize>),
+}
+
+impl ParityFile {
+    /// Compute the parity for `data`
+    pub fn new(data: &[u8], rs: &ReedSolomon) -> Self {
+        let (data_shards, parity_shards) = (rs.data_shard_count(), rs.parity_shard_count());
+        let shard_size = data.len().div_ceil(data_shards).max(1);
+        let shards = split_shards(data, data_shards, shard_size);
+        let mut parity = vec![vec![0; shard_size]; parity_shards];
+        rs.encode_sep(&shards, &mut parity)
+            .expect("shards have the same size");
+        let crcs = shards.iter().chain(&parity).map(|s| crc32(s)).collect();
+        Self {
+            header: ParityHeader {
+                version: PARITY_VERSION,
+                pack_size: data.len() as u64,
+                shard_size,
+                data_shards,
+                parity_shards,
+                crcs,
+            },
+            parity,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = serde_json::to_vec(&self.header).expect("serializing header failed");
+        bytes.push(b'\n');
+        for shard in &self.parity {
+            bytes.extend_from_slice(shard);
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let Some(pos) = bytes.iter().position(|b| *b == b'\n') else {
+            bail!("invalid parity file: no header found");
+        };
+        let header: ParityHeader = serde_json::from_slice(&bytes[..pos])?;
+        if header.version != PARITY_VERSION {
+            bail!("unsupported parity file version {}", header.version);
+        }
+        let data = &bytes[pos + 1..];
+        if header.data_shards == 0
+            || header.data_shards + header.parity_shards > 256
+            || header.crcs.len() != header.data_shards + header.parity_shards
+            || data.len() != header.parity_shards * header.shard_size
+        {
+            bail!("invalid parity file: sizes do not match");
+        }
+        let parity = data.chunks(header.shard_size).map(<[u8]>::to_vec).collect();
+        Ok(Self { header, parity })
+    }
+
+    fn data_shards(&self, data: &[u8]) -> Vec<Vec<u8>> {
+        split_shards(data, self.header.data_shards, self.header.shard_size)
+    }
+
+    /// Indices of data shards which don't match their checksum
+    fn damaged_shards(&self, data: &[u8]) -> Vec<usize> {
+        let mut damaged: Vec<_> = self
+            .data_shards(data)
+            .iter()
+            .enumerate()
+            .filter(|(i, shard)| crc32(shard) != self.header.crcs[*i])
+            .map(|(i, _)| i)
+            .collect();
+        let pack_size = usize::try_from(self.header.pack_size).unwrap_or(usize::MAX);
+        if data.len() < pack_size {
+            // a truncated pack damages all shards after its end
+            let first = data.len() / self.header.shard_size;
+            damaged.retain(|i| *i < first);
+            damaged.extend(first..self.header.data_shards);
+        } else if data.len() > pack_size && damaged.is_empty() {
+            // additional data at the end of the pack; report the last shard as damaged
+            damaged.push(
+                (pack_size.saturating_sub(1) / self.header.shard_size)
+                    .min(self.header.data_shards - 1),
+            );
+        }
+        damaged
+    }
+
+    /// Indices of valid parity shards
+    fn valid_parity(&self) -> Vec<usize> {
+        self.parity
+            .iter()
+            .enumerate()
+            .filter(|(i, shard)| crc32(shard) == self.header.crcs[self.header.data_shards + i])
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Verify the pack `data` against this parity file
+    pub fn verify(&self, data: &[u8]) -> ParityStatus {
+        let damaged = self.damaged_shards(data);
+        if damaged.is_empty() {
+            ParityStatus::Ok
+        } else if damaged.len() <= self.valid_parity().len() {
+            ParityStatus::Repairable(damaged)
+        } else {
+            ParityStatus::Unrepairable(damaged)
+        }
+    }
+
+    /// Reconstruct the original pack from the (possibly damaged) `data`
+    pub fn reconstruct(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let damaged = self.damaged_shards(data);
+        let k = self.header.data_shards;
+        let mut shards: Vec<Option<Vec<u8>>> = self
+            .data_shards(data)
+            .into_iter()
+            .enumerate()
+            .map(|(i, shard)| (!damaged.contains(&i)).then_some(shard))
+            .collect();
+        let valid = self.valid_parity();
+        shards.extend(
+            (0..self.header.parity_shards)
+                .map(|i| valid.contains(&i).then(|| self.parity[i].clone())),
+        );
+        if damaged.len() > valid.len() {
+            bail!(
+                "{} data shards are damaged, but only {} parity shards are available",
+                damaged.len(),
+                valid.len()
+            );
+        }
+        ReedSolomon::new(k, self.header.parity_shards)?.reconstruct_data(&mut shards)?;
+        let mut result: Vec<u8> = shards.into_iter().take(k).flatten().flatten().collect();
+        result.truncate(self.header.pack_size.try_into()?);
+        Ok(result)
+    }
+}
+
+/// Reconstruct a damaged pack using its parity file
+///
+/// The reconstructed pack is only returned if it matches the pack id.
+pub fn reconstruct_pack(
+    be: &dyn WriteBackend,
+    parity_be: &dyn WriteBackend,
+    id: &Id,
+) -> Result<Vec<u8>> {
+    let parity = ParityFile::from_bytes(&parity_be.read_full(FileType::Pack, id)?)?;
+    // a missing pack is treated as empty, i.e. completely damaged
+    let data = be.read_full(FileType::Pack, id).unwrap_or_default();
+    let pack = parity.reconstruct(&data)?;
+    if !id.blob_matches_reader(pack.len() as u64, &mut pack.as_slice()) {
+        bail!("reconstructed pack {id} does not match its id");
+    }
+    Ok(pack)
+}
+
+fn split_shards(data: &[u8], data_shards: usize, shard_size: usize) -> Vec<Vec<u8>> {
+    (0..data_shards)
+        .map(|i| {
+            let start = (i * shard_size).min(data.len());
+            let end = ((i + 1) * shard_size).min(data.len());
+            let mut shard = data[start..end].to_vec();
+            shard.resize(shard_size, 0);
+            shard
+        })
+        .collect()
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = flate2::Crc::new();
+    crc.update(data);
+    crc.sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    fn data(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 % 251) as u8).collect()
+    }
+
+    fn parity_file(data: &[u8], data_shards: usize, parity_shards: usize) -> ParityFile {
+        ParityFile::new(data, &ReedSolomon::new(data_shards, parity_shards).unwrap())
+    }
+
+    #[test]
+    fn parity_file_roundtrip() -> Result<()> {
+        let parity = parity_file(&data(1000), 4, 2);
+        assert_eq!(ParityFile::from_bytes(&parity.to_bytes())?, parity);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(1000, 4, 2, &[1])]
+    #[case(1000, 4, 2, &[0, 3])]
+    #[case(1001, 16, 3, &[2, 7, 15])]
+    #[case(10, 16, 2, &[0])]
+    fn damaged_packs_are_reconstructed(
+        #[case] len: usize,
+        #[case] data_shards: usize,
+        #[case] parity_shards: usize,
+        #[case] damaged: &[usize],
+    ) -> Result<()> {
+        let original = data(len);
+        let parity = parity_file(&original, data_shards, parity_shards);
+        assert_eq!(parity.verify(&original), ParityStatus::Ok);
+
+        let shard_size = parity.header.shard_size;
+        let mut broken = original.clone();
+        for shard in damaged {
+            if let Some(b) = broken.get_mut(shard * shard_size) {
+                *b ^= 0xff;
+            }
+        }
+        assert_eq!(
+            parity.verify(&broken),
+            ParityStatus::Repairable(damaged.to_vec())
+        );
+        assert_eq!(parity.reconstruct(&broken)?, original);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(&[])]
+    #[case(&[1])]
+    #[case(&[0, 3])]
+    fn sequential_reads_are_verified(#[case] damaged: &[usize]) {
+        let original = data(1000);
+        let parity = parity_file(&original, 4, 2);
+        let shard_size = parity.header.shard_size;
+        let mut broken = original;
+        for shard in damaged {
+            broken[shard * shard_size] ^= 0xff;
+        }
+
+        let mut checker = ShardChecker::new(&parity);
+        for chunk in broken.chunks(97) {
+            assert_eq!(checker.status(), None);
+            checker.update(chunk);
+        }
+        assert_eq!(checker.status(), Some(parity.verify(&broken)));
+    }
+
+    #[test]
+    fn truncated_pack_is_reconstructed() -> Result<()> {
+        let original = data(1000);
+        let parity = parity_file(&original, 4, 2);
+        assert_eq!(parity.reconstruct(&original[..900])?, original);
+        Ok(())
+    }
+
+    #[test]
+    fn too_many_damaged_shards_are_detected() {
+        let original = data(1000);
+        let parity = parity_file(&original, 4, 1);
+        let mut broken = original;
+        broken[0] ^= 1;
+        broken[999] ^= 1;
+        assert_eq!(
+            parity.verify(&broken),
+            ParityStatus::Unrepairable(vec![0, 3])
+        );
+        assert!(parity.reconstruct(&broken).is_err());
+    }
+}
@@ -0,0 +1,279 @@
+//! Use snapshot contents as source for the archiver
+//!
+//! This allows to save the contents of several snapshots within a single archiver run, e.g. to
+//! re-chunk them. The contents of each snapshot are placed in their own dir; file contents are
+//! read from the repository. [`NodeSource`] instead yields given entries, e.g. to save them at a
+//! different location, and [`SnapshotPathSource`] yields a single path of a snapshot.
+
+use std::{
+    io::{self, Read},
+    path::PathBuf,
+    sync::Arc,
+    vec,
+};
+
+use rustic_core::{
+    LsOptions, ReadSource, ReadSourceEntry, ReadSourceOpen, RusticError, RusticResult, TreeId,
+    repofile::{Node, NodeType, SnapshotFile},
+    vfs::OpenFile,
+};
+
+use crate::repository::{IndexedIdsRepo, IndexedRepo};
+
+/// [`ReadSource`] which yields the contents of the given snapshots and reads file contents from the
+/// repository
+pub struct SnapshotSource {
+    repo: Arc<IndexedRepo>,
+    snapshots: Vec<(PathBuf, SnapshotFile)>,
+}
+
+impl SnapshotSource {
+    /// Create a new source from snapshots of `repo`.
+    ///
+    /// The contents of each snapshot are placed in the given dir; the dirs must be sorted.
+    pub fn new(repo: Arc<IndexedRepo>, snapshots: Vec<(PathBuf, SnapshotFile)>) -> Self {
+        Self { repo, snapshots }
+    }
+}
+
+impl ReadSource for SnapshotSource {
+    type Open = SnapshotFileOpen;
+    type Iter = Box<dyn Iterator<Item = RusticResult<ReadSourceEntry<SnapshotFileOpen>>> + Send>;
+
+    fn size(&self) -> RusticResult<Option<u64>> {
+        Ok(self
+            .snapshots
+            .iter()
+            .map(|(_, sn)| sn.summary.as_ref().map(|s| s.total_bytes_processed))
+            .sum())
+    }
+
+    fn entries(&self) -> Self::Iter {
+        let repo = self.repo.clone();
+        // only the entries of one snapshot are kept in memory at a time
+        let entries = self
+            .snapshots
+            .clone()
+            .into_iter()
+            .flat_map(move |(dir, sn)| {
+                let entries = (|| -> RusticResult<_> {
+                    let root = repo.node_from_snapshot_and_path(&sn, "")?;
+                    let dir_node =
+                        Node::new_node(dir.as_os_str(), NodeType::Dir, root.meta.clone());
+                    let entries = repo
+                        .ls(&root, &LsOptions::default().recursive(true))?
+                        .map(|item| item.map(|(path, node)| (dir.join(path), node)));
+                    Ok(std::iter::once(Ok((dir.clone(), dir_node)))
+                        .chain(entries)
+                        .collect::<Vec<_>>())
+                })()
+                .unwrap_or_else(|err| vec![Err(err)]);
+
+                let repo = repo.clone();
+                entries.into_iter().map(move |item| {
+                    item.map(|(path, node)| ReadSourceEntry {
+                        path,
+                        open: node.is_file().then(|| SnapshotFileOpen {
+                            repo: repo.clone(),
+                            node: node.clone(),
+                        }),
+                        node,
+                    })
+                })
+            });
+        Box::new(entries)
+    }
+}
+
+/// [`ReadSource`] which yields the given entries and reads file contents from the repository
+pub struct NodeSource {
+    repo: Arc<IndexedRepo>,
+    entries: Arc<[(PathBuf, Node)]>,
+}
+
+impl NodeSource {
+    /// Create a new source from entries of `repo`; the entries must be sorted by their path.
+    pub fn new(repo: Arc<IndexedRepo>, entries: Vec<(PathBuf, Node)>) -> Self {
+        Self {
+            repo,
+            entries: entries.into(),
+        }
+    }
+}
+
+impl ReadSource for NodeSource {
+    type Open = SnapshotFileOpen;
+    type Iter = Box<dyn Iterator<Item = RusticResult<ReadSourceEntry<SnapshotFileOpen>>> + Send>;
+
+    fn size(&self) -> RusticResult<Option<u64>> {
+        Ok(Some(
+            self.entries
+                .iter()
+                .filter(|(_, node)| node.is_file())
+                .map(|(_, node)| node.meta.size)
+                .sum(),
+        ))
+    }
+
+    fn entries(&self) -> Self::Iter {
+        let repo = self.repo.clone();
+        let entries = self.entries.clone();
+        Box::new((0..entries.len()).map(move |idx| {
+            let (path, node) = entries[idx].clone();
+            Ok(ReadSourceEntry {
+                path,
+                open: node.is_file().then(|| SnapshotFileOpen {
+                    repo: repo.clone(),
+                    node: node.clone(),
+                }),
+                node,
+            })
+        }))
+    }
+}
+
+/// [`ReadSource`] which yields a path of a snapshot together with its parent dirs
+///
+/// File contents are not read, so the snapshot itself has to be used as parent to save them.
+/// Trees are read while iterating, so the entries are not kept in memory.
+pub struct SnapshotPathSource {
+    repo: Arc<IndexedIdsRepo>,
+    tree: TreeId,
+    path: PathBuf,
+}
+
+impl SnapshotPathSource {
+    /// Create a new source for `path` within the snapshot tree `tree` of `repo`
+    pub fn new(repo: Arc<IndexedIdsRepo>, tree: TreeId, path: PathBuf) -> Self {
+        Self { repo, tree, path }
+    }
+}
+
+impl ReadSource for SnapshotPathSource {
+    type Open = NoOpen;
+    type Iter = Box<dyn Iterator<Item = RusticResult<ReadSourceEntry<NoOpen>>> + Send>;
+
+    fn size(&self) -> RusticResult<Option<u64>> {
+        Ok(None)
+    }
+
+    fn entries(&self) -> Self::Iter {
+        let mut path = PathBuf::new();
+        let mut parents = Vec::new();
+        let mut last = None;
+        for comp in self.path.components() {
+            path.push(comp);
+            match self.repo.node_from_path(self.tree, &path) {
+                Ok(node) => {
+                    last = Some(node.clone());
+                    parents.push(Ok((path.clone(), node)));
+                }
+                Err(err) => {
+                    parents.push(Err(err));
+                    last = None;
+                    break;
+                }
+            }
+        }
+        let walk = TreeWalk {
+            repo: self.repo.clone(),
+            stack: Vec::new(),
+            next_tree: last
+                .filter(Node::is_dir)
+                .and_then(|node| node.subtree)
+                .map(|subtree| (path, subtree)),
+        };
+        Box::new(parents.into_iter().chain(walk).map(|item| {
+            item.map(|(path, node)| ReadSourceEntry {
+                path,
+                node,
+                open: None,
+            })
+        }))
+    }
+}
+
+/// Iterator over all entries below a tree; trees are only read when their entries are needed
+struct TreeWalk {
+    repo: Arc<IndexedIdsRepo>,
+    /// The remaining nodes of all dirs which are currently visited
+    stack: Vec<(PathBuf, vec::IntoIter<Node>)>,
+    /// The tree to visit next
+    next_tree: Option<(PathBuf, TreeId)>,
+}
+
+impl Iterator for TreeWalk {
+    type Item = RusticResult<(PathBuf, Node)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some((path, id)) = self.next_tree.take() {
+            match self.repo.get_tree(&id) {
+                Ok(tree) => self.stack.push((path, tree.nodes.into_iter())),
+                Err(err) => return Some(Err(err)),
+            }
+        }
+        loop {
+            let (path, nodes) = self.stack.last_mut()?;
+            let Some(node) = nodes.next() else {
+                _ = self.stack.pop();
+                continue;
+            };
+            let path = path.join(node.name());
+            if node.is_dir()
+                && let Some(subtree) = node.subtree
+            {
+                self.next_tree = Some((path.clone(), subtree));
+            }
+            return Some(Ok((path, node)));
+        }
+    }
+}
+
+/// Placeholder for sources which never open files
+pub enum NoOpen {}
+
+impl ReadSourceOpen for NoOpen {
+    type Reader = io::Empty;
+
+    fn open(self) -> RusticResult<Self::Reader> {
+        match self {}
+    }
+}
+
+/// Opens a file of a snapshot; only needed if the file cannot be taken from a parent snapshot
+pub struct SnapshotFileOpen {
+    repo: Arc<IndexedRepo>,
+    node: Node,
+}
+
+impl ReadSourceOpen for SnapshotFileOpen {
+    type Reader = SnapshotFileReader;
+
+    fn open(self) -> RusticResult<Self::Reader> {
+        let file = self.repo.open_file(&self.node)?;
+        Ok(SnapshotFileReader {
+            repo: self.repo,
+            file,
+            offset: 0,
+        })
+    }
+}
+
+/// Reader for the contents of a snapshot file
+pub struct SnapshotFileReader {
+    repo: Arc<IndexedRepo>,
+    file: OpenFile,
+    offset: usize,
+}
+
+impl Read for SnapshotFileReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let data = self
+            .file
+            .read_at(&self.repo, self.offset, buf.len())
+            .map_err(|err: Box<RusticError>| io::Error::other(err))?;
+        buf[..data.len()].copy_from_slice(&data);
+        self.offset += data.len();
+        Ok(data.len())
+    }
+}
@@ -0,0 +1,489 @@
+//! Limit the rate of uploads to and downloads from the repository and of reading local files
+//!
+//! Limits can be set globally and overwritten for time ranges of the day, e.g. to only limit the
+//! upload rate during office hours.
+
+use std::{
+    io::Read,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use bytesize::ByteSize;
+use clap::Parser;
+use conflate::Merge;
+use jiff::{Zoned, civil::Time};
+use rustic_core::{
+    BytesList, FileType, Id, ReadBackend, ReadSource, ReadSourceEntry, ReadSourceOpen,
+    RepositoryBackends, RusticResult, WriteBackend,
+};
+use serde::{Deserialize, Serialize};
+use serde_with::{DisplayFromStr, serde_as};
+
+/// Interval in which the limit is checked against the schedules
+const LIMIT_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Size of the chunks in which transfers to and from the repository are accounted
+const CHUNK_SIZE: u64 = 256 * 1024;
+
+#[serde_as]
+#[derive(Clone, Default, Debug, Parser, Serialize, Deserialize, Merge)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct ThrottleOptions {
+    /// Limit the rate of uploads to the repository (per second), e.g. "10MiB"
+    ///
+    /// Files are uploaded as a whole: An upload waits for its first 256 KiB to be allowed, is then
+    /// sent at full speed and afterwards waits for its remaining size.
+    #[clap(long, global = true, env = "RUSTIC_LIMIT_UPLOAD", value_name = "RATE")]
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[merge(strategy=conflate::option::overwrite_none)]
+    pub limit_upload: Option<ByteSize>,
+
+    /// Limit the rate of downloads from the repository (per second), e.g. "10MiB"
+    ///
+    /// Whole files (e.g. snapshot and index files) are downloaded at full speed and the download
+    /// waits afterwards, as their size is only known after reading. Parts of pack files wait for
+    /// their first 256 KiB, are then downloaded at full speed and wait for their remaining size.
+    #[clap(
+        long,
+        global = true,
+        env = "RUSTIC_LIMIT_DOWNLOAD",
+        value_name = "RATE"
+    )]
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[merge(strategy=conflate::option::overwrite_none)]
+    pub limit_download: Option<ByteSize>,
+
+    /// Limit the rate of reading local files when backing up (per second), e.g. "50MiB"
+    #[clap(long, global = true, env = "RUSTIC_LIMIT_READ", value_name = "RATE")]
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[merge(strategy=conflate::option::overwrite_none)]
+    pub limit_read: Option<ByteSize>,
+
+    /// Limits to use within given time ranges of the day (only in config file)
+    #[clap(skip)]
+    #[merge(strategy=conflate::vec::overwrite_empty)]
+    pub limit_schedules: Vec<LimitSchedule>,
+}
+
+/// Limits which are used within a time range of the day
+///
+/// Limits which are not given are taken from the global limits; a limit of 0 means no limit.
+#[serde_as]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct LimitSchedule {
+    /// Start of the time range, e.g. "08:00"
+    #[serde_as(as = "DisplayFromStr")]
+    from: Time,
+
+    /// End of the time range, e.g. "18:00". If it is before the start, the range spans midnight.
+    #[serde_as(as = "DisplayFromStr")]
+    to: Time,
+
+    /// Limit the rate of uploads to the repository
+    #[serde(default)]
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    limit_upload: Option<ByteSize>,
+
+    /// Limit the rate of downloads from the repository
+    #[serde(default)]
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    limit_download: Option<ByteSize>,
+
+    /// Limit the rate of reading local files
+    #[serde(default)]
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    limit_read: Option<ByteSize>,
+}
+
+impl LimitSchedule {
+    fn contains(&self, time: Time) -> bool {
+        if self.from <= self.to {
+            self.from <= time && time < self.to
+        } else {
+            self.from <= time || time < self.to
+        }
+    }
+
+    fn get(&self, direction: Direction) -> Option<ByteSize> {
+        direction.select(self.limit_upload, self.limit_download, self.limit_read)
+    }
+}
+
+/// What is limited
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Upload,
+    Download,
+    Read,
+}
+
+impl Direction {
+    fn select<T>(self, upload: T, download: T, read: T) -> T {
+        match self {
+            Self::Upload => upload,
+            Self::Download => download,
+            Self::Read => read,
+        }
+    }
+}
+
+impl ThrottleOptions {
+    fn get(&self, direction: Direction) -> Option<ByteSize> {
+        direction.select(self.limit_upload, self.limit_download, self.limit_read)
+    }
+
+    /// The limit in bytes per second at the given time of the day, if any
+    fn limit(&self, direction: Direction, time: Time) -> Option<u64> {
+        self.limit_schedules
+            .iter()
+            .find(|schedule| schedule.contains(time))
+            .and_then(|schedule| schedule.get(direction))
+            .or_else(|| self.get(direction))
+            .map(|limit| limit.as_u64())
+            .filter(|limit| *limit > 0)
+    }
+
+    /// The rate limiter for the given direction, if any limit is set
+    pub fn limiter(&self, direction: Direction) -> Option<Arc<RateLimiter>> {
+        let is_set = self.get(direction).is_some()
+            || self
+                .limit_schedules
+                .iter()
+                .any(|schedule| schedule.get(direction).is_some());
+        is_set.then(|| {
+            Arc::new(RateLimiter {
+                opts: self.clone(),
+                direction,
+                state: Mutex::new(RateState {
+                    next: Instant::now(),
+                    limit: None,
+                    checked: None,
+                }),
+            })
+        })
+    }
+
+    /// Wrap the repository backends such that uploads and downloads are limited
+    pub fn wrap(&self, backends: RepositoryBackends) -> RepositoryBackends {
+        let upload = self.limiter(Direction::Upload);
+        let download = self.limiter(Direction::Download);
+        if upload.is_none() && download.is_none() {
+            return backends;
+        }
+        // limiters are shared by the hot and cold repository, so the limit applies to their sum
+        let wrap = |be| -> Arc<dyn WriteBackend> {
+            Arc::new(ThrottledBackend {
+                be,
+                upload: upload.clone(),
+                download: download.clone(),
+            })
+        };
+        RepositoryBackends::new(wrap(backends.repository()), backends.repo_hot().map(wrap))
+    }
+}
+
+/// Limits the rate of transferred bytes; it can be shared between threads
+#[derive(Debug)]
+pub struct RateLimiter {
+    opts: ThrottleOptions,
+    direction: Direction,
+    state: Mutex<RateState>,
+}
+
+#[derive(Debug)]
+struct RateState {
+    /// Time at which the next transfer may start
+    next: Instant,
+    /// Current limit
+    limit: Option<u64>,
+    /// Time the limit was last checked
+    checked: Option<Instant>,
+}
+
+impl RateLimiter {
+    /// Wait until the given number of bytes may be transferred
+    pub fn wait(&self, bytes: u64) {
+        let delay = {
+            let Ok(mut state) = self.state.lock() else {
+                return;
+            };
+            let now = Instant::now();
+            if state
+                .checked
+                .is_none_or(|checked| now - checked >= LIMIT_CHECK_INTERVAL)
+            {
+                state.limit = self.opts.limit(self.direction, Zoned::now().time());
+                state.checked = Some(now);
+            }
+            let Some(limit) = state.limit else {
+                return;
+            };
+            let start = state.next.max(now);
+            #[allow(clippy::cast_precision_loss)]
+            let duration = Duration::from_secs_f64(bytes as f64 / limit as f64);
+            state.next = start + duration;
+            start - now
+        };
+        if !delay.is_zero() {
+            thread::sleep(delay);
+        }
+    }
+}
+
+/// Backend which limits the rate of uploads and downloads
+#[derive(Debug)]
+struct ThrottledBackend {
+    be: Arc<dyn WriteBackend>,
+    upload: Option<Arc<RateLimiter>>,
+    download: Option<Arc<RateLimiter>>,
+}
+
+/// Split a transfer of the given size into chunks of at most [`CHUNK_SIZE`]
+fn chunks(bytes: u64) -> impl Iterator<Item = u64> {
+    (0..bytes)
+        .step_by(CHUNK_SIZE as usize)
+        .map(move |start| (bytes - start).min(CHUNK_SIZE))
+}
+
+/// Run the transfer of the given size with the rate limited by `limiter`
+///
+/// Backends get the whole file at once, so the transfer can't be slowed down itself. Instead, it is
+/// started once the first chunk is allowed and the remaining chunks are waited for afterwards. This
+/// interleaves the transfers of all threads chunk by chunk and doesn't delay a transfer by the time
+/// needed for the whole file.
+fn throttled<T>(limiter: Option<&RateLimiter>, bytes: u64, transfer: impl FnOnce() -> T) -> T {
+    let Some(limiter) = limiter else {
+        return transfer();
+    };
+    let mut chunks = chunks(bytes);
+    if let Some(chunk) = chunks.next() {
+        limiter.wait(chunk);
+    }
+    let result = transfer();
+    for chunk in chunks {
+        limiter.wait(chunk);
+    }
+    result
+}
+
+impl ReadBackend for ThrottledBackend {
+    fn location(&self) -> String {
+        self.be.location()
+    }
+
+    fn list_with_size(&self, tpe: FileType) -> RusticResult<Vec<(Id, u32)>> {
+        self.be.list_with_size(tpe)
+    }
+
+    fn list(&self, tpe: FileType) -> RusticResult<Vec<Id>> {
+        self.be.list(tpe)
+    }
+
+    fn read_full(&self, tpe: FileType, id: &Id) -> RusticResult<Bytes> {
+        let data = self.be.read_full(tpe, id)?;
+        // the size is only known after reading
+        throttled(self.download.as_deref(), data.len() as u64, || ());
+        Ok(data)
+    }
+
+    fn read_partial(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        offset: u32,
+        length: u32,
+    ) -> RusticResult<Bytes> {
+        throttled(self.download.as_deref(), length.into(), || {
+            self.be.read_partial(tpe, id, cacheable, offset, length)
+        })
+    }
+
+    fn warmup_path(&self, tpe: FileType, id: &Id) -> String {
+        self.be.warmup_path(tpe, id)
+    }
+
+    fn needs_warm_up(&self) -> bool {
+        self.be.needs_warm_up()
+    }
+
+    fn warm_up(&self, tpe: FileType, id: &Id) -> RusticResult<()> {
+        self.be.warm_up(tpe, id)
+    }
+}
+
+impl WriteBackend for ThrottledBackend {
+    fn create(&self) -> RusticResult<()> {
+        self.be.create()
+    }
+
+    fn write_bytes(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        content: BytesList,
+    ) -> RusticResult<()> {
+        throttled(self.upload.as_deref(), content.size() as u64, || {
+            self.be.write_bytes(tpe, id, cacheable, content)
+        })
+    }
+
+    fn remove(&self, tpe: FileType, id: &Id, cacheable: bool) -> RusticResult<()> {
+        self.be.remove(tpe, id, cacheable)
+    }
+}
+
+/// [`ReadSource`] which limits the rate of reading files
+pub struct ThrottledSource<S> {
+    src: S,
+    limiter: Option<Arc<RateLimiter>>,
+}
+
+impl<S> ThrottledSource<S> {
+    pub fn new(src: S, limiter: Option<Arc<RateLimiter>>) -> Self {
+        Self { src, limiter }
+    }
+}
+
+impl<S: ReadSource> ReadSource for ThrottledSource<S> {
+    type Open = ThrottledOpen<S::Open>;
+    type Iter = ThrottledIter<S::Iter>;
+
+    fn size(&self) -> RusticResult<Option<u64>> {
+        self.src.size()
+    }
+
+    fn entries(&self) -> Self::Iter {
+        ThrottledIter {
+            iter: self.src.entries(),
+            limiter: self.limiter.clone(),
+        }
+    }
+}
+
+pub struct ThrottledIter<I> {
+    iter: I,
+    limiter: Option<Arc<RateLimiter>>,
+}
+
+impl<I, O> Iterator for ThrottledIter<I>
+where
+    I: Iterator<Item = RusticResult<ReadSourceEntry<O>>>,
+{
+    type Item = RusticResult<ReadSourceEntry<ThrottledOpen<O>>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = self.iter.next()?;
+        Some(entry.map(|entry| ReadSourceEntry {
+            path: entry.path,
+            node: entry.node,
+            open: entry.open.map(|open| ThrottledOpen {
+                open,
+                limiter: self.limiter.clone(),
+            }),
+        }))
+    }
+}
+
+pub struct ThrottledOpen<O> {
+    open: O,
+    limiter: Option<Arc<RateLimiter>>,
+}
+
+impl<O: ReadSourceOpen> ReadSourceOpen for ThrottledOpen<O> {
+    type Reader = ThrottledReader<O::Reader>;
+
+    fn open(self) -> RusticResult<Self::Reader> {
+        Ok(ThrottledReader {
+            reader: self.open.open()?,
+            limiter: self.limiter,
+        })
+    }
+}
+
+pub struct ThrottledReader<R> {
+    reader: R,
+    limiter: Option<Arc<RateLimiter>>,
+}
+
+impl<R: Read> Read for ThrottledReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.reader.read(buf)?;
+        if let Some(limiter) = &self.limiter {
+            limiter.wait(n as u64);
+        }
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn opts() -> ThrottleOptions {
+        let schedule = |from: &str, to: &str, upload: &str| LimitSchedule {
+            from: from.parse().unwrap(),
+            to: to.parse().unwrap(),
+            limit_upload: Some(upload.parse().unwrap()),
+            limit_download: None,
+            limit_read: None,
+        };
+        ThrottleOptions {
+            limit_upload: Some(ByteSize::mib(10)),
+            limit_download: Some(ByteSize::mib(20)),
+            limit_read: None,
+            limit_schedules: vec![
+                schedule("08:00", "18:00", "1MiB"),
+                schedule("22:00", "06:00", "0"),
+            ],
+        }
+    }
+
+    #[rstest]
+    #[case(Direction::Upload, "07:59", Some(10 * 1024 * 1024))]
+    #[case(Direction::Upload, "08:00", Some(1024 * 1024))]
+    #[case(Direction::Upload, "18:00", Some(10 * 1024 * 1024))]
+    #[case(Direction::Upload, "23:00", None)]
+    #[case(Direction::Upload, "05:59", None)]
+    #[case(Direction::Download, "12:00", Some(20 * 1024 * 1024))]
+    #[case(Direction::Read, "12:00", None)]
+    fn limit(#[case] direction: Direction, #[case] time: &str, #[case] expected: Option<u64>) {
+        assert_eq!(opts().limit(direction, time.parse().unwrap()), expected);
+    }
+
+    #[rstest]
+    #[case(0, &[])]
+    #[case(1, &[1])]
+    #[case(CHUNK_SIZE, &[CHUNK_SIZE])]
+    #[case(2 * CHUNK_SIZE + 5, &[CHUNK_SIZE, CHUNK_SIZE, 5])]
+    fn split_into_chunks(#[case] bytes: u64, #[case] expected: &[u64]) {
+        assert_eq!(chunks(bytes).collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn transfers_are_limited() {
+        let opts = ThrottleOptions {
+            limit_upload: Some(ByteSize::b(4 * CHUNK_SIZE)),
+            ..Default::default()
+        };
+        let limiter = opts.limiter(Direction::Upload).unwrap();
+        let start = Instant::now();
+        // the first chunk is transferred directly, the remaining 3 chunks need 3/4 seconds
+        let started = throttled(Some(&limiter), 4 * CHUNK_SIZE, || start.elapsed());
+        assert!(started < Duration::from_millis(100));
+        assert!(start.elapsed() >= Duration::from_millis(700));
+    }
+
+    #[test]
+    fn limiter_is_only_created_if_needed() {
+        let opts = opts();
+        assert!(opts.limiter(Direction::Upload).is_some());
+        assert!(opts.limiter(Direction::Read).is_none());
+    }
+}
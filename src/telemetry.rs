@@ -0,0 +1,91 @@
+//! Export traces and log records using OpenTelemetry
+//!
+//! If a traces endpoint is configured, the command run, opening the repository and all phases reported
+//! by progress bars (e.g. reading the index, backing up, repacking or checking packs) are exported as spans.
+//! If a logs endpoint is configured, all log messages are additionally exported as log records which are
+//! correlated with the spans.
+
+use anyhow::Result;
+use rustic_core::Progress;
+
+use crate::config::GlobalOptions;
+
+#[cfg(feature = "opentelemetry")]
+mod otlp;
+
+/// Set up the exporters for traces and logs, if configured
+#[cfg(feature = "opentelemetry")]
+pub fn init(global: &GlobalOptions) -> Result<()> {
+    otlp::init(global)
+}
+
+/// Set up the exporters for traces and logs, if configured
+#[cfg(not(feature = "opentelemetry"))]
+pub fn init(global: &GlobalOptions) -> Result<()> {
+    if global.opentelemetry_traces_endpoint().is_some()
+        || global.opentelemetry_logs_endpoint().is_some()
+    {
+        anyhow::bail!("opentelemetry support is not compiled-in!");
+    }
+    Ok(())
+}
+
+/// Start the span of the command run; it is ended by [`shutdown`]
+///
+/// The returned guard makes the span the parent of all spans created by the current thread.
+#[cfg(feature = "opentelemetry")]
+pub fn start_command(name: &str) -> impl Sized + use<> {
+    otlp::start_command(name)
+}
+
+/// Start the span of the command run; it is ended by [`shutdown`]
+#[cfg(not(feature = "opentelemetry"))]
+pub fn start_command(_name: &str) -> impl Sized + use<> {}
+
+/// Run `f` within a span with the given name
+#[cfg(feature = "opentelemetry")]
+pub fn in_span<T>(name: &'static str, f: impl FnOnce() -> T) -> T {
+    use opentelemetry::{global, trace::Tracer};
+
+    global::tracer("rustic").in_span(name, |_| f())
+}
+
+/// Run `f` within a span with the given name
+#[cfg(not(feature = "opentelemetry"))]
+pub fn in_span<T>(_name: &'static str, f: impl FnOnce() -> T) -> T {
+    f()
+}
+
+/// Wrap the progress such that the progressed phase is exported as span
+#[cfg(feature = "opentelemetry")]
+pub fn trace_progress(prefix: &str, progress: Progress) -> Progress {
+    otlp::trace_progress(prefix, progress)
+}
+
+/// Wrap the progress such that the progressed phase is exported as span
+#[cfg(not(feature = "opentelemetry"))]
+pub fn trace_progress(_prefix: &str, progress: Progress) -> Progress {
+    progress
+}
+
+/// Appender exporting log messages as log records, if configured
+#[cfg(feature = "opentelemetry")]
+pub fn log_appender() -> Option<Box<dyn log4rs::append::Append>> {
+    otlp::log_appender()
+}
+
+/// Appender exporting log messages as log records, if configured
+#[cfg(not(feature = "opentelemetry"))]
+pub fn log_appender() -> Option<Box<dyn log4rs::append::Append>> {
+    None
+}
+
+/// End the span of the command run and flush all pending spans and log records
+#[cfg(feature = "opentelemetry")]
+pub fn shutdown(success: bool) {
+    otlp::shutdown(success);
+}
+
+/// End the span of the command run and flush all pending spans and log records
+#[cfg(not(feature = "opentelemetry"))]
+pub fn shutdown(_success: bool) {}
@@ -0,0 +1,210 @@
+use std::sync::{
+    Mutex, OnceLock,
+    atomic::{AtomicU64, Ordering},
+};
+
+use anyhow::Result;
+use log::{Level, Record, warn};
+use opentelemetry::{
+    Context, ContextGuard, KeyValue, global,
+    logs::{AnyValue, LogRecord, Logger, LoggerProvider, Severity},
+    trace::{Span, Status, TraceContextExt, Tracer},
+};
+use opentelemetry_otlp::{LogExporter, Protocol, SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{
+    Resource,
+    logs::{SdkLogger, SdkLoggerProvider},
+    trace::SdkTracerProvider,
+};
+use rustic_core::{Progress, RusticProgress};
+
+use crate::{commands::version, config::GlobalOptions};
+
+/// Log targets which are not exported to avoid recursion when exporting log records
+const IGNORED_TARGETS: [&str; 4] = ["opentelemetry", "reqwest", "hyper", "h2"];
+
+static TRACER_PROVIDER: OnceLock<SdkTracerProvider> = OnceLock::new();
+static LOGGER_PROVIDER: OnceLock<SdkLoggerProvider> = OnceLock::new();
+static COMMAND_CONTEXT: OnceLock<Context> = OnceLock::new();
+
+pub(super) fn init(global: &GlobalOptions) -> Result<()> {
+    let resource = || {
+        let attributes = global
+            .metrics_labels
+            .iter()
+            .map(|(k, v)| KeyValue::new(k.clone(), v.clone()));
+        Resource::builder()
+            .with_service_name("rustic")
+            .with_attribute(KeyValue::new("service.version", version()))
+            .with_attributes(attributes)
+            .build()
+    };
+
+    if let Some(endpoint) = global.opentelemetry_traces_endpoint() {
+        let exporter = SpanExporter::builder()
+            .with_http()
+            .with_protocol(Protocol::HttpBinary)
+            .with_endpoint(endpoint.to_string())
+            .build()?;
+        let provider = SdkTracerProvider::builder()
+            .with_batch_exporter(exporter)
+            .with_resource(resource())
+            .build();
+        global::set_tracer_provider(provider.clone());
+        _ = TRACER_PROVIDER.set(provider);
+    }
+
+    if let Some(endpoint) = global.opentelemetry_logs_endpoint() {
+        let exporter = LogExporter::builder()
+            .with_http()
+            .with_protocol(Protocol::HttpBinary)
+            .with_endpoint(endpoint.to_string())
+            .build()?;
+        let provider = SdkLoggerProvider::builder()
+            .with_batch_exporter(exporter)
+            .with_resource(resource())
+            .build();
+        _ = LOGGER_PROVIDER.set(provider);
+    }
+    Ok(())
+}
+
+pub(super) fn start_command(name: &str) -> Option<ContextGuard> {
+    _ = TRACER_PROVIDER.get()?;
+    let span = global::tracer("rustic").start(format!("rustic {name}"));
+    let cx = Context::current_with_span(span);
+    _ = COMMAND_CONTEXT.set(cx.clone());
+    Some(cx.attach())
+}
+
+pub(super) fn shutdown(success: bool) {
+    if let Some(cx) = COMMAND_CONTEXT.get() {
+        let span = cx.span();
+        if !success {
+            span.set_status(Status::error("command failed"));
+        }
+        span.end();
+    }
+    if let Some(provider) = TRACER_PROVIDER.get()
+        && let Err(err) = provider.shutdown()
+    {
+        warn!("error exporting opentelemetry traces: {err}");
+    }
+    if let Some(provider) = LOGGER_PROVIDER.get()
+        && let Err(err) = provider.shutdown()
+    {
+        warn!("error exporting opentelemetry logs: {err}");
+    }
+}
+
+pub(super) fn trace_progress(prefix: &str, progress: Progress) -> Progress {
+    if TRACER_PROVIDER.get().is_none() {
+        return progress;
+    }
+    let span = global::tracer("rustic").start(span_name(prefix));
+    Progress::new(TracedProgress {
+        progress,
+        span: Mutex::new(Some(span)),
+        position: AtomicU64::new(0),
+        length: AtomicU64::new(0),
+    })
+}
+
+fn span_name(title: &str) -> String {
+    title.trim_end_matches('.').to_string()
+}
+
+/// A progress which additionally records the progressed phase as span
+///
+/// The span is ended when the progress is finished or dropped.
+#[derive(Debug)]
+struct TracedProgress {
+    progress: Progress,
+    span: Mutex<Option<global::BoxedSpan>>,
+    position: AtomicU64,
+    length: AtomicU64,
+}
+
+impl RusticProgress for TracedProgress {
+    fn is_hidden(&self) -> bool {
+        self.progress.is_hidden()
+    }
+
+    fn set_length(&self, len: u64) {
+        self.length.store(len, Ordering::Relaxed);
+        self.progress.set_length(len);
+    }
+
+    fn set_title(&self, title: &str) {
+        if let Ok(mut span) = self.span.lock()
+            && let Some(span) = span.as_mut()
+        {
+            span.update_name(span_name(title));
+        }
+        self.progress.set_title(title);
+    }
+
+    fn inc(&self, inc: u64) {
+        _ = self.position.fetch_add(inc, Ordering::Relaxed);
+        self.progress.inc(inc);
+    }
+
+    #[allow(clippy::cast_possible_wrap)]
+    fn finish(&self) {
+        self.progress.finish();
+        let Some(mut span) = self.span.lock().ok().and_then(|mut span| span.take()) else {
+            return;
+        };
+        span.set_attribute(KeyValue::new(
+            "rustic.progress.position",
+            self.position.load(Ordering::Relaxed) as i64,
+        ));
+        let length = self.length.load(Ordering::Relaxed);
+        if length > 0 {
+            span.set_attribute(KeyValue::new("rustic.progress.length", length as i64));
+        }
+        span.end();
+    }
+}
+
+pub(super) fn log_appender() -> Option<Box<dyn log4rs::append::Append>> {
+    let logger = LOGGER_PROVIDER.get()?.logger("rustic");
+    Some(Box::new(LogAppender(logger)))
+}
+
+/// Appender which exports log messages as OpenTelemetry log records
+struct LogAppender(SdkLogger);
+
+impl std::fmt::Debug for LogAppender {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LogAppender").finish_non_exhaustive()
+    }
+}
+
+impl log4rs::append::Append for LogAppender {
+    fn append(&self, record: &Record<'_>) -> Result<()> {
+        let target = record.target();
+        if IGNORED_TARGETS
+            .iter()
+            .any(|ignored| target.starts_with(ignored))
+        {
+            return Ok(());
+        }
+        let severity = match record.level() {
+            Level::Error => Severity::Error,
+            Level::Warn => Severity::Warn,
+            Level::Info => Severity::Info,
+            Level::Debug => Severity::Debug,
+            Level::Trace => Severity::Trace,
+        };
+        let mut log_record = self.0.create_log_record();
+        log_record.set_target(target.to_string());
+        log_record.set_severity_number(severity);
+        log_record.set_severity_text(record.level().as_str());
+        log_record.set_body(AnyValue::from(record.args().to_string()));
+        self.0.emit(log_record);
+        Ok(())
+    }
+
+    fn flush(&self) {}
+}
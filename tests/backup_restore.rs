@@ -223,3 +223,773 @@ fn test_backup_and_restore_passes() -> TestResult<()> {
 
     Ok(())
 }
+
+#[cfg(unix)]
+#[test]
+fn content_filters_select_latest_snapshot() -> TestResult<()> {
+    let temp_dir = setup()?;
+    let source = temp_dir.path().join("source");
+    std::fs::create_dir(&source)?;
+    std::fs::write(source.join("config.conf"), "old config")?;
+    std::fs::write(source.join("data.txt"), "data")?;
+
+    rustic_runner(&temp_dir)?
+        .arg("backup")
+        .arg(&source)
+        .assert()
+        .success();
+
+    std::fs::remove_file(source.join("config.conf"))?;
+
+    rustic_runner(&temp_dir)?
+        .arg("backup")
+        .arg(&source)
+        .assert()
+        .success();
+
+    let config_path = source.join("config.conf");
+    let config_path = config_path.to_str().unwrap();
+
+    rustic_runner(&temp_dir)?
+        .args(["snapshots", "--filter-contains", config_path])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("total: 1 snapshot(s)"));
+
+    // without the filter, `latest` doesn't contain the file
+    rustic_runner(&temp_dir)?
+        .args(["ls", "latest"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("config.conf").not());
+
+    rustic_runner(&temp_dir)?
+        .args(["ls", "latest", "--filter-contains", config_path])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("config.conf"));
+
+    rustic_runner(&temp_dir)?
+        .args(["dump", &format!("latest:{config_path}")])
+        .args(["--filter-contains", config_path])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("old config"));
+
+    Ok(())
+}
+
+/// Get the ids of all packs containing blobs of the given type
+fn packs_of_type(temp_dir: &TempDir, tpe: &str) -> TestResult<Vec<String>> {
+    let mut packs = Vec::new();
+    for entry in std::fs::read_dir(temp_dir.path().join("repo").join("index"))? {
+        let output = rustic_runner(temp_dir)?
+            .args(["cat", "index"])
+            .arg(entry?.file_name())
+            .output()?;
+        let index: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+        for pack in index["packs"].as_array().unwrap() {
+            if pack["blobs"][0]["type"] == tpe {
+                packs.push(pack["id"].as_str().unwrap().to_string());
+            }
+        }
+    }
+    Ok(packs)
+}
+
+/// Flip the byte at `pos` (counted from the end if negative) of the given pack
+fn damage_pack(temp_dir: &TempDir, id: &str, pos: i64) -> TestResult<()> {
+    let path = temp_dir
+        .path()
+        .join("repo")
+        .join("data")
+        .join(&id[..2])
+        .join(id);
+    let mut data = std::fs::read(&path)?;
+    let pos = if pos < 0 {
+        data.len() - pos.unsigned_abs() as usize
+    } else {
+        pos as usize
+    };
+    data[pos] ^= 0xff;
+    let mut permissions = std::fs::metadata(&path)?.permissions();
+    #[allow(clippy::permissions_set_readonly_false)]
+    permissions.set_readonly(false);
+    std::fs::set_permissions(&path, permissions)?;
+    std::fs::write(&path, data)?;
+    Ok(())
+}
+
+#[test]
+fn repair_packs_salvages_blobs_of_damaged_packs() -> TestResult<()> {
+    let temp_dir = setup()?;
+    let restore_dir = temp_dir.path().join("restore");
+    let backup_files = src_snapshot()?.into_path();
+
+    rustic_runner(&temp_dir)?
+        .arg("backup")
+        .arg(backup_files.path())
+        .arg("--as-path")
+        .arg("/")
+        .assert()
+        .success();
+
+    // damage the headers of all packs; all blobs can still be read using the index
+    let trees = packs_of_type(&temp_dir, "tree")?;
+    let packs = packs_of_type(&temp_dir, "data")?;
+    for id in trees.iter().chain(&packs) {
+        damage_pack(&temp_dir, id, -10)?;
+    }
+    rustic_runner(&temp_dir)?
+        .args(["check", "--read-data"])
+        .assert()
+        .failure();
+
+    // tree packs cannot be salvaged and are kept
+    rustic_runner(&temp_dir)?
+        .args(["repair", "packs"])
+        .args(&trees)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "packs containing trees could not be repaired.",
+        ));
+    // flipping the byte again restores the tree packs
+    for id in &trees {
+        damage_pack(&temp_dir, id, -10)?;
+    }
+
+    rustic_runner(&temp_dir)?
+        .args(["repair", "packs"])
+        .args(&packs)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "all blobs of the damaged packs have been recovered.",
+        ));
+
+    rustic_runner(&temp_dir)?
+        .args(["check", "--read-data"])
+        .assert()
+        .success();
+
+    rustic_runner(&temp_dir)?
+        .arg("restore")
+        .arg("latest")
+        .arg(&restore_dir)
+        .assert()
+        .success();
+    let compare_result = Comparison::default().compare(backup_files.path(), &restore_dir)?;
+    assert!(compare_result.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn repair_packs_recovers_damaged_blobs_from_files() -> TestResult<()> {
+    let temp_dir = setup()?;
+    let source = temp_dir.path().join("source");
+    std::fs::create_dir(&source)?;
+    let content: Vec<u8> = (0..100_000u32).map(|i| (i * 7 % 251) as u8).collect();
+    std::fs::write(source.join("data.bin"), &content)?;
+
+    rustic_runner(&temp_dir)?
+        .arg("backup")
+        .arg(&source)
+        .assert()
+        .success();
+
+    // damage the (only) data blob
+    let packs = packs_of_type(&temp_dir, "data")?;
+    assert_eq!(packs.len(), 1);
+    damage_pack(&temp_dir, &packs[0], 20)?;
+
+    rustic_runner(&temp_dir)?
+        .args(["repair", "packs", "--dry-run"])
+        .args(&packs)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "0 blobs salvaged, 0 blobs found in other packs, 1 blobs missing.",
+        ));
+
+    rustic_runner(&temp_dir)?
+        .args(["repair", "packs", "--from"])
+        .arg(&source)
+        .args(&packs)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "1 blobs recovered from the given paths.",
+        ));
+
+    rustic_runner(&temp_dir)?
+        .args(["check", "--read-data"])
+        .assert()
+        .success();
+
+    Ok(())
+}
+
+#[test]
+fn repair_packs_recovers_damaged_blobs_from_renamed_files() -> TestResult<()> {
+    let temp_dir = setup()?;
+    let source = temp_dir.path().join("source");
+    std::fs::create_dir(&source)?;
+    let content: Vec<u8> = (0..100_000u32).map(|i| (i * 7 % 251) as u8).collect();
+    std::fs::write(source.join("data.bin"), &content)?;
+
+    rustic_runner(&temp_dir)?
+        .arg("backup")
+        .arg(&source)
+        .assert()
+        .success();
+
+    let packs = packs_of_type(&temp_dir, "data")?;
+    damage_pack(&temp_dir, &packs[0], 20)?;
+
+    // the file has been renamed and moved since the backup
+    let moved = temp_dir.path().join("moved");
+    std::fs::create_dir_all(moved.join("sub"))?;
+    std::fs::rename(
+        source.join("data.bin"),
+        moved.join("sub").join("renamed.bin"),
+    )?;
+    std::fs::write(moved.join("other.bin"), b"other content")?;
+
+    rustic_runner(&temp_dir)?
+        .args(["repair", "packs", "--from"])
+        .arg(&moved)
+        .args(&packs)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "1 blobs recovered from the given paths.",
+        ));
+
+    rustic_runner(&temp_dir)?
+        .args(["check", "--read-data"])
+        .assert()
+        .success();
+
+    Ok(())
+}
+
+#[test]
+fn rewrite_moves_paths_without_reading_the_source() -> TestResult<()> {
+    let temp_dir = setup()?;
+    let source = temp_dir.path().join("source");
+    for (path, content) in [
+        ("srv/old/file1.txt", "one"),
+        ("srv/old/x/file2.txt", "two"),
+        ("srv/other.txt", "other"),
+        ("home/user/notes.txt", "three"),
+    ] {
+        let path = source.join(path);
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        std::fs::write(path, content)?;
+    }
+    // two identical snapshots share all rewritten trees
+    for _ in 0..2 {
+        rustic_runner(&temp_dir)?
+            .args(["backup", "--as-path", "/"])
+            .arg(&source)
+            .assert()
+            .success();
+    }
+    // moved files are read from the repository
+    std::fs::remove_dir_all(&source)?;
+
+    rustic_runner(&temp_dir)?
+        .args(["rewrite", "--forget", "--move", "srv/old=data/new"])
+        .args(["--strip-prefix", "home/user"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("2 snapshots have been rewritten"));
+
+    rustic_runner(&temp_dir)?
+        .args(["ls", "latest"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("data/new/file1.txt"))
+        .stdout(predicate::str::contains("data/new/x/file2.txt"))
+        .stdout(predicate::str::contains("srv/other.txt"))
+        .stdout(predicate::str::contains("notes.txt"))
+        .stdout(predicate::str::contains("srv/old").not())
+        .stdout(predicate::str::contains("home").not());
+
+    rustic_runner(&temp_dir)?
+        .args(["snapshots"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("total: 2 snapshot(s)"));
+
+    rustic_runner(&temp_dir)?
+        .args(["check", "--read-data"])
+        .assert()
+        .success();
+
+    let restore_dir = temp_dir.path().join("restore");
+    rustic_runner(&temp_dir)?
+        .args(["restore", "latest"])
+        .arg(&restore_dir)
+        .assert()
+        .success();
+    assert_eq!(
+        std::fs::read_to_string(restore_dir.join("data/new/x/file2.txt"))?,
+        "two"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn merge_resolves_conflicts_using_the_strategy() -> TestResult<()> {
+    let temp_dir = setup()?;
+    let snapshot_id = |output: std::process::Output| -> TestResult<String> {
+        assert!(output.status.success());
+        let snapshot: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+        Ok(snapshot["id"].as_str().unwrap().to_string())
+    };
+    let mut ids = Vec::new();
+    for (num, content) in ["one", "two, but larger"].into_iter().enumerate() {
+        let source = temp_dir.path().join(format!("source{num}"));
+        std::fs::create_dir_all(source.join("data"))?;
+        std::fs::write(source.join("data").join("a.txt"), content)?;
+        std::fs::write(source.join(format!("only{num}.txt")), content)?;
+        ids.push(snapshot_id(
+            rustic_runner(&temp_dir)?
+                .args(["backup", "--json", "--as-path", "/"])
+                .arg(&source)
+                .output()?,
+        )?);
+    }
+
+    rustic_runner(&temp_dir)?
+        .args(["merge", "--dry-run", "--strategy", "largest"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("/data/a.txt"));
+    let merged = snapshot_id(
+        rustic_runner(&temp_dir)?
+            .args(["merge", "--json", "--strategy", "largest"])
+            .output()?,
+    )?;
+    rustic_runner(&temp_dir)?
+        .args(["dump", &format!("{merged}:/data/a.txt")])
+        .assert()
+        .success()
+        .stdout("two, but larger");
+    rustic_runner(&temp_dir)?
+        .args(["ls", &merged])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("only0.txt"))
+        .stdout(predicate::str::contains("only1.txt"));
+
+    // only merge the selected path of the first snapshot
+    let merged = snapshot_id(
+        rustic_runner(&temp_dir)?
+            .args(["merge", "--json", "--strategy", "first"])
+            .arg(format!("{}:/data", ids[0]))
+            .arg(&ids[1])
+            .output()?,
+    )?;
+    rustic_runner(&temp_dir)?
+        .args(["dump", &format!("{merged}:/data/a.txt")])
+        .assert()
+        .success()
+        .stdout("one");
+    rustic_runner(&temp_dir)?
+        .args(["ls", &merged])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("only0.txt").not())
+        .stdout(predicate::str::contains("only1.txt"));
+    // the snapshot saved to select the path is removed
+    rustic_runner(&temp_dir)?
+        .args(["snapshots"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("total: 4 snapshot(s)"));
+
+    rustic_runner(&temp_dir)?
+        .args(["check", "--read-data"])
+        .assert()
+        .success();
+
+    // a dir losing against a file: its contents are no conflicts
+    let source = temp_dir.path().join("source2");
+    std::fs::create_dir_all(&source)?;
+    std::fs::write(source.join("data"), "file")?;
+    let file_id = snapshot_id(
+        rustic_runner(&temp_dir)?
+            .args(["backup", "--json", "--as-path", "/"])
+            .arg(&source)
+            .output()?,
+    )?;
+    rustic_runner(&temp_dir)?
+        .args([
+            "merge",
+            "--dry-run",
+            "--strategy",
+            "last",
+            &ids[0],
+            &file_id,
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("/data "))
+        .stdout(predicate::str::contains("/data/a.txt").not());
+
+    // nothing to merge
+    rustic_runner(&temp_dir)?
+        .args(["merge", "--filter-host", "no-such-host"])
+        .assert()
+        .success();
+
+    Ok(())
+}
+
+#[test]
+fn init_writes_profile_without_password() -> TestResult<()> {
+    let temp_dir = tempdir()?;
+    let config_home = temp_dir.path().join("config");
+    rustic_runner(&temp_dir)?
+        .env("XDG_CONFIG_HOME", &config_home)
+        .args(["init", "--write-profile", "new-profile"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("not writing the password"));
+
+    let path = config_home.join("rustic").join("new-profile.toml");
+    let contents = std::fs::read_to_string(&path)?;
+    assert!(contents.contains("[repository]"));
+    assert!(!contents.contains("password"));
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(
+            std::fs::metadata(&path)?.permissions().mode() & 0o777,
+            0o600
+        );
+    }
+
+    // the profile can be used to access the repository
+    Command::new(env!("CARGO_BIN_EXE_rustic"))
+        .env("XDG_CONFIG_HOME", &config_home)
+        .args(["-P", "new-profile", "--password", "test", "--no-progress"])
+        .arg("snapshots")
+        .assert()
+        .success();
+
+    // an existing profile is not overwritten
+    let temp_dir2 = tempdir()?;
+    rustic_runner(&temp_dir2)?
+        .env("XDG_CONFIG_HOME", &config_home)
+        .args(["init", "--write-profile", "new-profile"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("already exists"));
+    Ok(())
+}
+
+#[test]
+fn modifying_commands_publish_metrics() -> TestResult<()> {
+    let temp_dir = setup()?;
+    let metrics_dir = temp_dir.path().join("metrics");
+    std::fs::create_dir_all(&metrics_dir)?;
+    let source = temp_dir.path().join("source");
+    std::fs::create_dir_all(&source)?;
+    std::fs::write(source.join("file"), "content")?;
+    let metrics = |job: &str| -> TestResult<String> {
+        let mut contents = String::new();
+        for entry in std::fs::read_dir(&metrics_dir)? {
+            let path = entry?.path();
+            if path.file_name().unwrap().to_string_lossy().starts_with(job) {
+                contents.push_str(&std::fs::read_to_string(path)?);
+            }
+        }
+        Ok(contents)
+    };
+    let value = |contents: &str, name: &str| -> Option<String> {
+        contents
+            .lines()
+            .find(|line| {
+                line.strip_prefix(name)
+                    .is_some_and(|rest| rest.starts_with(['{', ' ']))
+            })
+            .and_then(|line| line.rsplit(' ').next())
+            .map(ToString::to_string)
+    };
+
+    rustic_runner(&temp_dir)?
+        .arg("--prometheus-textfile-dir")
+        .arg(&metrics_dir)
+        .arg("backup")
+        .arg(&source)
+        .assert()
+        .success();
+    let backup = metrics("rustic_backup")?;
+    assert_eq!(
+        value(&backup, "rustic_backup_files_new").as_deref(),
+        Some("1")
+    );
+    assert_eq!(
+        value(&backup, "rustic_backup_success").as_deref(),
+        Some("1")
+    );
+    assert!(value(&backup, "rustic_backup_last_success").is_some());
+
+    rustic_runner(&temp_dir)?
+        .arg("--prometheus-textfile-dir")
+        .arg(&metrics_dir)
+        .args(["tag", "--add", "metrics"])
+        .assert()
+        .success();
+    let tag = metrics("rustic_tag")?;
+    assert_eq!(value(&tag, "rustic_tag_snapshots").as_deref(), Some("1"));
+    assert_eq!(value(&tag, "rustic_tag_success").as_deref(), Some("1"));
+
+    // a failed backup is published in the same group as the successful one
+    std::fs::remove_file(source.join("file"))?;
+    rustic_runner(&temp_dir)?
+        .arg("--prometheus-textfile-dir")
+        .arg(&metrics_dir)
+        .args(["backup", "--stdin-command", "false", "--as-path"])
+        .arg(&source)
+        .arg("-")
+        .assert()
+        .failure();
+    let backup = metrics("rustic_backup")?;
+    assert_eq!(
+        value(&backup, "rustic_backup_success").as_deref(),
+        Some("0")
+    );
+    assert!(value(&backup, "rustic_backup_last_success").is_none());
+    Ok(())
+}
+
+#[test]
+fn status_exit_code_reflects_snapshot_age() -> TestResult<()> {
+    let temp_dir = setup()?;
+
+    // no snapshots at all is critical
+    rustic_runner(&temp_dir)?
+        .arg("status")
+        .assert()
+        .code(2)
+        .stdout(predicate::str::starts_with(
+            "RUSTIC CRITICAL - no snapshots found",
+        ));
+
+    let source = temp_dir.path().join("source");
+    std::fs::create_dir(&source)?;
+    std::fs::write(source.join("file.txt"), "content")?;
+    rustic_runner(&temp_dir)?
+        .arg("backup")
+        .arg(&source)
+        .assert()
+        .success();
+
+    rustic_runner(&temp_dir)?
+        .arg("status")
+        .assert()
+        .code(0)
+        .stdout(predicate::str::starts_with(
+            "RUSTIC OK - 1 snapshot groups are fresh",
+        ));
+
+    rustic_runner(&temp_dir)?
+        .args(["status", "--warn-age", "0s"])
+        .assert()
+        .code(1)
+        .stdout(predicate::str::starts_with("RUSTIC WARNING"));
+
+    rustic_runner(&temp_dir)?
+        .args(["status", "--warn-age", "0s", "--crit-age", "0s"])
+        .assert()
+        .code(2)
+        .stdout(predicate::str::starts_with("RUSTIC CRITICAL"));
+
+    // the repository can't be opened
+    Command::new(env!("CARGO_BIN_EXE_rustic"))
+        .arg("-r")
+        .arg(temp_dir.path().join("repo"))
+        .args(["--password", "wrong", "--no-progress", "status"])
+        .assert()
+        .code(3)
+        .stdout(predicate::str::starts_with("RUSTIC UNKNOWN"));
+
+    Ok(())
+}
+
+#[test]
+fn annotations_are_kept_when_setting_tags() -> TestResult<()> {
+    let temp_dir = setup()?;
+    let source = temp_dir.path().join("source");
+    std::fs::create_dir(&source)?;
+    std::fs::write(source.join("file.txt"), "content")?;
+
+    rustic_runner(&temp_dir)?
+        .args(["backup", "--tag", "a", "--annotate", "ticket=123"])
+        .arg(&source)
+        .assert()
+        .success();
+
+    rustic_runner(&temp_dir)?
+        .args(["tag", "--set", "b", "--annotate", "env=prod"])
+        .assert()
+        .success();
+
+    rustic_runner(&temp_dir)?
+        .args([
+            "snapshots",
+            "--filter-tags",
+            "b",
+            "--filter-annotation",
+            "ticket=123",
+            "--filter-annotation",
+            "env=prod",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("total: 1 snapshot(s)"));
+
+    rustic_runner(&temp_dir)?
+        .args(["tag", "--remove-annotation", "ticket"])
+        .assert()
+        .success();
+
+    rustic_runner(&temp_dir)?
+        .args(["snapshots", "--filter-annotation", "ticket=123"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("total: 0 snapshot(s)"));
+
+    rustic_runner(&temp_dir)?
+        .args(["snapshots", "--filter-tags", "b"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("total: 1 snapshot(s)"));
+
+    Ok(())
+}
+
+#[test]
+fn tag_rules_write_a_single_snapshot() -> TestResult<()> {
+    let temp_dir = setup()?;
+    let source = temp_dir.path().join("source");
+    std::fs::create_dir(&source)?;
+    std::fs::write(source.join("file.txt"), "content")?;
+    // profiles are also searched in the current directory
+    std::fs::write(
+        temp_dir.path().join("tag-rules.toml"),
+        "[[backup.tag-rules]]\ntags = [\"by-rule\"]\n",
+    )?;
+
+    rustic_runner(&temp_dir)?
+        .current_dir(&temp_dir)
+        .args(["-P", "tag-rules", "backup", "source"])
+        .assert()
+        .success();
+
+    rustic_runner(&temp_dir)?
+        .args(["snapshots", "--filter-tags", "by-rule"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("total: 1 snapshot(s)"));
+
+    // the snapshot written by the backup is replaced by the tagged one
+    rustic_runner(&temp_dir)?
+        .args(["repoinfo", "--only-files"])
+        .assert()
+        .success()
+        .stdout(predicate::str::is_match(r"Snapshot\s+\|\s+1\s")?);
+
+    Ok(())
+}
+
+#[test]
+fn captured_log_is_saved_without_changing_the_tree() -> TestResult<()> {
+    let temp_dir = setup()?;
+    let source = temp_dir.path().join("source");
+    std::fs::create_dir(&source)?;
+    std::fs::write(source.join("file.txt"), "content")?;
+
+    rustic_runner(&temp_dir)?
+        .current_dir(&temp_dir)
+        // absolute paths are canonicalized, so a missing one wouldn't only give a warning
+        .args([
+            "backup",
+            "--capture-log",
+            "--description",
+            "nightly",
+            "source",
+            "missing",
+        ])
+        .assert()
+        .success();
+
+    rustic_runner(&temp_dir)?
+        .args(["snapshots", "--log", "latest"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("[WARN]"))
+        .stdout(predicate::str::contains("missing"));
+
+    rustic_runner(&temp_dir)?
+        .args(["snapshots", "--long"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("nightly"))
+        .stdout(predicate::str::contains("[WARN]").not())
+        .stdout(predicate::str::contains("total: 1 snapshot(s)"));
+
+    rustic_runner(&temp_dir)?
+        .args(["ls", "latest"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(".rustic").not());
+
+    // modifying the description keeps the saved log
+    let modifications: [&[&str]; 2] =
+        [&["--set-description", "changed"], &["--remove-description"]];
+    for modification in modifications {
+        rustic_runner(&temp_dir)?
+            .args(["rewrite", "--forget"])
+            .args(modification)
+            .assert()
+            .success();
+
+        rustic_runner(&temp_dir)?
+            .args(["snapshots", "--log", "latest"])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("missing"));
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn failing_stdin_stream_writes_no_snapshot() -> TestResult<()> {
+    let temp_dir = setup()?;
+
+    rustic_runner(&temp_dir)?
+        .args(["backup", "-"])
+        .args(["--stdin-stream", "ok.txt=echo ok"])
+        .args(["--stdin-stream", "failed.txt=sh -c 'echo partial; exit 3'"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("failed.txt"));
+
+    rustic_runner(&temp_dir)?
+        .args(["repoinfo", "--only-files"])
+        .assert()
+        .success()
+        .stdout(predicate::str::is_match(r"Snapshot\s+\|\s+0\s")?);
+
+    Ok(())
+}
@@ -0,0 +1,85 @@
+//! Telemetry test: runs the application as a subprocess and asserts that
+//! traces and logs are exported to an OpenTelemetry collector
+
+#![cfg(feature = "opentelemetry")]
+
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use assert_cmd::Command;
+use tempfile::tempdir;
+
+use rustic_testing::TestResult;
+
+/// A collector stub which accepts all HTTP requests and records their paths
+fn collector() -> TestResult<(String, Arc<Mutex<Vec<String>>>)> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let url = format!("http://{}", listener.local_addr()?);
+    let paths = Arc::new(Mutex::new(Vec::new()));
+    let received = paths.clone();
+    _ = thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            if let Some(path) = handle(stream) {
+                received.lock().unwrap().push(path);
+            }
+        }
+    });
+    Ok((url, paths))
+}
+
+/// Read a single request and answer it with an empty success response
+fn handle(mut stream: TcpStream) -> Option<String> {
+    let mut reader = BufReader::new(stream.try_clone().ok()?);
+    let mut line = String::new();
+    _ = reader.read_line(&mut line).ok()?;
+    let path = line.split(' ').nth(1)?.to_string();
+    let mut length = 0;
+    loop {
+        line.clear();
+        _ = reader.read_line(&mut line).ok()?;
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            length = value.trim().parse().ok()?;
+        }
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).ok()?;
+    stream
+        .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\nconnection: close\r\n\r\n")
+        .ok()?;
+    Some(path)
+}
+
+#[test]
+fn traces_and_logs_are_exported() -> TestResult<()> {
+    let (url, paths) = collector()?;
+    let temp_dir = tempdir()?;
+
+    Command::new(env!("CARGO_BIN_EXE_rustic"))
+        .arg("-r")
+        .arg(temp_dir.path().join("repo"))
+        .args([
+            "--password",
+            "test",
+            "--no-progress",
+            "--otel",
+            &url,
+            "init",
+        ])
+        .assert()
+        .success();
+
+    let paths = paths.lock().unwrap();
+    assert!(paths.iter().any(|path| path == "/v1/traces"), "{paths:?}");
+    assert!(paths.iter().any(|path| path == "/v1/logs"), "{paths:?}");
+    Ok(())
+}